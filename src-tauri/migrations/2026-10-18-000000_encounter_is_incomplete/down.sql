@@ -0,0 +1 @@
+ALTER TABLE encounters DROP COLUMN is_incomplete;
@@ -0,0 +1,2 @@
+-- Flag encounters during which capture data was lost
+ALTER TABLE encounters ADD COLUMN is_incomplete INTEGER NOT NULL DEFAULT 0;
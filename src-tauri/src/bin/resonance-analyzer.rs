@@ -0,0 +1,218 @@
+//! Headless analyzer: replays pcap/pcapng captures or recorded `.rlsession`
+//! files through the parser and prints every finished encounter as JSON.
+//!
+//! Usage:
+//! - `resonance-analyzer [--unknown-methods] [--schema-diff] <file>...`
+//! - `resonance-analyzer inspect [--pkt <name>] <payload.bin>...`
+//! - `resonance-analyzer load [--rate <msgs/s>] [--seconds <n>] [--players <n>]`
+//!
+//! One pretty-printed JSON document is written to stdout per input file. Map
+//! keys are sorted, so output from two parser versions can be diffed directly.
+//! With `--unknown-methods` each document also lists the notify method ids
+//! that are missing from `opcodes.rs`, and with `--schema-diff` the fields
+//! seen on the wire that `blueprotobuf_package.rs` does not declare.
+//!
+//! `inspect` decodes raw protobuf payloads (such as unknown method samples)
+//! without a schema, diffing them against the prost type of `--pkt` if given.
+//!
+//! `load` sends synthetic damage messages through the decoder and live state
+//! at a fixed rate and prints the achieved rate and handler times as JSON.
+//!
+//! The analyzer never opens a window, but it links the app library and with it
+//! Tauri, so building it on Linux still needs the WebKitGTK stack. On Debian or
+//! Ubuntu: `libwebkit2gtk-4.1-dev libgtk-3-dev libsoup-3.0-dev
+//! libayatana-appindicator3-dev libxdo-dev`, plus `pkg-config` and a C++
+//! compiler for the module optimizer.
+
+use resonance_logs_lib::{discovery, headless, wire};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "usage: resonance-analyzer [--unknown-methods] [--schema-diff] <capture.pcap|capture.pcapng|session.rlsession>...
+       resonance-analyzer inspect [--pkt <name>] <payload.bin>...
+       resonance-analyzer load [--rate <msgs/s>] [--seconds <n>] [--players <n>]";
+
+fn main() -> ExitCode {
+    let mut args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "inspect") {
+        args.remove(0);
+        return inspect(args);
+    }
+    if args.first().is_some_and(|arg| arg == "load") {
+        args.remove(0);
+        return load(args);
+    }
+
+    let mut unknown_methods = false;
+    let mut schema_diff = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        if arg == "--unknown-methods" {
+            unknown_methods = true;
+        } else if arg == "--schema-diff" {
+            schema_diff = true;
+        } else {
+            paths.push(PathBuf::from(arg));
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    }
+    if unknown_methods && let Err(e) = discovery::enable(None) {
+        eprintln!("failed to enable method discovery: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    let mut failed = false;
+    for path in &paths {
+        discovery::clear_unknown_methods();
+        let encounters = match headless::analyze_file(path) {
+            Ok(encounters) => encounters,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                failed = true;
+                continue;
+            }
+        };
+        let missing_fields = if schema_diff {
+            match headless::schema_diff_file(path) {
+                Ok(missing) => Some(missing),
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    failed = true;
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+        // Going through `Value` sorts map keys (skill ids etc.) for stable output.
+        let json = serde_json::to_value(&encounters).and_then(|encounters| {
+            let mut report = serde_json::json!({
+                "file": path.display().to_string(),
+                "encounters": encounters,
+            });
+            if unknown_methods {
+                report["unknownMethods"] = serde_json::to_value(discovery::unknown_methods())?;
+            }
+            if let Some(missing) = &missing_fields {
+                report["missingFields"] = serde_json::to_value(missing)?;
+            }
+            serde_json::to_string_pretty(&report)
+        });
+        if !print_json(path, json) {
+            failed = true;
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn inspect(args: Vec<OsString>) -> ExitCode {
+    let mut pkt = None;
+    let mut paths = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--pkt" {
+            let Some(name) = args.next().and_then(|name| name.into_string().ok()) else {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            };
+            pkt = Some(name);
+        } else {
+            paths.push(PathBuf::from(arg));
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    }
+
+    let mut failed = false;
+    for path in &paths {
+        let inspection = std::fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|payload| wire::inspect_payload(&payload, pkt.as_deref()));
+        let inspection = match inspection {
+            Ok(inspection) => inspection,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                failed = true;
+                continue;
+            }
+        };
+        let json = serde_json::to_string_pretty(&serde_json::json!({
+            "file": path.display().to_string(),
+            "inspection": inspection,
+        }));
+        if !print_json(path, json) {
+            failed = true;
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn load(args: Vec<OsString>) -> ExitCode {
+    let mut options = headless::LoadOptions {
+        rate: 2_000,
+        duration: Duration::from_secs(10),
+        players: 20,
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .and_then(|value| value.into_string().ok())
+            .and_then(|value| value.parse::<u32>().ok());
+        match (arg.to_str(), value) {
+            (Some("--rate"), Some(rate)) => options.rate = rate,
+            (Some("--seconds"), Some(seconds)) => {
+                options.duration = Duration::from_secs(seconds.into())
+            }
+            (Some("--players"), Some(players)) => options.players = players,
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    match headless::generate_load(&options) {
+        Ok(report) => {
+            if print_json(Path::new("load"), serde_json::to_string_pretty(&report)) {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(e) => {
+            eprintln!("load: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print_json(path: &Path, json: serde_json::Result<String>) -> bool {
+    match json {
+        Ok(json) => {
+            println!("{}", json);
+            true
+        }
+        Err(e) => {
+            eprintln!("{}: failed to serialize report: {}", path.display(), e);
+            false
+        }
+    }
+}
@@ -4,8 +4,6 @@ use serde::{Deserialize, Serialize};
 use crate::database::schema as sch;
 use crate::database::db_exec;
 use crate::live::commands_models as lc;
-use crate::live::opcodes_models::class;
-use blueprotobuf_lib::blueprotobuf::EEntityType;
 
 /// A summary of an encounter.
 #[derive(Debug, Clone, Serialize, Deserialize, specta::Type)]
@@ -35,6 +33,8 @@ pub struct EncounterSummaryDto {
     pub remote_encounter_id: Option<i64>,
     /// Whether the encounter is favorited.
     pub is_favorite: bool,
+    /// Whether capture data was lost during the encounter, so its numbers are not accurate.
+    pub is_incomplete: bool,
 }
 
 /// The result of a query for recent encounters.
@@ -244,6 +244,7 @@ pub fn get_recent_encounters_filtered(
             i32,
             Option<String>,
             Option<String>,
+            i32,
         )> = e::encounters
             .filter(e::ended_at_ms.is_not_null())
             .order(e::started_at_ms.desc())
@@ -260,11 +261,12 @@ pub fn get_recent_encounters_filtered(
                 e::is_favorite,
                 e::boss_names,
                 e::player_names,
+                e::is_incomplete,
             ))
             .load(conn)
             .map_err(|er| er.to_string())?;
     if let Some(filter) = filters {
-        rows.retain(|(_, started, _, _, _, _, scene_name, _, _, is_favorite, boss_names_json, player_names_json)| {
+        rows.retain(|(_, started, _, _, _, _, scene_name, _, _, is_favorite, boss_names_json, player_names_json, _)| {
             if let Some(is_fav) = filter.is_favorite {
                 if is_fav && *is_favorite == 0 {
                     return false;
@@ -331,7 +333,7 @@ pub fn get_recent_encounters_filtered(
     // Collect boss and player data for each encounter
     let mut mapped: Vec<EncounterSummaryDto> = Vec::new();
 
-    for (id, started, ended, td, th, scene_id, scene_name, duration, remote_id, is_fav, boss_json, _) in paged_rows {
+    for (id, started, ended, td, th, scene_id, scene_name, duration, remote_id, is_fav, boss_json, _, incomplete) in paged_rows {
         let boss_entries: Vec<BossSummaryDto> = boss_json
             .as_ref()
             .and_then(|j| serde_json::from_str::<Vec<String>>(j).ok())
@@ -357,6 +359,7 @@ pub fn get_recent_encounters_filtered(
             bosses: boss_entries,
             remote_encounter_id: remote_id,
             is_favorite: is_fav != 0,
+            is_incomplete: incomplete != 0,
         });
     }
 
@@ -491,6 +494,7 @@ pub fn get_encounter_by_id(encounter_id: i32) -> Result<EncounterSummaryDto, Str
         Option<i64>,
         i32,
         Option<String>,
+        i32,
     ) = with_db(move |conn| {
         e::encounters
             .filter(e::id.eq(encounter_id))
@@ -507,6 +511,7 @@ pub fn get_encounter_by_id(encounter_id: i32) -> Result<EncounterSummaryDto, Str
                 e::remote_encounter_id,
                 e::is_favorite,
                 e::boss_names,
+                e::is_incomplete,
             ))
             .first(conn)
             .map_err(|er| er.to_string())
@@ -537,6 +542,7 @@ pub fn get_encounter_by_id(encounter_id: i32) -> Result<EncounterSummaryDto, Str
         bosses: boss_names,
         remote_encounter_id: row.9,
         is_favorite: row.10 != 0,
+        is_incomplete: row.12 != 0,
     })
 }
 
@@ -545,52 +551,98 @@ pub fn get_encounter_by_id(encounter_id: i32) -> Result<EncounterSummaryDto, Str
 #[specta::specta]
 pub fn get_encounter_entities_raw(encounter_id: i32) -> Result<Vec<lc::HistoryEntityData>, String> {
     let entities = crate::database::load_encounter_data(encounter_id)?;
-    let mut rows = Vec::new();
-    for (&uid, entity) in &entities {
-        if entity.entity_type != EEntityType::EntChar {
-            continue;
-        }
-        let has_combat = entity.damage.hits > 0 || entity.healing.hits > 0 || entity.taken.hits > 0;
-        if !has_combat {
-            continue;
-        }
-        rows.push(lc::HistoryEntityData {
-            uid,
-            name: entity.name.clone(),
-            class_id: entity.class_id,
-            class_spec: entity.class_spec as i32,
-            class_name: class::get_class_name(entity.class_id),
-            class_spec_name: class::get_class_spec(entity.class_spec),
-            ability_score: entity.ability_score,
-            damage: lc::to_raw_combat_stats(&entity.damage),
-            damage_boss_only: lc::to_raw_combat_stats(&entity.damage_boss_only),
-            healing: lc::to_raw_combat_stats(&entity.healing),
-            taken: lc::to_raw_combat_stats(&entity.taken),
-            active_dmg_time_ms: entity.active_dmg_time_ms,
-            dmg_skills: entity
-                .skill_uid_to_dmg_skill
-                .iter()
-                .map(|(skill_id, stats)| (*skill_id, lc::to_raw_skill_stats(stats)))
-                .collect(),
-            heal_skills: entity
-                .skill_uid_to_heal_skill
-                .iter()
-                .map(|(skill_id, stats)| (*skill_id, lc::to_raw_skill_stats(stats)))
-                .collect(),
-            taken_skills: entity
-                .skill_uid_to_taken_skill
-                .iter()
-                .map(|(skill_id, stats)| (*skill_id, lc::to_raw_skill_stats(stats)))
-                .collect(),
-            dmg_per_target: lc::build_per_target_stats(
-                &entity.skill_dmg_to_target,
-                Some(&entity.dmg_to_target),
-            ),
-            heal_per_target: lc::build_per_target_stats(&entity.skill_heal_to_target, None),
-        });
-    }
-    rows.sort_by_key(|row| row.uid);
-    Ok(rows)
+    Ok(lc::build_history_entities(&entities))
+}
+
+/// Gets the per-second damage, healing and damage taken of each entity in an encounter.
+///
+/// # Arguments
+///
+/// * `encounter_id` - The ID of the encounter.
+///
+/// # Returns
+///
+/// * `Result<Vec<lc::EntityTimeline>, String>` - One timeline per entity that dealt, healed or took damage.
+#[tauri::command]
+#[specta::specta]
+pub fn get_encounter_timeline(encounter_id: i32) -> Result<Vec<lc::EntityTimeline>, String> {
+    let entities = crate::database::load_encounter_data(encounter_id)?;
+    Ok(lc::build_entity_timelines(&entities))
+}
+
+/// Gets the death recaps of an encounter: who died, to what, and the hits and heals before it.
+///
+/// # Arguments
+///
+/// * `encounter_id` - The ID of the encounter.
+///
+/// # Returns
+///
+/// * `Result<Vec<lc::DeathRecap>, String>` - The player deaths, in the order they happened.
+#[tauri::command]
+#[specta::specta]
+pub fn get_encounter_deaths(encounter_id: i32) -> Result<Vec<lc::DeathRecap>, String> {
+    let entities = crate::database::load_encounter_data(encounter_id)?;
+    Ok(lc::build_death_recaps(&entities))
+}
+
+/// Gets the buff uptime of each entity over the fight of an encounter.
+///
+/// # Arguments
+///
+/// * `encounter_id` - The ID of the encounter.
+///
+/// # Returns
+///
+/// * `Result<Vec<lc::EntityBuffUptime>, String>` - Uptime percentage and average stacks per buff.
+#[tauri::command]
+#[specta::specta]
+pub fn get_encounter_buff_uptime(encounter_id: i32) -> Result<Vec<lc::EntityBuffUptime>, String> {
+    use sch::encounters::dsl as e;
+    let (started_at_ms, duration): (i64, f64) = with_db(move |conn| {
+        e::encounters
+            .filter(e::id.eq(encounter_id))
+            .select((e::started_at_ms, e::duration))
+            .first(conn)
+            .map_err(|er| er.to_string())
+    })?;
+    let entities = crate::database::load_encounter_data(encounter_id)?;
+    let ended_at_ms = started_at_ms + (duration * 1000.0) as i64;
+    Ok(lc::build_buff_uptimes(&entities, started_at_ms, ended_at_ms))
+}
+
+/// Gets the HP timeline of each boss in an encounter, split into phases at HP thresholds.
+///
+/// # Arguments
+///
+/// * `encounter_id` - The ID of the encounter.
+/// * `phase_thresholds` - HP thresholds per monster type id; bosses without any form a single phase.
+///
+/// # Returns
+///
+/// * `Result<Vec<lc::BossHpTimeline>, String>` - HP samples and per-player damage per phase for each boss.
+#[tauri::command]
+#[specta::specta]
+pub fn get_encounter_phases(
+    encounter_id: i32,
+    phase_thresholds: Vec<lc::PhaseThresholds>,
+) -> Result<Vec<lc::BossHpTimeline>, String> {
+    use sch::encounters::dsl as e;
+    let (started_at_ms, duration): (i64, f64) = with_db(move |conn| {
+        e::encounters
+            .filter(e::id.eq(encounter_id))
+            .select((e::started_at_ms, e::duration))
+            .first(conn)
+            .map_err(|er| er.to_string())
+    })?;
+    let entities = crate::database::load_encounter_data(encounter_id)?;
+    let ended_at_ms = started_at_ms + (duration * 1000.0) as i64;
+    Ok(lc::build_boss_hp_timelines(
+        &entities,
+        started_at_ms,
+        ended_at_ms,
+        &phase_thresholds,
+    ))
 }
 
 
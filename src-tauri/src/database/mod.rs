@@ -41,6 +41,8 @@ pub struct EncounterMetadata {
     pub scene_name: Option<String>,
     pub duration: f64,
     pub is_manually_reset: bool,
+    /// Capture data was lost during the encounter, so its numbers are not accurate.
+    pub is_incomplete: bool,
     pub boss_names: Vec<String>,
     pub player_names: Vec<String>,
 }
@@ -271,11 +273,8 @@ pub fn save_encounter(encounter: &Encounter, metadata: &EncounterMetadata) -> Re
     let combat_entities: HashMap<i64, Entity> = encounter
         .entity_uid_to_entity
         .iter()
-        .filter_map(|(uid, entity)| {
-            let has_combat =
-                entity.damage.hits > 0 || entity.healing.hits > 0 || entity.taken.hits > 0;
-            has_combat.then_some((*uid, entity.clone()))
-        })
+        .filter(|(_, entity)| entity.has_combat())
+        .map(|(uid, entity)| (*uid, entity.clone()))
         .collect();
 
     let entities_bin = rmp_serde::to_vec(&combat_entities).map_err(|e| e.to_string())?;
@@ -304,6 +303,7 @@ pub fn save_encounter(encounter: &Encounter, metadata: &EncounterMetadata) -> Re
             diesel::update(e::encounters.filter(e::id.eq(encounter_id)))
                 .set((
                     e::is_manually_reset.eq(if metadata.is_manually_reset { 1 } else { 0 }),
+                    e::is_incomplete.eq(if metadata.is_incomplete { 1 } else { 0 }),
                     e::boss_names.eq(Some(boss_names_json)),
                     e::player_names.eq(Some(player_names_json)),
                 ))
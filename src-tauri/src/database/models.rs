@@ -137,6 +137,8 @@ pub struct EncounterRow {
     pub is_manually_reset: i32,
     pub boss_names: Option<String>,
     pub player_names: Option<String>,
+    /// Whether capture data was lost during the encounter.
+    pub is_incomplete: i32,
 }
 
 /// Represents a new encounter to be inserted into the `encounters` table.
@@ -80,6 +80,8 @@ diesel::table! {
         boss_names -> Nullable<Text>,
         // JSON-encoded array of player names for fast list/filter queries.
         player_names -> Nullable<Text>,
+        // Whether capture data was lost during this encounter (numbers are not accurate).
+        is_incomplete -> Integer,
     }
 }
 
@@ -3,10 +3,21 @@ mod live;
 pub mod module_optimizer;
 mod packets;
 
+/// Offline analysis of capture and session files, used by the `resonance-analyzer` binary.
+pub use live::headless;
+pub use packets::discovery;
+/// Synthetic game traffic and capture files, used by the end-to-end tests.
+pub use packets::encoder;
+pub use packets::wire;
+/// Embedding the live state outside the app: create both halves with
+/// [`AppStateManager::with_event_sink`], feed [`StateEvent`]s through the
+/// manager, and read what the state emits from the sink.
+pub use live::event_sink;
+pub use live::state::{AppState, AppStateManager, StateEvent};
+
 use crate::build_app::build_and_run;
 use log::{info, warn};
 use specta_typescript::{BigIntExportBehavior, Typescript};
-#[cfg(windows)]
 use std::process::{Command, Stdio};
 
 use std::path::{Path, PathBuf};
@@ -14,7 +25,7 @@ use std::sync::OnceLock;
 
 use tauri::menu::MenuBuilder;
 use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
-use tauri::{Emitter, LogicalPosition, LogicalSize, Manager, Position, Size, Window, WindowEvent};
+use tauri::{LogicalPosition, LogicalSize, Manager, Position, Size, Window, WindowEvent};
 use tauri_plugin_window_state::{AppHandleExt, StateFlags};
 // NOTE: the updater extension trait is imported next to the helper that uses it
 // and is cfg-gated to avoid unused-import warnings on builds that don't enable
@@ -64,6 +75,8 @@ pub fn run() {
             live::commands::search_buffs_by_name,
             live::commands::set_monitor_all_buff,
             live::commands::set_buff_priority,
+            live::commands::start_session_recording,
+            live::commands::stop_session_recording,
             database::commands::get_recent_encounters,
             database::commands::get_unique_scene_names,
             database::commands::get_unique_boss_names,
@@ -71,6 +84,10 @@ pub fn run() {
             database::commands::get_recent_encounters_filtered,
             database::commands::get_encounter_by_id,
             database::commands::get_encounter_entities_raw,
+            database::commands::get_encounter_timeline,
+            database::commands::get_encounter_deaths,
+            database::commands::get_encounter_buff_uptime,
+            database::commands::get_encounter_phases,
             database::commands::delete_encounter,
             database::commands::delete_encounters,
             database::commands::toggle_favorite_encounter,
@@ -79,6 +96,16 @@ pub fn run() {
             packet_settings_commands::save_packet_capture_settings,
             packets::npcap::get_network_devices,
             packets::npcap::check_npcap_status,
+            packets::af_packet::get_af_packet_interfaces,
+            packets::flows::get_active_flows,
+            packets::stats::get_capture_stats,
+            packets::discovery::set_method_discovery,
+            packets::discovery::get_unknown_methods,
+            packets::discovery::clear_unknown_methods,
+            packets::detection::get_server_detection_status,
+            packets::detection::reload_server_detection_rules,
+            packets::detection::pin_game_server,
+            packets::wire::inspect_protobuf,
             debug_commands::open_log_dir,
             debug_commands::create_diagnostics_bundle,
             module_optimizer::commands::check_gpu_support,
@@ -224,11 +251,17 @@ pub fn run() {
 mod packet_settings_commands {
     use super::*;
 
+    /// Persists the packet capture method and its options to `stores/packetCapture.json`.
+    ///
+    /// Keys not covered by the arguments (e.g. replay file paths) are preserved.
+    /// The `af_packet_*` options are only used by the Linux `AfPacket` method.
     #[tauri::command]
     #[specta::specta]
     pub fn save_packet_capture_settings(
         method: String,
         npcap_device: String,
+        af_packet_device: Option<String>,
+        af_packet_filter: Option<bool>,
         app_handle: tauri::AppHandle,
     ) -> Result<(), String> {
         let app_data_dirs = [
@@ -244,10 +277,19 @@ mod packet_settings_commands {
                 continue;
             }
             let path = target_dir.join("packetCapture.json");
-            let payload = json!({
-                "method": method,
-                "npcapDevice": npcap_device,
-            });
+            let mut payload = std::fs::read(&path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+                .filter(|value| value.is_object())
+                .unwrap_or_else(|| json!({}));
+            payload["method"] = json!(method);
+            payload["npcapDevice"] = json!(npcap_device);
+            if let Some(device) = &af_packet_device {
+                payload["afPacketDevice"] = json!(device);
+            }
+            if let Some(filter) = af_packet_filter {
+                payload["afPacketFilter"] = json!(filter);
+            }
             match std::fs::write(
                 &path,
                 serde_json::to_vec_pretty(&payload).map_err(|e| e.to_string())?,
@@ -415,6 +457,8 @@ fn run_command_silently(cmd: &mut Command) -> std::io::Result<std::process::Exit
 // Updater helper: checks for updates and emits an event for frontend reminder.
 // This runs only on Windows builds (guarded where it is invoked).
 #[cfg(windows)]
+use tauri::Emitter;
+#[cfg(windows)]
 use tauri_plugin_updater::UpdaterExt;
 
 #[cfg(windows)]
@@ -0,0 +1,105 @@
+//! Time source of the live state.
+//!
+//! Live capture stamps combat with the system clock. A replayed capture or
+//! session is stamped with the time its messages were originally captured, so
+//! fight durations, segment timeouts and deferred resets come out the same no
+//! matter how fast the file is fed through the pipeline.
+
+use crate::database::now_ms;
+use std::time::{Duration, Instant};
+
+/// Where [`AppState`](crate::live::state::AppState) reads the current time from.
+#[derive(Debug, Clone, Copy)]
+pub enum Clock {
+    /// The system clock.
+    Wall,
+    /// The capture time of the messages being applied, set by [`Clock::advance_to`].
+    Replay {
+        /// The instant standing for `origin_ms`.
+        origin: Instant,
+        /// The first capture time seen, if any.
+        origin_ms: Option<i64>,
+        now_ms: i64,
+    },
+}
+
+impl Clock {
+    /// A clock for replays. Until the first capture time arrives it reads the
+    /// time it was created at.
+    pub fn replay() -> Self {
+        Self::Replay {
+            origin: Instant::now(),
+            origin_ms: None,
+            now_ms: now_ms(),
+        }
+    }
+
+    /// Current time in ms since the Unix epoch.
+    pub fn now_ms(&self) -> i64 {
+        match self {
+            Self::Wall => now_ms(),
+            Self::Replay { now_ms, .. } => *now_ms,
+        }
+    }
+
+    /// Current time as an instant, for measuring timeouts.
+    ///
+    /// A replay clock advances with the capture time, not with the time spent replaying.
+    pub fn now(&self) -> Instant {
+        match *self {
+            Self::Wall => Instant::now(),
+            Self::Replay {
+                origin,
+                origin_ms,
+                now_ms,
+            } => {
+                let elapsed_ms = origin_ms.map_or(0, |origin_ms| now_ms - origin_ms);
+                origin + Duration::from_millis(u64::try_from(elapsed_ms).unwrap_or(0))
+            }
+        }
+    }
+
+    /// Moves a replay clock to `timestamp_ms`; the wall clock ignores this.
+    ///
+    /// Packets of different connections may be captured slightly out of order,
+    /// so the clock never moves backwards.
+    pub fn advance_to(&mut self, timestamp_ms: i64) {
+        if let Self::Replay {
+            origin_ms, now_ms, ..
+        } = self
+        {
+            if origin_ms.is_none() {
+                *origin_ms = Some(timestamp_ms);
+                *now_ms = timestamp_ms;
+            } else {
+                *now_ms = (*now_ms).max(timestamp_ms);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Clock;
+    use std::time::Duration;
+
+    #[test]
+    fn replay_clock_follows_capture_time() {
+        let mut clock = Clock::replay();
+        clock.advance_to(1_000_000);
+        let start = clock.now();
+        assert_eq!(clock.now_ms(), 1_000_000);
+
+        clock.advance_to(1_015_000);
+        clock.advance_to(1_014_000);
+        assert_eq!(clock.now_ms(), 1_015_000);
+        assert_eq!(clock.now() - start, Duration::from_secs(15));
+    }
+
+    #[test]
+    fn wall_clock_ignores_capture_time() {
+        let mut clock = Clock::Wall;
+        clock.advance_to(1_000);
+        assert!(clock.now_ms() > 1_000);
+    }
+}
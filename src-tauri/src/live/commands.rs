@@ -1,6 +1,7 @@
 use crate::WINDOW_LIVE_LABEL;
 use crate::live::dungeon_log;
 use crate::live::state::{AppStateManager, StateEvent};
+use crate::packets::session::SESSION_FILE_EXTENSION;
 use log::info;
 use tauri::Manager;
 use window_vibrancy::{apply_blur, clear_blur};
@@ -309,3 +310,58 @@ pub async fn set_buff_priority(
     state_manager.set_buff_priority(priority_buff_ids).await?;
     Ok(())
 }
+
+/// Starts recording every received message into a new session file.
+///
+/// Sessions are written to `<app data>/sessions` and can be replayed with the
+/// `SessionFile` capture method.
+///
+/// # Arguments
+///
+/// * `app_handle` - A handle to the Tauri application instance.
+/// * `state_manager` - The state manager.
+///
+/// # Returns
+///
+/// * `Result<String, String>` - The path of the session file being written.
+#[tauri::command]
+#[specta::specta]
+pub async fn start_session_recording(
+    app_handle: tauri::AppHandle,
+    state_manager: tauri::State<'_, AppStateManager>,
+) -> Result<String, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?
+        .join("sessions");
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("create_dir_all {}: {}", dir.display(), e))?;
+    let file_name = format!(
+        "session-{}.{}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        SESSION_FILE_EXTENSION
+    );
+    let path = dir.join(file_name);
+    state_manager.start_session_recording(path.clone()).await?;
+    info!("session recording requested path={}", path.display());
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Stops the active session recording, if any, and finalizes its file.
+///
+/// # Arguments
+///
+/// * `state_manager` - The state manager.
+///
+/// # Returns
+///
+/// * `Result<(), String>` - An empty result.
+#[tauri::command]
+#[specta::specta]
+pub async fn stop_session_recording(
+    state_manager: tauri::State<'_, AppStateManager>,
+) -> Result<(), String> {
+    state_manager.stop_session_recording().await?;
+    Ok(())
+}
@@ -1,5 +1,10 @@
+use crate::live::buff_names;
 use crate::live::opcodes_models::SkillTargetStats;
-use crate::live::opcodes_models::{CombatStats, Skill};
+use crate::live::opcodes_models::{
+    CombatStats, Entity, HpSample, RecapEvent, Skill, TIMELINE_BUCKET_MS, class,
+};
+use blueprotobuf_lib::blueprotobuf::EEntityType;
+use std::cmp::Reverse;
 use std::collections::HashMap;
 
 /// Represents the health of a boss.
@@ -14,6 +19,12 @@ pub struct BossHealth {
     pub current_hp: Option<i64>,
     /// The maximum HP of the boss.
     pub max_hp: Option<i64>,
+    /// The predicted time until the boss dies at the raid's recent damage rate, in milliseconds.
+    pub time_to_kill_ms: Option<u64>,
+    /// The time from the pull until the boss enrages, in milliseconds, if known.
+    pub enrage_ms: Option<u64>,
+    /// Whether the boss is predicted to die before it enrages.
+    pub on_track: Option<bool>,
 }
 
 /// Represents the header information for an encounter.
@@ -57,6 +68,8 @@ pub struct LiveDataPayload {
     pub entities: Vec<RawEntityData>,
     pub current_segment_type: Option<String>,
     pub current_segment_name: Option<String>,
+    /// Capture data was lost during the fight, so its numbers are not accurate.
+    pub is_incomplete: bool,
 }
 
 #[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
@@ -99,6 +112,18 @@ pub struct HistoryEntityData {
     pub taken_skills: HashMap<i64, RawSkillStats>,
     pub dmg_per_target: Vec<PerTargetStats>,
     pub heal_per_target: Vec<PerTargetStats>,
+    /// Skill casts in the order they were seen; only recorded for the local player.
+    pub casts: Vec<SkillCastRecord>,
+}
+
+/// A single skill cast in an entity's cast log.
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SkillCastRecord {
+    pub timestamp_ms: i64,
+    pub skill_id: i32,
+    pub skill_level_id: i32,
+    pub target_uid: Option<i64>,
 }
 
 #[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
@@ -210,6 +235,441 @@ pub fn build_per_target_stats(
     rows
 }
 
+/// Builds the per-player rows shown in encounter history.
+///
+/// Only player characters with at least one damage, healing or taken hit are
+/// included. Rows are sorted by uid.
+pub fn build_history_entities(entities: &HashMap<i64, Entity>) -> Vec<HistoryEntityData> {
+    let mut rows = Vec::new();
+    for (&uid, entity) in entities {
+        if entity.entity_type != EEntityType::EntChar {
+            continue;
+        }
+        if !entity.has_combat() {
+            continue;
+        }
+        rows.push(HistoryEntityData {
+            uid,
+            name: entity.name.clone(),
+            class_id: entity.class_id,
+            class_spec: entity.class_spec as i32,
+            class_name: class::get_class_name(entity.class_id),
+            class_spec_name: class::get_class_spec(entity.class_spec),
+            ability_score: entity.ability_score,
+            damage: to_raw_combat_stats(&entity.damage),
+            damage_boss_only: to_raw_combat_stats(&entity.damage_boss_only),
+            healing: to_raw_combat_stats(&entity.healing),
+            taken: to_raw_combat_stats(&entity.taken),
+            active_dmg_time_ms: entity.active_dmg_time_ms,
+            dmg_skills: entity
+                .skill_uid_to_dmg_skill
+                .iter()
+                .map(|(skill_id, stats)| (*skill_id, to_raw_skill_stats(stats)))
+                .collect(),
+            heal_skills: entity
+                .skill_uid_to_heal_skill
+                .iter()
+                .map(|(skill_id, stats)| (*skill_id, to_raw_skill_stats(stats)))
+                .collect(),
+            taken_skills: entity
+                .skill_uid_to_taken_skill
+                .iter()
+                .map(|(skill_id, stats)| (*skill_id, to_raw_skill_stats(stats)))
+                .collect(),
+            dmg_per_target: build_per_target_stats(
+                &entity.skill_dmg_to_target,
+                Some(&entity.dmg_to_target),
+            ),
+            heal_per_target: build_per_target_stats(&entity.skill_heal_to_target, None),
+            casts: entity
+                .casts
+                .iter()
+                .map(|cast| SkillCastRecord {
+                    timestamp_ms: cast.timestamp_ms,
+                    skill_id: cast.skill_id(),
+                    skill_level_id: cast.skill_level_id,
+                    target_uid: cast.target_uid,
+                })
+                .collect(),
+        });
+    }
+    rows.sort_by_key(|row| row.uid);
+    rows
+}
+
+/// Damage, healing and damage taken of one entity over an encounter, in fixed-width buckets.
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityTimeline {
+    pub uid: i64,
+    pub name: String,
+    pub class_id: i32,
+    pub is_player: bool,
+    /// Bucket width in ms; bucket `i` starts `i * bucket_ms` after the fight started.
+    pub bucket_ms: u128,
+    pub damage: Vec<u128>,
+    pub healing: Vec<u128>,
+    pub taken: Vec<u128>,
+}
+
+/// Builds the timelines of every entity that dealt, healed or took damage, sorted by uid.
+pub fn build_entity_timelines(entities: &HashMap<i64, Entity>) -> Vec<EntityTimeline> {
+    let mut rows: Vec<EntityTimeline> = entities
+        .iter()
+        .filter(|(_, entity)| !entity.timeline.is_empty())
+        .map(|(&uid, entity)| EntityTimeline {
+            uid,
+            name: entity.name.clone(),
+            class_id: entity.class_id,
+            is_player: entity.entity_type == EEntityType::EntChar,
+            bucket_ms: TIMELINE_BUCKET_MS,
+            damage: entity.timeline.iter().map(|bucket| bucket.damage).collect(),
+            healing: entity
+                .timeline
+                .iter()
+                .map(|bucket| bucket.healing)
+                .collect(),
+            taken: entity.timeline.iter().map(|bucket| bucket.taken).collect(),
+        })
+        .collect();
+    rows.sort_by_key(|row| row.uid);
+    rows
+}
+
+/// A player death with the hits and heals received before it.
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeathRecap {
+    pub uid: i64,
+    pub name: String,
+    pub class_id: i32,
+    pub timestamp_ms: i64,
+    pub killer_uid: Option<i64>,
+    pub killer_name: Option<String>,
+    /// The skill that dealt the killing blow.
+    pub skill_id: Option<i32>,
+    /// Hits and heals received before the death, oldest first, including the killing blow.
+    pub events: Vec<RecapEventRecord>,
+}
+
+/// A hit or heal in a death recap.
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RecapEventRecord {
+    pub timestamp_ms: i64,
+    pub source_uid: i64,
+    pub source_name: Option<String>,
+    pub skill_id: i32,
+    /// Damage (HP and shield lost) or healing received.
+    pub value: u128,
+    pub is_heal: bool,
+    /// Current HP before the event, when known.
+    pub hp_before: Option<i64>,
+}
+
+/// Builds the recap of every recorded death, in the order the deaths happened.
+pub fn build_death_recaps(entities: &HashMap<i64, Entity>) -> Vec<DeathRecap> {
+    let name_of = |uid: i64| {
+        entities
+            .get(&uid)
+            .map(|entity| entity.name.clone())
+            .filter(|name| !name.is_empty())
+    };
+    let to_record = |event: &RecapEvent| RecapEventRecord {
+        timestamp_ms: event.timestamp_ms,
+        source_uid: event.source_uid,
+        source_name: name_of(event.source_uid),
+        skill_id: event.skill_id,
+        value: event.value,
+        is_heal: event.is_heal,
+        hp_before: event.hp_before,
+    };
+
+    let mut rows = Vec::new();
+    for (&uid, entity) in entities {
+        for death in &entity.deaths {
+            rows.push(DeathRecap {
+                uid,
+                name: entity.name.clone(),
+                class_id: entity.class_id,
+                timestamp_ms: death.timestamp_ms,
+                killer_uid: death.killer_uid,
+                killer_name: death.killer_uid.and_then(name_of),
+                skill_id: death.skill_id,
+                events: death.recent_events.iter().map(to_record).collect(),
+            });
+        }
+    }
+    rows.sort_by_key(|row| (row.timestamp_ms, row.uid));
+    rows
+}
+
+/// Buff uptime of one entity over an encounter.
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityBuffUptime {
+    pub uid: i64,
+    pub name: String,
+    pub class_id: i32,
+    pub is_player: bool,
+    /// Buffs sorted by uptime, highest first.
+    pub buffs: Vec<BuffUptimeStats>,
+}
+
+/// Uptime of one buff base id on an entity.
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BuffUptimeStats {
+    pub base_id: i32,
+    pub name: Option<String>,
+    pub uptime_ms: i64,
+    /// Share of the encounter the buff was up, 0-100.
+    pub uptime_pct: f64,
+    /// Average stacks while the buff was up.
+    pub avg_stacks: f64,
+}
+
+/// Builds the buff uptime of every entity over the fight from `start_ms` to `end_ms`.
+///
+/// Buffs that were never up during the fight are left out. Rows are sorted by uid.
+pub fn build_buff_uptimes(
+    entities: &HashMap<i64, Entity>,
+    start_ms: i64,
+    end_ms: i64,
+) -> Vec<EntityBuffUptime> {
+    let duration_ms = end_ms - start_ms;
+    if duration_ms <= 0 {
+        return Vec::new();
+    }
+
+    let mut rows = Vec::new();
+    for (&uid, entity) in entities {
+        let mut buffs: Vec<BuffUptimeStats> = entity
+            .buff_uptime
+            .iter()
+            .filter_map(|(&base_id, uptime)| {
+                let (up_ms, layer_ms) = uptime.up_ms_within(start_ms, end_ms);
+                (up_ms > 0).then(|| BuffUptimeStats {
+                    base_id,
+                    name: buff_names::lookup_name(base_id),
+                    uptime_ms: up_ms,
+                    uptime_pct: up_ms as f64 * 100.0 / duration_ms as f64,
+                    avg_stacks: layer_ms as f64 / up_ms as f64,
+                })
+            })
+            .collect();
+        if buffs.is_empty() {
+            continue;
+        }
+        buffs.sort_by_key(|buff| (Reverse(buff.uptime_ms), buff.base_id));
+        rows.push(EntityBuffUptime {
+            uid,
+            name: entity.name.clone(),
+            class_id: entity.class_id,
+            is_player: entity.entity_type == EEntityType::EntChar,
+            buffs,
+        });
+    }
+    rows.sort_by_key(|row| row.uid);
+    rows
+}
+
+/// HP percentages at which fights against one boss type move to the next phase.
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseThresholds {
+    pub monster_type_id: i32,
+    /// Boss HP in percent (0-100); a new phase starts when HP drops to each of them.
+    pub hp_pct: Vec<f64>,
+}
+
+/// HP over time and phases of one boss in an encounter.
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BossHpTimeline {
+    pub uid: i64,
+    pub name: String,
+    pub monster_type_id: Option<i32>,
+    pub samples: Vec<HpSampleRecord>,
+    /// Empty when the boss never lost HP.
+    pub phases: Vec<BossPhase>,
+}
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HpSampleRecord {
+    pub timestamp_ms: i64,
+    pub hp: i64,
+    pub max_hp: i64,
+}
+
+/// A stretch of a boss fight between two HP thresholds.
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BossPhase {
+    /// 1-based phase number.
+    pub index: u32,
+    pub started_at_ms: i64,
+    pub ended_at_ms: i64,
+    /// The threshold that started the phase; 100 for the first phase.
+    pub start_hp_pct: f64,
+    /// Damage each player dealt to the boss during the phase, highest first.
+    pub players: Vec<PhasePlayerDamage>,
+}
+
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PhasePlayerDamage {
+    pub uid: i64,
+    pub name: String,
+    pub class_id: i32,
+    pub damage: u128,
+}
+
+/// Builds the HP timeline and phases of every boss with HP samples, sorted by uid.
+///
+/// The first phase starts when a boss first loses HP. Each threshold configured for
+/// its monster type that the boss reached starts a new phase, and the last phase ends
+/// when the boss dies or the fight (`fight_start_ms..fight_end_ms`) ends. Player
+/// damage per phase counts damage to that boss at [`TIMELINE_BUCKET_MS`] resolution.
+pub fn build_boss_hp_timelines(
+    entities: &HashMap<i64, Entity>,
+    fight_start_ms: i64,
+    fight_end_ms: i64,
+    thresholds: &[PhaseThresholds],
+) -> Vec<BossHpTimeline> {
+    let mut rows: Vec<BossHpTimeline> = entities
+        .iter()
+        .filter(|(_, entity)| !entity.hp_samples.is_empty())
+        .map(|(&uid, entity)| {
+            let hp_pct = thresholds
+                .iter()
+                .find(|t| Some(t.monster_type_id) == entity.monster_type_id)
+                .map_or(&[][..], |t| t.hp_pct.as_slice());
+            let phases = boss_phase_windows(&entity.hp_samples, hp_pct, fight_end_ms)
+                .into_iter()
+                .enumerate()
+                .map(
+                    |(i, (start_hp_pct, started_at_ms, ended_at_ms))| BossPhase {
+                        index: i as u32 + 1,
+                        started_at_ms,
+                        ended_at_ms,
+                        start_hp_pct,
+                        players: phase_player_damage(
+                            entities,
+                            uid,
+                            fight_start_ms,
+                            started_at_ms,
+                            ended_at_ms,
+                        ),
+                    },
+                )
+                .collect();
+            BossHpTimeline {
+                uid,
+                name: entity.name.clone(),
+                monster_type_id: entity.monster_type_id,
+                samples: entity
+                    .hp_samples
+                    .iter()
+                    .map(|sample| HpSampleRecord {
+                        timestamp_ms: sample.timestamp_ms,
+                        hp: sample.hp,
+                        max_hp: sample.max_hp,
+                    })
+                    .collect(),
+                phases,
+            }
+        })
+        .collect();
+    rows.sort_by_key(|row| row.uid);
+    rows
+}
+
+/// Splits a boss fight into `(start_hp_pct, started_at_ms, ended_at_ms)` windows.
+fn boss_phase_windows(
+    samples: &[HpSample],
+    hp_pct: &[f64],
+    fight_end_ms: i64,
+) -> Vec<(f64, i64, i64)> {
+    let Some(first_hit) = samples.iter().position(|s| s.hp < s.max_hp) else {
+        return Vec::new();
+    };
+    let samples = &samples[first_hit..];
+    let death_ms = samples.iter().find(|s| s.hp <= 0).map(|s| s.timestamp_ms);
+    let end_ms = death_ms
+        .unwrap_or(fight_end_ms)
+        .max(samples[0].timestamp_ms);
+
+    let mut thresholds: Vec<f64> = hp_pct
+        .iter()
+        .copied()
+        .filter(|pct| *pct > 0.0 && *pct < 100.0)
+        .collect();
+    thresholds.sort_by(|a, b| b.total_cmp(a));
+
+    let mut windows = vec![(100.0, samples[0].timestamp_ms, end_ms)];
+    for pct in thresholds {
+        let reached = samples
+            .iter()
+            .find(|s| s.max_hp > 0 && (s.hp as f64) * 100.0 <= pct * s.max_hp as f64);
+        let Some(reached) = reached else {
+            break;
+        };
+        let Some(current) = windows.last_mut() else {
+            break;
+        };
+        if reached.timestamp_ms >= end_ms {
+            break;
+        }
+        // Thresholds passed in one hit leave an empty phase behind; drop it
+        if reached.timestamp_ms <= current.1 {
+            current.0 = pct;
+            continue;
+        }
+        current.2 = reached.timestamp_ms;
+        windows.push((pct, reached.timestamp_ms, end_ms));
+    }
+    windows
+}
+
+/// Damage each player dealt to `boss_uid` in the timeline buckets starting within
+/// `started_at_ms..ended_at_ms`.
+fn phase_player_damage(
+    entities: &HashMap<i64, Entity>,
+    boss_uid: i64,
+    fight_start_ms: i64,
+    started_at_ms: i64,
+    ended_at_ms: i64,
+) -> Vec<PhasePlayerDamage> {
+    let bucket_ms = TIMELINE_BUCKET_MS as i64;
+    let mut players: Vec<PhasePlayerDamage> = entities
+        .iter()
+        .filter(|(_, entity)| entity.entity_type == EEntityType::EntChar)
+        .filter_map(|(&uid, entity)| {
+            let damage: u128 = entity
+                .boss_damage_timeline
+                .get(&boss_uid)?
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| {
+                    let bucket_start_ms = fight_start_ms + *i as i64 * bucket_ms;
+                    (started_at_ms..ended_at_ms).contains(&bucket_start_ms)
+                })
+                .map(|(_, damage)| damage)
+                .sum();
+            (damage > 0).then(|| PhasePlayerDamage {
+                uid,
+                name: entity.name.clone(),
+                class_id: entity.class_id,
+                damage,
+            })
+        })
+        .collect();
+    players.sort_by_key(|player| (Reverse(player.damage), player.uid));
+    players
+}
+
 /// Represents a skill cooldown state.
 #[derive(specta::Type, serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -294,3 +754,98 @@ pub struct ModuleCalcProgressPayload {
     pub processed: u64,
     pub total: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{PhaseThresholds, build_boss_hp_timelines, build_history_entities};
+    use crate::live::opcodes_models::{Entity, HpSample, SkillCast, TimelineBucket};
+    use blueprotobuf_lib::blueprotobuf::EEntityType;
+    use std::collections::HashMap;
+
+    #[test]
+    fn history_keeps_players_that_only_cast() {
+        let caster = Entity {
+            entity_type: EEntityType::EntChar,
+            casts: vec![SkillCast {
+                timestamp_ms: 1_000,
+                skill_level_id: 1_234_501,
+                target_uid: None,
+            }],
+            ..Default::default()
+        };
+        let idle = Entity {
+            entity_type: EEntityType::EntChar,
+            ..Default::default()
+        };
+        let entities = HashMap::from([(1, caster), (2, idle)]);
+
+        let rows = build_history_entities(&entities);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].uid, 1);
+        assert_eq!(rows[0].casts.len(), 1);
+    }
+
+    #[test]
+    fn boss_fights_split_at_hp_thresholds() {
+        let mut boss = Entity {
+            entity_type: EEntityType::EntMonster,
+            monster_type_id: Some(4_242),
+            ..Default::default()
+        };
+        for (timestamp_ms, hp) in [
+            (0, 1_000),
+            (2_000, 900),
+            (5_000, 600),
+            (8_000, 300),
+            (9_000, 0),
+        ] {
+            boss.hp_samples.push(HpSample {
+                timestamp_ms,
+                hp,
+                max_hp: 1_000,
+            });
+        }
+        let mut player = Entity {
+            entity_type: EEntityType::EntChar,
+            ..Default::default()
+        };
+        // Damage to other targets does not count towards the boss phases
+        player.timeline = (0..10)
+            .map(|_| TimelineBucket {
+                damage: 25,
+                ..Default::default()
+            })
+            .collect();
+        player.boss_damage_timeline = HashMap::from([(1, vec![10; 10])]);
+        let entities = HashMap::from([(1, boss), (2, player)]);
+        let thresholds = [PhaseThresholds {
+            monster_type_id: 4_242,
+            hp_pct: vec![40.0, 70.0, 10.0],
+        }];
+
+        let timelines = build_boss_hp_timelines(&entities, 0, 10_000, &thresholds);
+        assert_eq!(timelines.len(), 1);
+        let phases: Vec<(f64, i64, i64, u128)> = timelines[0]
+            .phases
+            .iter()
+            .map(|phase| {
+                let damage = phase.players.iter().map(|p| p.damage).sum();
+                (
+                    phase.start_hp_pct,
+                    phase.started_at_ms,
+                    phase.ended_at_ms,
+                    damage,
+                )
+            })
+            .collect();
+        // The 10% threshold is only passed by the killing blow
+        assert_eq!(
+            phases,
+            [
+                (100.0, 2_000, 5_000, 30),
+                (70.0, 5_000, 8_000, 30),
+                (40.0, 8_000, 9_000, 10),
+            ]
+        );
+    }
+}
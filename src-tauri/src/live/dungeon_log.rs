@@ -1,9 +1,10 @@
-use log::{info, trace, warn};
+use crate::live::clock::Clock;
+use crate::live::event_sink::{self, EventSink};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, LazyLock, Mutex, MutexGuard};
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
-use tauri::{AppHandle, Emitter, Manager};
+use std::time::{Duration, Instant};
 
 /// Shared handle that can be stored inside Tauri state.
 pub type SharedDungeonLog = Arc<Mutex<DungeonLog>>;
@@ -28,43 +29,68 @@ pub static GLOBAL_BOSS_LIST: LazyLock<HashSet<i64>> = LazyLock::new(|| {
         .unwrap_or_default()
 });
 
-/// Runtime helper that bundles the shared log handle with an app handle for emissions.
+/// Runtime helper that bundles the shared log handle with an event sink for emissions
+/// and the clock of the live state.
+///
+/// Without a sink (headless runs) the log is still updated but nothing is emitted.
 #[derive(Clone)]
 pub struct DungeonLogRuntime {
     pub shared_log: SharedDungeonLog,
-    pub app_handle: AppHandle,
+    pub sink: Option<Arc<dyn EventSink>>,
+    pub clock: Clock,
 }
 
 impl DungeonLogRuntime {
-    pub fn new(shared_log: SharedDungeonLog, app_handle: AppHandle) -> Self {
+    pub fn new(
+        shared_log: SharedDungeonLog,
+        sink: Option<Arc<dyn EventSink>>,
+        clock: Clock,
+    ) -> Self {
         Self {
             shared_log,
-            app_handle,
+            sink,
+            clock,
         }
     }
 
     pub fn process_damage_event(&self, event: DamageEvent) -> (bool, bool) {
-        let (snapshot, boss_died, new_boss_started) = process_damage_event(&self.shared_log, event);
-        emit_if_changed(&self.app_handle, snapshot);
+        let (snapshot, boss_died, new_boss_started) =
+            process_damage_event(&self.shared_log, event, self.clock.now());
+        self.emit(snapshot);
         (boss_died, new_boss_started)
     }
 
     pub fn reset_for_scene(&self, scene_id: Option<i32>, scene_name: Option<String>) {
         let snapshot = reset_for_scene(&self.shared_log, scene_id, scene_name);
-        emit_if_changed(&self.app_handle, snapshot);
+        self.emit(snapshot);
     }
 
-    pub fn check_for_timeout(&self, now: Instant) {
-        let snapshot = check_for_timeout(&self.shared_log, now, SEGMENT_TIMEOUT);
+    pub fn check_for_timeout(&self) {
+        let snapshot = check_for_timeout(
+            &self.shared_log,
+            self.clock.now(),
+            self.clock.now_ms(),
+            SEGMENT_TIMEOUT,
+        );
         if snapshot.is_some() {
-            persist_segments(&self.shared_log, false);
+            self.persist_segments(false);
         }
-        emit_if_changed(&self.app_handle, snapshot);
+        self.emit(snapshot);
+    }
+
+    pub fn persist_segments(&self, force_close: bool) {
+        persist_segments(&self.shared_log, force_close, self.clock.now_ms());
     }
 
     pub fn snapshot(&self) -> Option<DungeonLog> {
         snapshot(&self.shared_log)
     }
+
+    fn emit(&self, snapshot: Option<DungeonLog>) {
+        if let Some(sink) = &self.sink {
+            emit_if_changed(sink.as_ref(), snapshot);
+        }
+    }
 }
 
 /// Cached entity info for boss detection when attributes arrive late.
@@ -322,12 +348,13 @@ impl BattleStateMachine {
         target_id: i32,
         nums: i32,
         complete: i32,
+        now: Instant,
     ) -> Option<EncounterResetReason> {
         let new_entry = DungeonTargetEntry {
             target_id,
             nums,
             complete,
-            received_at: Instant::now(),
+            received_at: now,
         };
 
         if let Some(previous) = self.previous_dungeon_target.as_ref() {
@@ -362,9 +389,9 @@ impl BattleStateMachine {
         None
     }
 
-    pub fn check_deferred_calls(&mut self) -> Option<EncounterResetReason> {
+    pub fn check_deferred_calls(&mut self, now: Instant) -> Option<EncounterResetReason> {
         if let Some((trigger_at, reason)) = self.deferred_reset {
-            if Instant::now() >= trigger_at {
+            if now >= trigger_at {
                 self.deferred_reset = None;
                 info!(
                     target: "app::live",
@@ -533,6 +560,7 @@ mod tests {
         // Simulate timeout - boss segments should NOT close on timeout
         log.handle_timeout(
             Instant::now() + std::time::Duration::from_secs(20),
+            20_000,
             SEGMENT_TIMEOUT,
         );
         assert_eq!(log.combat_state, CombatState::Idle);
@@ -680,6 +708,7 @@ mod tests {
         // Simulate 20 seconds of no damage (boss is invulnerable)
         let timeout_result = log.handle_timeout(
             Instant::now() + std::time::Duration::from_secs(20),
+            20_000,
             SEGMENT_TIMEOUT,
         );
         assert!(timeout_result, "Should return true (state changed)");
@@ -740,6 +769,7 @@ mod tests {
         // Boss transitions - goes invulnerable
         log.handle_timeout(
             Instant::now() + std::time::Duration::from_secs(20),
+            20_000,
             SEGMENT_TIMEOUT,
         );
 
@@ -784,28 +814,9 @@ mod tests {
 }
 
 /// Emits the provided snapshot if available.
-/// Uses safe emission to handle WebView2 state errors gracefully.
-pub fn emit_if_changed(app_handle: &AppHandle, snapshot: Option<DungeonLog>) {
+pub fn emit_if_changed(sink: &dyn EventSink, snapshot: Option<DungeonLog>) {
     if let Some(log) = snapshot {
-        // Check if windows are available before emitting
-        let live_window = app_handle.get_webview_window(crate::WINDOW_LIVE_LABEL);
-        let main_window = app_handle.get_webview_window(crate::WINDOW_MAIN_LABEL);
-
-        if live_window.is_none() && main_window.is_none() {
-            trace!("Skipping log-update emit: no windows available");
-            return;
-        }
-
-        if let Err(err) = app_handle.emit("log-update", log) {
-            // Check if this is a WebView2 state error (0x8007139F)
-            let error_str = format!("{:?}", err);
-            if error_str.contains("0x8007139F") || error_str.contains("not in the correct state") {
-                // This is expected when windows are minimized/hidden - don't spam logs
-                trace!("WebView2 not ready for log-update (window may be minimized/hidden)");
-            } else {
-                warn!("Failed to emit log-update: {}", err);
-            }
-        }
+        event_sink::emit(sink, "log-update", log);
     }
 }
 
@@ -813,8 +824,8 @@ pub fn emit_if_changed(app_handle: &AppHandle, snapshot: Option<DungeonLog>) {
 pub fn process_damage_event(
     handle: &SharedDungeonLog,
     event: DamageEvent,
+    now: Instant,
 ) -> (Option<DungeonLog>, bool, bool) {
-    let now = Instant::now();
     let mut log = match lock_log(handle) {
         Some(guard) => guard,
         None => return (None, false, false),
@@ -855,10 +866,11 @@ pub fn snapshot(handle: &SharedDungeonLog) -> Option<DungeonLog> {
 pub fn check_for_timeout(
     handle: &SharedDungeonLog,
     now: Instant,
+    now_ms: i64,
     timeout: Duration,
 ) -> Option<DungeonLog> {
     let mut log = lock_log(handle)?;
-    let changed = log.handle_timeout(now, timeout);
+    let changed = log.handle_timeout(now, now_ms, timeout);
     if changed { Some(log.clone()) } else { None }
 }
 
@@ -1288,7 +1300,7 @@ impl DungeonLog {
         }
     }
 
-    fn handle_timeout(&mut self, now: Instant, timeout: Duration) -> bool {
+    fn handle_timeout(&mut self, now: Instant, now_ms: i64, timeout: Duration) -> bool {
         if self.combat_state != CombatState::InCombat {
             return false;
         }
@@ -1308,7 +1320,7 @@ impl DungeonLog {
             if let Some(segment) = self.segments.get_mut(idx) {
                 if segment.segment_type == SegmentType::Trash {
                     // Close trash segment on timeout
-                    segment.close(timestamp_now_ms());
+                    segment.close(now_ms);
                     self.active_segment_idx = None;
                 }
                 // Boss segments: keep them open but just go to Idle state
@@ -1325,15 +1337,10 @@ impl DungeonLog {
     }
 }
 
-fn timestamp_now_ms() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|duration| duration.as_millis() as i64)
-        .unwrap_or_default()
-}
-
 /// Persists all closed segments to the database.
-pub fn persist_segments(handle: &SharedDungeonLog, force_close: bool) {
+///
+/// With `force_close`, segments that are still open are first closed at `now_ms`.
+pub fn persist_segments(handle: &SharedDungeonLog, force_close: bool, now_ms: i64) {
 
     // Lock the log to mutate persistence state
     let mut log = match lock_log(handle) {
@@ -1341,11 +1348,9 @@ pub fn persist_segments(handle: &SharedDungeonLog, force_close: bool) {
         None => return,
     };
 
-    let now = timestamp_now_ms();
-
     for segment in log.segments.iter_mut() {
         if force_close && segment.ended_at_ms.is_none() {
-            segment.close(now);
+            segment.close(now_ms);
         }
 
         // Only persist closed segments that haven't been persisted yet
@@ -1,55 +1,71 @@
 use crate::live::commands_models::{
     BossHealth, HeaderInfo, LiveDataPayload, RawEntityData, to_raw_combat_stats, to_raw_skill_stats,
 };
+use crate::live::event_sink::{self, EventSink};
 use crate::live::opcodes_models::{Encounter, class};
+use crate::live::ttk;
+use crate::packets::stats::CaptureStats;
 use blueprotobuf_lib::blueprotobuf::EEntityType;
 use log::{info, trace, warn};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use tauri::{AppHandle, Emitter, Manager};
 use tokio::sync::RwLock;
 
-/// Safely emits an event to the frontend, handling WebView2 state errors gracefully.
-/// This prevents the app from freezing when the WebView is in an invalid state
-/// (e.g., minimized, hidden, or transitioning).
-///
-/// Returns `true` if the event was emitted successfully, `false` otherwise.
-fn safe_emit<S: Serialize + Clone>(app_handle: &AppHandle, event: &str, payload: S) -> bool {
-    // First check if the live window exists and is valid
-    let live_window = app_handle.get_webview_window(crate::WINDOW_LIVE_LABEL);
-    let main_window = app_handle.get_webview_window(crate::WINDOW_MAIN_LABEL);
-
-    // If no windows are available, skip emitting
-    if live_window.is_none() && main_window.is_none() {
-        trace!("Skipping emit for '{}': no windows available", event);
-        return false;
+/// Emits to the frontend windows of the app.
+pub struct TauriEventSink {
+    app_handle: AppHandle,
+}
+
+impl TauriEventSink {
+    pub fn new(app_handle: AppHandle) -> Self {
+        Self { app_handle }
     }
+}
 
-    // Try to emit the event, catching WebView2 errors
-    match app_handle.emit(event, payload) {
-        Ok(_) => true,
-        Err(e) => {
-            // Check if this is a WebView2 state error (0x8007139F)
-            let error_str = format!("{:?}", e);
-            if error_str.contains("0x8007139F") || error_str.contains("not in the correct state") {
-                // This is expected when windows are minimized/hidden - don't spam logs
-                trace!(
-                    "WebView2 not ready for '{}' (window may be minimized/hidden)",
-                    event
-                );
-            } else {
-                // Log other errors as warnings
-                warn!("Failed to emit '{}': {}", event, e);
+impl EventSink for TauriEventSink {
+    /// Handles WebView2 state errors gracefully, so the app does not freeze when
+    /// a WebView is minimized, hidden or transitioning.
+    fn emit(&self, event: &str, payload: Value) -> bool {
+        // First check if the live window exists and is valid
+        let live_window = self.app_handle.get_webview_window(crate::WINDOW_LIVE_LABEL);
+        let main_window = self.app_handle.get_webview_window(crate::WINDOW_MAIN_LABEL);
+
+        // If no windows are available, skip emitting
+        if live_window.is_none() && main_window.is_none() {
+            trace!("Skipping emit for '{}': no windows available", event);
+            return false;
+        }
+
+        // Try to emit the event, catching WebView2 errors
+        match self.app_handle.emit(event, payload) {
+            Ok(_) => true,
+            Err(e) => {
+                // Check if this is a WebView2 state error (0x8007139F)
+                let error_str = format!("{:?}", e);
+                if error_str.contains("0x8007139F")
+                    || error_str.contains("not in the correct state")
+                {
+                    // This is expected when windows are minimized/hidden - don't spam logs
+                    trace!(
+                        "WebView2 not ready for '{}' (window may be minimized/hidden)",
+                        event
+                    );
+                } else {
+                    // Log other errors as warnings
+                    warn!("Failed to emit '{}': {}", event, e);
+                }
+                false
             }
-            false
         }
     }
 }
 
 /// Manages events and emits them to the frontend.
-#[derive(Debug)]
 pub struct EventManager {
-    app_handle: Option<AppHandle>,
+    sink: Option<Arc<dyn EventSink>>,
     dead_bosses: HashSet<i64>,
     // Map boss_uid -> boss_name for persisted marking
     dead_boss_names: HashMap<i64, String>,
@@ -59,22 +75,32 @@ impl EventManager {
     /// Creates a new `EventManager`.
     pub fn new() -> Self {
         Self {
-            app_handle: None,
+            sink: None,
             dead_bosses: HashSet::new(),
             dead_boss_names: HashMap::new(),
         }
     }
 
-    /// Initializes the `EventManager` with a Tauri app handle.
+    /// Initializes the `EventManager` with the destination of its events.
     ///
     /// # Arguments
     ///
-    /// * `app_handle` - A handle to the Tauri application instance.
-    pub fn initialize(&mut self, app_handle: AppHandle) {
-        self.app_handle = Some(app_handle);
+    /// * `sink` - Receives every emitted event, e.g. the Tauri windows.
+    pub fn initialize(&mut self, sink: Arc<dyn EventSink>) {
+        self.sink = Some(sink);
         info!("Event manager initialized");
     }
 
+    /// Emits an arbitrary event.
+    ///
+    /// Returns `true` if the event was delivered.
+    pub fn emit<S: Serialize>(&self, event: &str, payload: S) -> bool {
+        match &self.sink {
+            Some(sink) => event_sink::emit(sink.as_ref(), event, payload),
+            None => false,
+        }
+    }
+
     /// Emits an encounter update event.
     ///
     /// # Arguments
@@ -82,21 +108,17 @@ impl EventManager {
     /// * `header_info` - The header information for the encounter.
     /// * `is_paused` - Whether the encounter is paused.
     pub fn emit_encounter_update(&self, header_info: HeaderInfo, is_paused: bool) {
-        if let Some(app_handle) = &self.app_handle {
-            let payload = EncounterUpdatePayload {
-                header_info,
-                is_paused,
-            };
-            safe_emit(app_handle, "encounter-update", payload);
-        }
+        let payload = EncounterUpdatePayload {
+            header_info,
+            is_paused,
+        };
+        self.emit("encounter-update", payload);
     }
 
     /// Emits an encounter reset event.
     pub fn emit_encounter_reset(&self) {
-        if let Some(app_handle) = &self.app_handle {
-            if safe_emit(app_handle, "reset-encounter", "") {
-                trace!("Emitted reset-encounter event");
-            }
+        if self.emit("reset-encounter", "") {
+            trace!("Emitted reset-encounter event");
         }
     }
 
@@ -112,10 +134,8 @@ impl EventManager {
     ///
     /// * `is_paused` - Whether the encounter is paused.
     pub fn emit_encounter_pause(&self, is_paused: bool) {
-        if let Some(app_handle) = &self.app_handle {
-            if safe_emit(app_handle, "pause-encounter", is_paused) {
-                trace!("Emitted pause-encounter event: {}", is_paused);
-            }
+        if self.emit("pause-encounter", is_paused) {
+            trace!("Emitted pause-encounter event: {}", is_paused);
         }
     }
 
@@ -125,11 +145,20 @@ impl EventManager {
     ///
     /// * `scene_name` - The name of the new scene.
     pub fn emit_scene_change(&self, scene_name: String) {
-        if let Some(app_handle) = &self.app_handle {
-            let payload = SceneChangePayload { scene_name };
-            if safe_emit(app_handle, "scene-change", payload) {
-                info!("Emitted scene-change event");
-            }
+        let payload = SceneChangePayload { scene_name };
+        if self.emit("scene-change", payload) {
+            info!("Emitted scene-change event");
+        }
+    }
+
+    /// Emits the capture pipeline counters.
+    ///
+    /// # Arguments
+    ///
+    /// * `stats` - The current capture statistics.
+    pub fn emit_capture_stats(&self, stats: CaptureStats) {
+        if self.emit("capture-stats", stats) {
+            trace!("Emitted capture-stats event");
         }
     }
 
@@ -145,11 +174,9 @@ impl EventManager {
         if self.dead_bosses.insert(boss_uid) {
             // record the boss name for later persistence
             self.dead_boss_names.insert(boss_uid, boss_name.clone());
-            if let Some(app_handle) = &self.app_handle {
-                let payload = BossDeathPayload { boss_name };
-                if safe_emit(app_handle, "boss-death", payload) {
-                    info!("Emitted boss-death event for {}", boss_uid);
-                }
+            let payload = BossDeathPayload { boss_name };
+            if self.emit("boss-death", payload) {
+                info!("Emitted boss-death event for {}", boss_uid);
             }
             return true;
         }
@@ -175,12 +202,12 @@ impl EventManager {
 
     /// Returns whether the `EventManager` should emit events.
     pub fn should_emit_events(&self) -> bool {
-        self.app_handle.is_some()
+        self.sink.is_some()
     }
 
-    /// Returns a clone of the app handle for lock-free event emission.
-    pub fn get_app_handle(&self) -> Option<AppHandle> {
-        self.app_handle.clone()
+    /// Returns the event sink, for emitters that outlive a borrow of the state.
+    pub fn sink(&self) -> Option<Arc<dyn EventSink>> {
+        self.sink.clone()
     }
 }
 
@@ -209,6 +236,16 @@ pub struct SceneChangePayload {
     pub scene_name: String,
 }
 
+impl std::fmt::Debug for EventManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventManager")
+            .field("emits", &self.sink.is_some())
+            .field("dead_bosses", &self.dead_bosses)
+            .field("dead_boss_names", &self.dead_boss_names)
+            .finish()
+    }
+}
+
 impl Default for EventManager {
     fn default() -> Self {
         Self::new()
@@ -233,8 +270,7 @@ pub fn generate_live_data_payload(
             continue;
         }
 
-        let has_combat = entity.damage.hits > 0 || entity.healing.hits > 0 || entity.taken.hits > 0;
-        if !has_combat {
+        if !entity.has_combat() {
             continue;
         }
 
@@ -296,6 +332,8 @@ pub fn generate_live_data_payload(
                 name,
                 current_hp,
                 max_hp,
+                enrage_ms: entity.monster_type_id.and_then(ttk::enrage_ms),
+                ..Default::default()
             })
         })
         .collect();
@@ -315,5 +353,6 @@ pub fn generate_live_data_payload(
         entities,
         current_segment_type,
         current_segment_name,
+        is_incomplete: encounter.data_loss_events > 0,
     }
 }
@@ -0,0 +1,130 @@
+//! Destinations for the events the live state emits.
+//!
+//! The meter emits to the Tauri windows through
+//! [`TauriEventSink`](crate::live::event_manager::TauriEventSink). Tests and
+//! tools that embed the live state without a window pass their own
+//! [`EventSink`], such as the in-memory [`RecordingSink`], to
+//! [`AppStateManager::with_event_sink`](crate::live::state::AppStateManager::with_event_sink).
+//! Nothing in this module depends on Tauri.
+
+use log::warn;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Mutex;
+
+/// Receives the events the live state emits.
+pub trait EventSink: Send + Sync {
+    /// Delivers `payload` under the event name `event`.
+    ///
+    /// Returns whether the event was delivered.
+    fn emit(&self, event: &str, payload: Value) -> bool;
+}
+
+/// Serializes `payload` and hands it to `sink`.
+///
+/// Returns whether the event was delivered.
+pub fn emit<T: Serialize>(sink: &dyn EventSink, event: &str, payload: T) -> bool {
+    match serde_json::to_value(payload) {
+        Ok(payload) => sink.emit(event, payload),
+        Err(e) => {
+            warn!("Failed to serialize '{}' payload: {}", event, e);
+            false
+        }
+    }
+}
+
+/// Keeps every emitted event in memory, in order.
+#[derive(Debug, Default)]
+pub struct RecordingSink {
+    events: Mutex<Vec<(String, Value)>>,
+}
+
+impl RecordingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes and returns the events recorded so far.
+    pub fn take(&self) -> Vec<(String, Value)> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// Payloads of the recorded events named `event`.
+    pub fn payloads(&self, event: &str) -> Vec<Value> {
+        self.lock()
+            .iter()
+            .filter(|(name, _)| name == event)
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(String, Value)>> {
+        self.events
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl EventSink for RecordingSink {
+    fn emit(&self, event: &str, payload: Value) -> bool {
+        self.lock().push((event.to_string(), payload));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordingSink, emit};
+    use crate::live::state::{AppStateManager, StateEvent};
+    use crate::packets::encoder::{self, Pkt};
+    use blueprotobuf_lib::blueprotobuf::SyncDamageInfo;
+    use bytes::Bytes;
+    use prost::Message;
+    use std::sync::Arc;
+
+    #[test]
+    fn records_events_in_order() {
+        let sink = RecordingSink::new();
+        assert!(emit(&sink, "pause-encounter", true));
+        assert!(emit(&sink, "reset-encounter", ""));
+
+        assert_eq!(
+            sink.payloads("pause-encounter"),
+            vec![serde_json::json!(true)]
+        );
+        let names: Vec<String> = sink.take().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["pause-encounter", "reset-encounter"]);
+        assert!(sink.take().is_empty());
+    }
+
+    #[test]
+    fn live_state_emits_into_any_sink() {
+        let sink = Arc::new(RecordingSink::new());
+        let (manager, mut state) = AppStateManager::with_event_sink(sink.clone());
+
+        let player = (7 << 16) | 640;
+        let monster = (9 << 16) | 64;
+        let hit = SyncDamageInfo {
+            attacker_uuid: Some(player),
+            value: Some(1_000),
+            owner_id: Some(1),
+            ..Default::default()
+        };
+        let delta = encoder::near_delta_damage(monster, vec![hit]);
+        let event = StateEvent::Message {
+            pkt: Pkt::SyncNearDeltaInfo,
+            data: Bytes::from(delta.encode_to_vec()),
+        };
+
+        tauri::async_runtime::block_on(async {
+            manager
+                .handle_events_batch_with_state(&mut state, vec![event])
+                .await;
+            manager.update_and_emit_events_with_state(&mut state).await;
+        });
+
+        let live_data = sink.payloads("live-data");
+        assert_eq!(live_data.len(), 1);
+        assert_eq!(live_data[0]["totalDmg"], 1_000);
+    }
+}
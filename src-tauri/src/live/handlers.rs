@@ -0,0 +1,251 @@
+//! Registry of the game messages the live state consumes.
+//!
+//! Every [`Pkt`] the meter reacts to has one entry in [`HANDLERS`] naming its
+//! prost message type and the state update it drives. Supporting a new message
+//! means adding its opcode to `opcodes.rs` and a handler here; the live loop,
+//! the headless analyzer and the state manager pick it up from the registry.
+
+use crate::live::state::{AppState, AppStateManager, StateEvent};
+use crate::packets::channel::decode_capture_time;
+use crate::packets::opcodes::Pkt;
+use blueprotobuf_lib::blueprotobuf;
+use bytes::Bytes;
+use log::{info, trace};
+use prost::Message;
+
+/// Decodes one message type and applies it to the live state.
+pub trait MessageHandler: Send + Sync {
+    /// The opcode this handler consumes.
+    fn pkt(&self) -> Pkt;
+
+    /// Whether the message is dropped while the encounter is paused.
+    fn pausable(&self) -> bool;
+
+    /// Decodes `data` and applies it to `state`.
+    fn handle(
+        &self,
+        manager: &AppStateManager,
+        state: &mut AppState,
+        data: Bytes,
+    ) -> Result<(), prost::DecodeError>;
+}
+
+/// A [`MessageHandler`] for the prost message `M`.
+struct Handler<M> {
+    pkt: Pkt,
+    pausable: bool,
+    apply: fn(&AppStateManager, &mut AppState, M),
+}
+
+impl<M> Handler<M> {
+    const fn new(pkt: Pkt, apply: fn(&AppStateManager, &mut AppState, M)) -> Self {
+        Self {
+            pkt,
+            pausable: false,
+            apply,
+        }
+    }
+
+    /// Marks combat data that must not reach a paused encounter.
+    const fn dropped_while_paused(mut self) -> Self {
+        self.pausable = true;
+        self
+    }
+}
+
+impl<M: Message + Default> MessageHandler for Handler<M> {
+    fn pkt(&self) -> Pkt {
+        self.pkt
+    }
+
+    fn pausable(&self) -> bool {
+        self.pausable
+    }
+
+    fn handle(
+        &self,
+        manager: &AppStateManager,
+        state: &mut AppState,
+        data: Bytes,
+    ) -> Result<(), prost::DecodeError> {
+        let message = M::decode(data)?;
+        (self.apply)(manager, state, message);
+        Ok(())
+    }
+}
+
+/// Every message the live state consumes.
+///
+/// `SyncSceneAttrs` is deliberately absent: its handling is disabled to
+/// possibly remedy a crashing bug.
+static HANDLERS: &[&dyn MessageHandler] = &[
+    &Handler::<blueprotobuf::EnterScene>::new(Pkt::EnterScene, |manager, state, data| {
+        manager.process_enter_scene(state, data)
+    }),
+    &Handler::<blueprotobuf::SyncNearEntities>::new(
+        Pkt::SyncNearEntities,
+        |manager, state, data| manager.process_sync_near_entities(state, data),
+    )
+    .dropped_while_paused(),
+    &Handler::<blueprotobuf::SyncContainerData>::new(
+        Pkt::SyncContainerData,
+        |manager, state, data| {
+            // store local_player copy
+            state.encounter.local_player = data.clone();
+            manager.process_sync_container_data(state, data)
+        },
+    )
+    .dropped_while_paused(),
+    &Handler::<blueprotobuf::SyncContainerDirtyData>::new(
+        Pkt::SyncContainerDirtyData,
+        |manager, state, data| manager.process_sync_container_dirty_data(state, data),
+    )
+    .dropped_while_paused(),
+    // todo: this is skipped, not sure what info it has
+    &Handler::<blueprotobuf::SyncServerTime>::new(Pkt::SyncServerTime, |_, _, _| {}),
+    &Handler::<blueprotobuf::SyncDungeonData>::new(Pkt::SyncDungeonData, on_sync_dungeon_data),
+    &Handler::<blueprotobuf::SyncDungeonDirtyData>::new(
+        Pkt::SyncDungeonDirtyData,
+        on_sync_dungeon_dirty_data,
+    ),
+    &Handler::<blueprotobuf::SyncToMeDeltaInfo>::new(
+        Pkt::SyncToMeDeltaInfo,
+        |manager, state, data| {
+            manager.process_sync_to_me_delta_info(state, data);
+            manager.apply_battle_state_resets_if_needed(state);
+        },
+    )
+    .dropped_while_paused(),
+    &Handler::<blueprotobuf::SyncNearDeltaInfo>::new(
+        Pkt::SyncNearDeltaInfo,
+        |manager, state, data| manager.process_sync_near_delta_info(state, data),
+    )
+    .dropped_while_paused(),
+    &Handler::<blueprotobuf::NotifyReviveUser>::new(
+        Pkt::NotifyReviveUser,
+        |manager, state, data| manager.process_notify_revive_user(state, data),
+    ),
+    &Handler::<blueprotobuf::SyncClientUseSkill>::new(
+        Pkt::SyncClientUseSkill,
+        |manager, state, data| manager.process_sync_client_use_skill(state, data),
+    )
+    .dropped_while_paused(),
+    &Handler::<blueprotobuf::GetSocialDataReply>::new(
+        Pkt::GetSocialDataReply,
+        |manager, state, data| manager.process_get_social_data_reply(state, data),
+    ),
+    &Handler::<blueprotobuf::BuffInfoSync>::new(Pkt::BuffInfoSync, |manager, state, data| {
+        manager.process_buff_info_sync(state, data)
+    })
+    .dropped_while_paused(),
+];
+
+/// Returns the handler registered for `pkt`.
+pub fn handler(pkt: Pkt) -> Option<&'static dyn MessageHandler> {
+    HANDLERS
+        .iter()
+        .copied()
+        .find(|handler| handler.pkt() == pkt)
+}
+
+/// Wraps a received message into a [`StateEvent`].
+///
+/// Returns `None` for messages the live state does not consume. The payload is
+/// decoded when the event is applied.
+pub fn state_event(pkt: Pkt, data: Bytes) -> Option<StateEvent> {
+    match pkt {
+        Pkt::ServerChangeInfo => Some(StateEvent::ServerChange),
+        Pkt::DataLoss => Some(StateEvent::DataLoss),
+        Pkt::CaptureTime => decode_capture_time(&data)
+            .and_then(|timestamp_ms| i64::try_from(timestamp_ms).ok())
+            .map(StateEvent::CaptureTime),
+        _ if handler(pkt).is_some() => Some(StateEvent::Message { pkt, data }),
+        _ => {
+            trace!("Unhandled packet opcode: {pkt:?}");
+            None
+        }
+    }
+}
+
+fn on_sync_dungeon_data(
+    manager: &AppStateManager,
+    state: &mut AppState,
+    data: blueprotobuf::SyncDungeonData,
+) {
+    let v_data = data.v_data.as_ref();
+    info!(
+        target: "app::live",
+        "Decoded SyncDungeonData (has_flow_info={}, target_entries={})",
+        v_data.and_then(|d| d.flow_info.as_ref()).is_some(),
+        v_data
+            .and_then(|d| d.target.as_ref())
+            .map(|t| t.target_data.len())
+            .unwrap_or(0)
+    );
+    manager.process_sync_dungeon_data(state, data);
+    manager.apply_battle_state_resets_if_needed(state);
+}
+
+fn on_sync_dungeon_dirty_data(
+    manager: &AppStateManager,
+    state: &mut AppState,
+    data: blueprotobuf::SyncDungeonDirtyData,
+) {
+    info!(
+        target: "app::live",
+        "Decoded SyncDungeonDirtyData (buffer_len={})",
+        data.v_data
+            .as_ref()
+            .and_then(|s| s.buffer.as_ref())
+            .map(|b| b.len())
+            .unwrap_or(0)
+    );
+    manager.process_sync_dungeon_dirty_data(state, data);
+    manager.apply_battle_state_resets_if_needed(state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HANDLERS, handler, state_event};
+    use crate::live::state::StateEvent;
+    use crate::packets::opcodes::Pkt;
+    use bytes::Bytes;
+    use std::collections::HashSet;
+
+    #[test]
+    fn each_opcode_has_at_most_one_handler() {
+        let mut seen = HashSet::new();
+        for entry in HANDLERS {
+            assert!(
+                seen.insert(entry.pkt()),
+                "{:?} registered twice",
+                entry.pkt()
+            );
+        }
+    }
+
+    #[test]
+    fn only_registered_messages_become_events() {
+        assert!(handler(Pkt::SyncNearDeltaInfo).is_some_and(|h| h.pausable()));
+        assert!(handler(Pkt::EnterScene).is_some_and(|h| !h.pausable()));
+        assert!(matches!(
+            state_event(Pkt::SyncNearDeltaInfo, Bytes::new()),
+            Some(StateEvent::Message {
+                pkt: Pkt::SyncNearDeltaInfo,
+                ..
+            })
+        ));
+        assert!(matches!(
+            state_event(Pkt::ServerChangeInfo, Bytes::new()),
+            Some(StateEvent::ServerChange)
+        ));
+        assert!(matches!(
+            state_event(
+                Pkt::CaptureTime,
+                Bytes::copy_from_slice(&1_500u64.to_le_bytes())
+            ),
+            Some(StateEvent::CaptureTime(1_500))
+        ));
+        assert!(state_event(Pkt::NotifyTimerList, Bytes::new()).is_none());
+    }
+}
@@ -0,0 +1,323 @@
+//! Runs a capture or session file through the live pipeline without a window.
+//!
+//! Packets go through the same capture, decode and state code as the live
+//! meter; encounters are collected as they close instead of being saved to
+//! the database or emitted to the frontend. [`generate_load`] feeds the same
+//! decode and state code with synthetic traffic at a fixed message rate.
+
+use crate::live::commands_models::{HeaderInfo, HistoryEntityData, build_history_entities};
+use crate::live::dungeon_log::Segment;
+use crate::live::event_manager::generate_live_data_payload;
+use crate::live::handlers;
+use crate::live::state::{AppState, AppStateManager, CompletedEncounter, StateEvent};
+use crate::packets::channel::{self, CAPTURE_CHANNEL_CAPACITY, OverflowPolicy};
+use crate::packets::encoder::{self, Pkt};
+use crate::packets::packet_capture::{self, CaptureMethod, ReplayTiming};
+use crate::packets::packet_process::process_packet;
+use crate::packets::rpc::RpcTracker;
+use crate::packets::session::SESSION_FILE_EXTENSION;
+use crate::packets::stats::{self, MessageStats};
+use crate::packets::wire::{MissingField, SchemaDiff};
+use blueprotobuf_lib::blueprotobuf::SyncDamageInfo;
+use bytes::Bytes;
+use log::info;
+use serde::Serialize;
+use std::path::Path;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Events applied per state batch; the live snapshot is republished once per batch.
+const EVENT_BATCH_SIZE: usize = 256;
+
+/// One finished encounter as printed by the analyzer.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EncounterReport {
+    pub header: HeaderInfo,
+    pub started_at_ms: i64,
+    pub ended_at_ms: Option<i64>,
+    pub is_manually_reset: bool,
+    /// Capture data was lost during the encounter.
+    pub is_incomplete: bool,
+    pub player_names: Vec<String>,
+    pub entities: Vec<HistoryEntityData>,
+    /// Dungeon segments that overlap the encounter.
+    pub segments: Vec<Segment>,
+}
+
+impl From<CompletedEncounter> for EncounterReport {
+    fn from(completed: CompletedEncounter) -> Self {
+        let encounter = &completed.encounter;
+        let payload = generate_live_data_payload(encounter, None, None);
+        let elapsed_secs = payload.elapsed_ms as f64 / 1000.0;
+        let total_dps = if elapsed_secs > 0.0 {
+            payload.total_dmg as f64 / elapsed_secs
+        } else {
+            0.0
+        };
+        // Sorted so reports from different runs diff cleanly.
+        let mut bosses = payload.bosses;
+        bosses.sort_by_key(|boss| boss.uid);
+        let header = HeaderInfo {
+            total_dps,
+            total_dmg: payload.total_dmg,
+            elapsed_ms: payload.elapsed_ms,
+            fight_start_timestamp_ms: payload.fight_start_timestamp_ms,
+            bosses,
+            scene_id: payload.scene_id,
+            scene_name: payload.scene_name,
+            current_segment_type: None,
+            current_segment_name: None,
+        };
+
+        let started_at_ms = completed.metadata.started_at_ms;
+        let segments = completed
+            .dungeon_log
+            .map(|log| {
+                log.segments
+                    .into_iter()
+                    .filter(|segment| segment.ended_at_ms.is_none_or(|end| end >= started_at_ms))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            header,
+            started_at_ms,
+            ended_at_ms: completed.metadata.ended_at_ms,
+            is_manually_reset: completed.metadata.is_manually_reset,
+            is_incomplete: completed.metadata.is_incomplete,
+            player_names: completed.metadata.player_names,
+            entities: build_history_entities(&encounter.entity_uid_to_entity),
+            segments,
+        }
+    }
+}
+
+/// Returns the replay method for `path`, picked from its extension.
+///
+/// `.rlsession` files are replayed as recorded sessions; anything else is read
+/// as pcap/pcapng.
+pub fn capture_method_for_path(path: &Path) -> CaptureMethod {
+    let is_session = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(SESSION_FILE_EXTENSION));
+    if is_session {
+        CaptureMethod::SessionFile(path.to_path_buf(), ReplayTiming::Fast)
+    } else {
+        CaptureMethod::PcapFile(path.to_path_buf(), ReplayTiming::Fast)
+    }
+}
+
+/// Replays `path` as fast as possible and returns every encounter it contains.
+///
+/// The encounter still open at the end of the file is closed as if the meter
+/// had been reset.
+///
+/// # Arguments
+///
+/// * `path` - A pcap/pcapng capture or a recorded `.rlsession` file.
+///
+/// # Returns
+///
+/// * `Result<Vec<EncounterReport>, String>` - The encounters in the order they finished.
+pub fn analyze_file(path: &Path) -> Result<Vec<EncounterReport>, String> {
+    if !path.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    let method = capture_method_for_path(path);
+    tauri::async_runtime::block_on(analyze(method))
+}
+
+/// Replays `path` and diffs every message that has a prost type against it.
+///
+/// # Arguments
+///
+/// * `path` - A pcap/pcapng capture or a recorded `.rlsession` file.
+///
+/// # Returns
+///
+/// * `Result<Vec<MissingField>, String>` - Fields seen on the wire but missing
+///   from the schema, ordered by path.
+pub fn schema_diff_file(path: &Path) -> Result<Vec<MissingField>, String> {
+    if !path.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    let (mut rx, replay) = packet_capture::replay_file(capture_method_for_path(path));
+    let mut diff = SchemaDiff::new();
+    let mut decode_failures = 0u64;
+    while let Some((op, data)) = rx.blocking_recv() {
+        if diff.add(op, &data).is_err() {
+            decode_failures += 1;
+        }
+    }
+    replay_result(replay)?;
+    info!(
+        target: "app::live",
+        "schema_diff_done path={} decode_failures={}",
+        path.display(),
+        decode_failures
+    );
+    Ok(diff.missing_fields())
+}
+
+/// Waits for a finished replay and returns why it stopped early, if it did.
+fn replay_result(replay: JoinHandle<Result<(), String>>) -> Result<(), String> {
+    replay
+        .join()
+        .map_err(|_| "replay thread panicked".to_string())?
+}
+
+async fn analyze(method: CaptureMethod) -> Result<Vec<EncounterReport>, String> {
+    let state_manager = AppStateManager::headless();
+    let mut state = AppState::headless();
+    let (mut rx, replay) = packet_capture::replay_file(method);
+
+    let mut messages = 0u64;
+    let mut batch = Vec::with_capacity(EVENT_BATCH_SIZE);
+    while let Some((op, data)) = rx.recv().await {
+        messages += 1;
+        if let Some(event) = handlers::state_event(op, data) {
+            batch.push(event);
+        }
+        if batch.len() >= EVENT_BATCH_SIZE {
+            state_manager
+                .handle_events_batch_with_state(&mut state, std::mem::take(&mut batch))
+                .await;
+        }
+    }
+    // The channel has closed, so the replay thread is done.
+    replay_result(replay)?;
+    batch.push(StateEvent::ResetEncounter { is_manual: false });
+    state_manager
+        .handle_events_batch_with_state(&mut state, batch)
+        .await;
+
+    let completed = state.completed_encounters.take().unwrap_or_default();
+    info!(
+        target: "app::live",
+        "headless_replay_done messages={} encounters={}",
+        messages,
+        completed.len()
+    );
+    Ok(completed.into_iter().map(EncounterReport::from).collect())
+}
+
+/// Settings for [`generate_load`].
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// Damage messages sent per second.
+    pub rate: u32,
+    /// How long messages are sent for.
+    pub duration: Duration,
+    /// Players hitting the target; every message carries one hit per player.
+    pub players: u32,
+}
+
+/// Outcome of a [`generate_load`] run.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadReport {
+    pub target_rate: u32,
+    /// Messages applied to the live state.
+    pub messages: u64,
+    pub elapsed_ms: u64,
+    /// Messages applied per second; below `target_rate` when the state fell behind.
+    pub achieved_rate: f64,
+    /// Message counts and handler times per opcode, from the capture stats.
+    pub handlers: Vec<MessageStats>,
+}
+
+/// Sends `SyncNearDeltaInfo` damage frames through the frame decoder and the
+/// live state at `options.rate` messages per second.
+///
+/// The capture queue blocks when full, so a state that cannot keep up slows
+/// the sender down and shows as an achieved rate below the target.
+///
+/// # Arguments
+///
+/// * `options` - Message rate, duration and players per message.
+///
+/// # Returns
+///
+/// * `Result<LoadReport, String>` - Throughput and handler times of the run.
+pub fn generate_load(options: &LoadOptions) -> Result<LoadReport, String> {
+    if options.rate == 0 {
+        return Err("rate must be positive".to_string());
+    }
+    tauri::async_runtime::block_on(run_load(options.clone()))
+}
+
+async fn run_load(options: LoadOptions) -> Result<LoadReport, String> {
+    const TARGET_UUID: i64 = (3_000_001 << 16) | 64;
+
+    let state_manager = AppStateManager::headless();
+    let mut state = AppState::headless();
+    let (packet_sender, mut rx, _queue_depth) =
+        channel::channel(OverflowPolicy::Block, CAPTURE_CHANNEL_CAPACITY);
+
+    let total = u64::from(options.rate) * options.duration.as_millis() as u64 / 1000;
+    let damages: Vec<SyncDamageInfo> = (0..i64::from(options.players.max(1)))
+        .map(|player| SyncDamageInfo {
+            attacker_uuid: Some(((1_000_001 + player) << 16) | 640),
+            value: Some(10_000),
+            owner_id: Some(1),
+            ..Default::default()
+        })
+        .collect();
+    let message = encoder::near_delta_damage(TARGET_UUID, damages);
+    let frame = Bytes::from(encoder::frame_down(
+        &encoder::notify(Pkt::SyncNearDeltaInfo, &message, false),
+        false,
+    ));
+
+    let started = Instant::now();
+    let sender = std::thread::spawn(move || {
+        let mut rpc = RpcTracker::new();
+        let interval_ns = 1_000_000_000 / u64::from(options.rate);
+        for sent in 0..total {
+            let due = started + Duration::from_nanos(sent * interval_ns);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+            process_packet(&frame, &mut rpc, &packet_sender);
+        }
+    });
+
+    let mut messages = 0u64;
+    let mut batch = Vec::with_capacity(EVENT_BATCH_SIZE);
+    while let Some((op, data)) = rx.recv().await {
+        messages += 1;
+        if let Some(event) = handlers::state_event(op, data) {
+            batch.push(event);
+        }
+        // Apply what is queued right away, as the live loop does.
+        if batch.len() >= EVENT_BATCH_SIZE || rx.is_empty() {
+            state_manager
+                .handle_events_batch_with_state(&mut state, std::mem::take(&mut batch))
+                .await;
+        }
+    }
+    state_manager
+        .handle_events_batch_with_state(&mut state, batch)
+        .await;
+    let elapsed = started.elapsed();
+    sender
+        .join()
+        .map_err(|_| "load sender thread panicked".to_string())?;
+
+    state.handler_times.publish();
+    let handlers = stats::snapshot()
+        .messages
+        .into_iter()
+        .filter(|message| message.handled > 0)
+        .collect();
+    Ok(LoadReport {
+        target_rate: options.rate,
+        messages,
+        elapsed_ms: elapsed.as_millis() as u64,
+        achieved_rate: messages as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        handlers,
+    })
+}
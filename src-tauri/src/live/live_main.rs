@@ -1,20 +1,25 @@
+use crate::live::clock::Clock;
+use crate::live::handlers;
+use crate::live::ttk;
 use crate::live::state::{AppState, AppStateManager, StateEvent};
 use crate::packets;
-use blueprotobuf_lib::blueprotobuf;
-use bytes::Bytes;
-use log::{debug, info, trace, warn};
-use prost::Message;
+use crate::packets::channel::{CAPTURE_CHANNEL_CAPACITY, OverflowPolicy};
+use log::{info, warn};
 use std::sync::atomic::Ordering;
 use std::time::{Duration, Instant};
 use tauri::{AppHandle, Manager};
 
-const QUEUE_DEPTH_WARN_THRESHOLD: usize = 100;
-const QUEUE_DEPTH_ERROR_THRESHOLD: usize = 500;
-const QUEUE_DEPTH_CRITICAL_THRESHOLD: usize = 2000;
+// Fractions of the capture queue; the critical level is where `DropLowValue` starts shedding.
+const QUEUE_DEPTH_WARN_THRESHOLD: usize = CAPTURE_CHANNEL_CAPACITY / 4;
+const QUEUE_DEPTH_ERROR_THRESHOLD: usize = CAPTURE_CHANNEL_CAPACITY / 2;
+const QUEUE_DEPTH_CRITICAL_THRESHOLD: usize =
+    CAPTURE_CHANNEL_CAPACITY - CAPTURE_CHANNEL_CAPACITY / 4;
 const QUEUE_DEPTH_LOG_INTERVAL: Duration = Duration::from_millis(500);
+const CAPTURE_STATS_EMIT_INTERVAL: Duration = Duration::from_secs(2);
 
 fn log_queue_depth_if_needed(
     queue_depth: &std::sync::atomic::AtomicUsize,
+    overflow_policy: OverflowPolicy,
     warn_counter: &mut usize,
     last_log_at: &mut Instant,
 ) {
@@ -29,8 +34,15 @@ fn log_queue_depth_if_needed(
         if *warn_counter % 5 == 1 {
             warn!(
                 target: "app::live",
-                "queue_depth_critical depth={} - consumer severely behind, risk of OOM",
-                current
+                "queue_depth_critical depth={} capacity={} overflow_policy={:?} - consumer severely behind, {}",
+                current,
+                CAPTURE_CHANNEL_CAPACITY,
+                overflow_policy,
+                match overflow_policy {
+                    OverflowPolicy::Block => "capture is waiting for room",
+                    OverflowPolicy::DropLowValue => "dropping low-value messages",
+                    OverflowPolicy::Coalesce => "snapshots are coalesced once the queue is full",
+                }
             );
         }
     } else if current >= QUEUE_DEPTH_ERROR_THRESHOLD {
@@ -38,8 +50,10 @@ fn log_queue_depth_if_needed(
         if *warn_counter % 3 == 1 {
             warn!(
                 target: "app::live",
-                "queue_depth_high depth={} - consumer significantly behind",
-                current
+                "queue_depth_high depth={} capacity={} overflow_policy={:?} - consumer significantly behind",
+                current,
+                CAPTURE_CHANNEL_CAPACITY,
+                overflow_policy
             );
         }
     } else if current >= QUEUE_DEPTH_WARN_THRESHOLD {
@@ -47,8 +61,10 @@ fn log_queue_depth_if_needed(
         if *warn_counter % 2 == 1 {
             warn!(
                 target: "app::live",
-                "queue_depth_elevated depth={} - consumer falling behind",
-                current
+                "queue_depth_elevated depth={} capacity={} overflow_policy={:?} - consumer falling behind",
+                current,
+                CAPTURE_CHANNEL_CAPACITY,
+                overflow_policy
             );
         }
     } else {
@@ -75,7 +91,6 @@ pub async fn start(app_handle: AppHandle) {
     // Get the state manager from app state
     let state_manager = app_handle.state::<AppStateManager>().inner().clone();
     let mut state = AppState::new(app_handle.clone());
-    state.event_manager.initialize(app_handle.clone());
     state_manager.publish_snapshot_from_state(&state);
 
     // Throttling for events - rate is read dynamically from state each iteration
@@ -86,189 +101,77 @@ pub async fn start(app_handle: AppHandle) {
     let heartbeat_duration = Duration::from_secs(2);
 
     // 1. Start capturing packets and send to rx
-    let method = get_capture_method(&app_handle);
-    let (mut rx, queue_depth) = packets::packet_capture::start_capture(method);
+    let detection_rules = packets::detection::override_path(&app_handle);
+    if let Err(e) = packets::detection::reload(detection_rules.as_deref()) {
+        warn!(
+            target: "app::capture",
+            "server_detection_rules_load_failed err={}; using bundled rules",
+            e
+        );
+    }
+    let rpc_methods = packets::rpc::override_path(&app_handle);
+    if let Err(e) = packets::rpc::reload(rpc_methods.as_deref()) {
+        warn!(
+            target: "app::capture",
+            "rpc_routes_load_failed err={}; using bundled method ids",
+            e
+        );
+    }
+    let enrage_table = ttk::override_path(&app_handle);
+    if let Err(e) = ttk::reload(enrage_table.as_deref()) {
+        warn!(
+            target: "app::live",
+            "enrage_table_load_failed err={}; using bundled enrage timers",
+            e
+        );
+    }
+    let (method, capture_options) = get_capture_config(&app_handle);
+    if method.is_replay() {
+        // Stamp the replayed fight with its capture time rather than the replay's.
+        state.clock = Clock::replay();
+    }
+    let overflow_policy = capture_options.overflow_policy;
+    info!(
+        target: "app::live",
+        "queue_depth_thresholds elevated={} high={} critical={} capacity={} overflow_policy={:?}",
+        QUEUE_DEPTH_WARN_THRESHOLD,
+        QUEUE_DEPTH_ERROR_THRESHOLD,
+        QUEUE_DEPTH_CRITICAL_THRESHOLD,
+        CAPTURE_CHANNEL_CAPACITY,
+        overflow_policy
+    );
+    let (mut rx, queue_depth) = packets::packet_capture::start_capture(method, capture_options);
     let mut queue_depth_warn_counter = 0usize;
     let mut queue_depth_last_log_at = Instant::now();
+    let mut last_capture_stats_emit = Instant::now();
 
     // 2. Use the channel to receive packets back and process them
     loop {
         log_queue_depth_if_needed(
             queue_depth.as_ref(),
+            overflow_policy,
             &mut queue_depth_warn_counter,
             &mut queue_depth_last_log_at,
         );
+        if last_capture_stats_emit.elapsed() >= CAPTURE_STATS_EMIT_INTERVAL {
+            last_capture_stats_emit = Instant::now();
+            state.handler_times.publish();
+            state
+                .event_manager
+                .emit_capture_stats(packets::stats::snapshot());
+        }
         state_manager.apply_pending_control_commands(&mut state).await;
 
         // Use tokio::time::timeout to ensure we emit periodically even if no packets arrive
         let packet_result = tokio::time::timeout(heartbeat_duration, rx.recv()).await;
 
-        // Helper to decode op/data into a StateEvent; returns None if decoding failed
-        let decode_event = |op: packets::opcodes::Pkt, data: Bytes| -> Option<StateEvent> {
-            match op {
-                packets::opcodes::Pkt::ServerChangeInfo => Some(StateEvent::ServerChange),
-                packets::opcodes::Pkt::EnterScene => {
-                    info!(target: "app::live", "Received EnterScene packet");
-                    match blueprotobuf::EnterScene::decode(data) {
-                        Ok(v) => Some(StateEvent::EnterScene(v)),
-                        Err(e) => {
-                            warn!("Error decoding EnterScene.. ignoring: {e}");
-                            None
-                        }
-                    }
-                }
-                packets::opcodes::Pkt::SyncNearEntities => {
-                    match blueprotobuf::SyncNearEntities::decode(data) {
-                        Ok(v) => Some(StateEvent::SyncNearEntities(v)),
-                        Err(e) => {
-                            warn!("Error decoding SyncNearEntities.. ignoring: {e}");
-                            None
-                        }
-                    }
-                }
-                packets::opcodes::Pkt::SyncContainerData => {
-                    match blueprotobuf::SyncContainerData::decode(data) {
-                        Ok(v) => Some(StateEvent::SyncContainerData(v)),
-                        Err(e) => {
-                            warn!("Error decoding SyncContainerData.. ignoring: {e}");
-                            None
-                        }
-                    }
-                }
-                packets::opcodes::Pkt::SyncContainerDirtyData => {
-                    match blueprotobuf::SyncContainerDirtyData::decode(data) {
-                        Ok(v) => Some(StateEvent::SyncContainerDirtyData(v)),
-                        Err(e) => {
-                            warn!("Error decoding SyncContainerDirtyData.. ignoring: {e}");
-                            None
-                        }
-                    }
-                }
-                packets::opcodes::Pkt::SyncServerTime => {
-                    match blueprotobuf::SyncServerTime::decode(data) {
-                        Ok(v) => Some(StateEvent::SyncServerTime(v)),
-                        Err(e) => {
-                            warn!("Error decoding SyncServerTime.. ignoring: {e}");
-                            None
-                        }
-                    }
-                }
-                packets::opcodes::Pkt::SyncDungeonData => {
-                    info!(target: "app::live", "Received SyncDungeonData packet");
-                    match blueprotobuf::SyncDungeonData::decode(data) {
-                        Ok(v) => {
-                            let has_flow = v
-                                .v_data
-                                .as_ref()
-                                .and_then(|d| d.flow_info.as_ref())
-                                .is_some();
-                            let target_count = v
-                                .v_data
-                                .as_ref()
-                                .and_then(|d| d.target.as_ref())
-                                .map(|t| t.target_data.len())
-                                .unwrap_or(0);
-                            info!(
-                                target: "app::live",
-                                "Decoded SyncDungeonData (has_flow_info={}, target_entries={})",
-                                has_flow,
-                                target_count
-                            );
-                            Some(StateEvent::SyncDungeonData(v))
-                        }
-                        Err(e) => {
-                            warn!("Error decoding SyncDungeonData.. ignoring: {e}");
-                            None
-                        }
-                    }
-                }
-                packets::opcodes::Pkt::SyncDungeonDirtyData => {
-                    info!(target: "app::live", "Received SyncDungeonDirtyData packet");
-                    match blueprotobuf::SyncDungeonDirtyData::decode(data) {
-                        Ok(v) => {
-                            let buffer_len = v
-                                .v_data
-                                .as_ref()
-                                .and_then(|s| s.buffer.as_ref())
-                                .map(|b| b.len())
-                                .unwrap_or(0);
-                            info!(
-                                target: "app::live",
-                                "Decoded SyncDungeonDirtyData (buffer_len={})",
-                                buffer_len
-                            );
-                            Some(StateEvent::SyncDungeonDirtyData(v))
-                        }
-                        Err(e) => {
-                            warn!("Error decoding SyncDungeonDirtyData.. ignoring: {e}");
-                            None
-                        }
-                    }
-                }
-                packets::opcodes::Pkt::SyncToMeDeltaInfo => {
-                    match blueprotobuf::SyncToMeDeltaInfo::decode(data) {
-                        Ok(v) => Some(StateEvent::SyncToMeDeltaInfo(v)),
-                        Err(e) => {
-                            warn!("Error decoding SyncToMeDeltaInfo.. ignoring: {e}");
-                            None
-                        }
-                    }
-                }
-                packets::opcodes::Pkt::SyncNearDeltaInfo => {
-                    match blueprotobuf::SyncNearDeltaInfo::decode(data) {
-                        Ok(v) => Some(StateEvent::SyncNearDeltaInfo(v)),
-                        Err(e) => {
-                            warn!("Error decoding SyncNearDeltaInfo.. ignoring: {e}");
-                            None
-                        }
-                    }
-                }
-                packets::opcodes::Pkt::NotifyReviveUser => {
-                    match blueprotobuf::NotifyReviveUser::decode(data) {
-                        Ok(v) => Some(StateEvent::NotifyReviveUser(v)),
-                        Err(e) => {
-                            warn!("Error decoding NotifyReviveUser.. ignoring: {e}");
-                            None
-                        }
-                    }
-                }
-                packets::opcodes::Pkt::BuffInfoSync => {
-                    match blueprotobuf::BuffInfoSync::decode(data) {
-                        Ok(v) => {
-                            // Dump the packet as JSON for debugging
-                            match serde_json::to_string_pretty(&v) {
-                                Ok(json) => {
-                                    debug!(target: "app::live", "BuffInfoSync packet received:\n{}", json);
-                                }
-                                Err(e) => {
-                                    debug!(
-                                        target: "app::live",
-                                        "BuffInfoSync packet received (JSON serialization failed: {}): {:?}",
-                                        e, v
-                                    );
-                                }
-                            }
-                            None // Not processed further for now
-                        }
-                        Err(e) => {
-                            warn!("Error decoding BuffInfoSync.. ignoring: {e}");
-                            None
-                        }
-                    }
-                }
-                _ => {
-                    trace!("Unhandled packet opcode: {op:?}");
-                    None
-                }
-            }
-        };
-
         match packet_result {
             Ok(Some((op, data))) => {
                 queue_depth.fetch_sub(1, Ordering::Relaxed);
+                state.record_session_message(op, &data);
                 // Process the first packet immediately (low-latency path)
                 let mut batch_events = Vec::new();
-                if let Some(event) = decode_event(op, data) {
+                if let Some(event) = handlers::state_event(op, data) {
                     batch_events.push(event);
                 }
 
@@ -289,7 +192,8 @@ pub async fn start(app_handle: AppHandle) {
                     match rx.try_recv() {
                         Ok((op, data)) => {
                             queue_depth.fetch_sub(1, Ordering::Relaxed);
-                            if let Some(event) = decode_event(op, data) {
+                            state.record_session_message(op, &data);
+                            if let Some(event) = handlers::state_event(op, data) {
                                 let is_server_change = matches!(event, StateEvent::ServerChange);
                                 batch_events.push(event);
                                 drained += 1;
@@ -351,7 +255,12 @@ pub async fn start(app_handle: AppHandle) {
     }
 }
 
-fn get_capture_method(app: &AppHandle) -> packets::packet_capture::CaptureMethod {
+fn get_capture_config(
+    app: &AppHandle,
+) -> (
+    packets::packet_capture::CaptureMethod,
+    packets::packet_capture::CaptureOptions,
+) {
     use packets::packet_capture::CaptureMethod;
 
     let filename_candidates = ["packetCapture.json", "packetCapture.bin", "packetCapture"];
@@ -373,30 +282,7 @@ fn get_capture_method(app: &AppHandle) -> packets::packet_capture::CaptureMethod
             }
             if let Ok(file) = std::fs::File::open(&path) {
                 if let Ok(json) = serde_json::from_reader::<_, serde_json::Value>(file) {
-                    let method = json
-                        .get("method")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("WinDivert");
-                    let device = json
-                        .get("npcapDevice")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("");
-
-                    info!(
-                        target: "app::capture",
-                        "Packet capture config found at {} (method={}, device={})",
-                        path.display(),
-                        method,
-                        device
-                    );
-
-                    if method == "Npcap" {
-                        info!(target: "app::capture", "Using Npcap capture method device={}", device);
-                        return CaptureMethod::Npcap(device.to_string());
-                    } else {
-                        info!(target: "app::capture", "Using WinDivert capture method (from config)");
-                        return CaptureMethod::WinDivert;
-                    }
+                    return capture_config_from_json(&json, &path);
                 } else {
                     warn!(
                         "Failed to parse packet capture config at {}",
@@ -417,30 +303,7 @@ fn get_capture_method(app: &AppHandle) -> packets::packet_capture::CaptureMethod
                 }
                 if let Ok(file) = std::fs::File::open(&path) {
                     if let Ok(json) = serde_json::from_reader::<_, serde_json::Value>(file) {
-                        let method = json
-                            .get("method")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("WinDivert");
-                        let device = json
-                            .get("npcapDevice")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("");
-
-                        info!(
-                            target: "app::capture",
-                            "Packet capture config found at {} (method={}, device={})",
-                            path.display(),
-                            method,
-                            device
-                        );
-
-                        if method == "Npcap" {
-                            info!(target: "app::capture", "Using Npcap capture method device={}", device);
-                            return CaptureMethod::Npcap(device.to_string());
-                        } else {
-                            info!(target: "app::capture", "Using WinDivert capture method (from config)");
-                            return CaptureMethod::WinDivert;
-                        }
+                        return capture_config_from_json(&json, &path);
                     } else {
                         warn!(
                             "Failed to parse packet capture config at {}",
@@ -455,5 +318,114 @@ fn get_capture_method(app: &AppHandle) -> packets::packet_capture::CaptureMethod
     warn!(target: "app::capture", "No packetCapture config found in app data dirs; falling back to WinDivert");
 
     info!(target: "app::capture", "Using WinDivert capture method (default)");
-    CaptureMethod::WinDivert
+    (CaptureMethod::WinDivert, Default::default())
+}
+
+fn capture_config_from_json(
+    json: &serde_json::Value,
+    path: &std::path::Path,
+) -> (
+    packets::packet_capture::CaptureMethod,
+    packets::packet_capture::CaptureOptions,
+) {
+    let method = capture_method_from_config(json, path);
+    let overflow_policy = match json.get("overflowPolicy").and_then(|v| v.as_str()) {
+        Some(value) => packets::channel::OverflowPolicy::from_config(value).unwrap_or_else(|| {
+            warn!(
+                target: "app::capture",
+                "Unknown overflowPolicy '{}' in {}; using the default",
+                value,
+                path.display()
+            );
+            Default::default()
+        }),
+        None => Default::default(),
+    };
+    let gap_timeout = match json.get("gapTimeoutMs").and_then(|v| v.as_u64()) {
+        Some(ms) => Duration::from_millis(ms),
+        None => packets::utils::DEFAULT_GAP_TIMEOUT,
+    };
+    (
+        method,
+        packets::packet_capture::CaptureOptions {
+            overflow_policy,
+            gap_timeout,
+        },
+    )
+}
+
+fn capture_method_from_config(
+    json: &serde_json::Value,
+    path: &std::path::Path,
+) -> packets::packet_capture::CaptureMethod {
+    use packets::packet_capture::{CaptureMethod, ReplayTiming};
+
+    let method = json
+        .get("method")
+        .and_then(|v| v.as_str())
+        .unwrap_or("WinDivert");
+    let device = json
+        .get("npcapDevice")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    info!(
+        target: "app::capture",
+        "Packet capture config found at {} (method={}, device={})",
+        path.display(),
+        method,
+        device
+    );
+
+    match method {
+        "Npcap" => {
+            info!(target: "app::capture", "Using Npcap capture method device={}", device);
+            CaptureMethod::Npcap(device.to_string())
+        }
+        "AfPacket" => {
+            let device = json
+                .get("afPacketDevice")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let tcp_filter = json
+                .get("afPacketFilter")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
+            info!(
+                target: "app::capture",
+                "Using AF_PACKET capture method device={} tcp_filter={}",
+                device,
+                tcp_filter
+            );
+            CaptureMethod::AfPacket {
+                device: device.to_string(),
+                tcp_filter,
+            }
+        }
+        "PcapFile" | "SessionFile" => {
+            let key = if method == "PcapFile" { "pcapFile" } else { "sessionFile" };
+            let file = json.get(key).and_then(|v| v.as_str()).unwrap_or("");
+            let timing = match json.get("replayTiming").and_then(|v| v.as_str()) {
+                Some("original") => ReplayTiming::Original,
+                _ => ReplayTiming::Fast,
+            };
+            info!(
+                target: "app::capture",
+                "Using {} replay path={} timing={:?}",
+                method,
+                file,
+                timing
+            );
+            let path = std::path::PathBuf::from(file);
+            if method == "PcapFile" {
+                CaptureMethod::PcapFile(path, timing)
+            } else {
+                CaptureMethod::SessionFile(path, timing)
+            }
+        }
+        _ => {
+            info!(target: "app::capture", "Using WinDivert capture method (from config)");
+            CaptureMethod::WinDivert
+        }
+    }
 }
@@ -1,10 +1,14 @@
 pub mod buff_names;
 pub mod cd_calc;
+pub mod clock;
 pub mod commands;
 pub mod commands_models;
 pub mod dungeon_dirty_blob;
 pub mod dungeon_log;
 pub mod event_manager;
+pub mod event_sink;
+pub mod handlers;
+pub mod headless;
 pub mod live_main;
 pub mod opcodes_models;
 pub mod opcodes_process;
@@ -13,3 +17,4 @@ pub mod scene_names;
 pub mod skill_names;
 pub mod skill_monitor_init;
 pub mod state;
+pub mod ttk;
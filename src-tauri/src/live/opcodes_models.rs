@@ -2,7 +2,7 @@ use crate::live::opcodes_models::class::ClassSpec;
 use crate::live::skill_names;
 use blueprotobuf_lib::blueprotobuf::{EEntityType, SyncContainerData};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::LazyLock;
 use tokio::sync::RwLock;
 
@@ -26,9 +26,13 @@ pub struct Encounter {
     // Last recorded revive timestamp per actor (ms) to avoid immediate duplicates.
     pub last_revive_ms: HashMap<i64, u128>,
     // Last recorded death timestamp per actor (ms) used only for deduplicating
-    // DB death inserts. We no longer use death tracking for wipe detection; revives
-    // are tracked for UI purposes while death DB inserts are still written.
+    // death records. We no longer use death tracking for wipe detection; revives
+    // are tracked for UI purposes.
     pub last_death_db_ms: HashMap<i64, u128>,
+    // Times capture data was lost while the current fight was running. A fight
+    // with any loss is flagged as incomplete.
+    #[serde(default)]
+    pub data_loss_events: u32,
 }
 
 // Use an async-aware RwLock so readers don't block the tokio runtime threads.
@@ -346,6 +350,146 @@ pub struct Entity {
     pub dmg_to_target: HashMap<i64, u128>,
     pub skill_dmg_to_target: HashMap<(i64, i64), SkillTargetStats>,
     pub skill_heal_to_target: HashMap<(i64, i64), SkillTargetStats>,
+    /// Skill casts in the order they were seen (local player only, from SyncClientUseSkill).
+    #[serde(default)]
+    pub casts: Vec<SkillCast>,
+    /// Damage, healing and damage taken per [`TIMELINE_BUCKET_MS`] since the fight started.
+    #[serde(default)]
+    pub timeline: Vec<TimelineBucket>,
+    /// Damage to each boss per [`TIMELINE_BUCKET_MS`] since the fight started, keyed by boss uid.
+    #[serde(default)]
+    pub boss_damage_timeline: HashMap<i64, Vec<u128>>,
+    /// Deaths of this player, in the order they happened.
+    #[serde(default)]
+    pub deaths: Vec<DeathRecord>,
+    /// Last hits and heals this player received, for the recap of its next death.
+    #[serde(skip)]
+    pub recent_received: VecDeque<RecapEvent>,
+    /// Buff intervals keyed by buff base id.
+    #[serde(default)]
+    pub buff_uptime: HashMap<i32, BuffUptime>,
+    /// Buffs currently on this entity: buff uuid -> (base id, stacks).
+    #[serde(skip)]
+    pub active_buff_layers: HashMap<i32, (i32, i32)>,
+    /// HP over time (bosses only), at most two samples per [`HP_SAMPLE_INTERVAL_MS`].
+    #[serde(default)]
+    pub hp_samples: Vec<HpSample>,
+}
+
+/// Boss HP changes within this of the sample before the last one replace the last sample
+/// instead of adding one, so a burst of changes keeps its first and latest HP.
+pub const HP_SAMPLE_INTERVAL_MS: i64 = 250;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HpSample {
+    pub timestamp_ms: i64,
+    pub hp: i64,
+    pub max_hp: i64,
+}
+
+/// When an entity had one buff, for every instance of the buff base id together.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuffUptime {
+    /// Spans of constant stacks, in order. The last one is open while the buff is up.
+    pub intervals: Vec<BuffInterval>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuffInterval {
+    pub start_ms: i64,
+    pub end_ms: Option<i64>,
+    // Stacks summed over every instance of the buff
+    pub layers: i32,
+}
+
+impl BuffUptime {
+    /// Ends the open interval at `now_ms` and opens one with `layers` stacks, if any.
+    fn set_layers(&mut self, layers: i32, now_ms: i64) {
+        if let Some(open) = self.intervals.last_mut().filter(|i| i.end_ms.is_none()) {
+            if open.layers == layers {
+                return;
+            }
+            if open.start_ms >= now_ms {
+                // Changed again within the same ms; the previous span never lasted
+                self.intervals.pop();
+            } else {
+                open.end_ms = Some(now_ms);
+            }
+        }
+        if layers > 0 {
+            self.intervals.push(BuffInterval {
+                start_ms: now_ms,
+                end_ms: None,
+                layers,
+            });
+        }
+    }
+
+    /// Time the buff was up within `start_ms..end_ms`, and the sum of stacks times that
+    /// time, both in ms. Open intervals count until `end_ms`.
+    pub fn up_ms_within(&self, start_ms: i64, end_ms: i64) -> (i64, i64) {
+        self.intervals
+            .iter()
+            .fold((0, 0), |(up_ms, layer_ms), interval| {
+                let from = interval.start_ms.max(start_ms);
+                let to = interval.end_ms.unwrap_or(end_ms).min(end_ms);
+                if to > from {
+                    let span = to - from;
+                    (up_ms + span, layer_ms + span * i64::from(interval.layers))
+                } else {
+                    (up_ms, layer_ms)
+                }
+            })
+    }
+}
+
+/// Width of one [`TimelineBucket`] in milliseconds.
+pub const TIMELINE_BUCKET_MS: u128 = 1_000;
+
+/// Longest timeline kept per entity (six hours); later events are not bucketed.
+pub const TIMELINE_MAX_BUCKETS: usize = 6 * 60 * 60;
+
+/// Number of hits and heals received that a death recap keeps.
+pub const DEATH_RECAP_EVENTS: usize = 20;
+
+/// A hit or heal a player received before dying.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecapEvent {
+    pub timestamp_ms: i64,
+    pub source_uid: i64,
+    pub skill_id: i32,
+    // Damage (HP and shield lost) or healing received
+    pub value: u128,
+    pub is_heal: bool,
+    // Current HP before the event, when known
+    pub hp_before: Option<i64>,
+}
+
+/// A player death and what led to it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeathRecord {
+    pub timestamp_ms: i64,
+    pub killer_uid: Option<i64>,
+    pub skill_id: Option<i32>,
+    /// Hits and heals received before the death, oldest first, including the killing blow.
+    pub recent_events: Vec<RecapEvent>,
+}
+
+/// Index of the timeline bucket `timestamp_ms` falls into, or `None` past
+/// [`TIMELINE_MAX_BUCKETS`].
+fn timeline_index(fight_start_ms: u128, timestamp_ms: u128) -> Option<usize> {
+    let elapsed_ms = timestamp_ms.saturating_sub(fight_start_ms);
+    usize::try_from(elapsed_ms / TIMELINE_BUCKET_MS)
+        .ok()
+        .filter(|&index| index < TIMELINE_MAX_BUCKETS)
+}
+
+/// Totals of one entity within one bucket of the fight timeline.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelineBucket {
+    pub damage: u128,
+    pub healing: u128,
+    pub taken: u128,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -371,6 +515,21 @@ pub struct Skill {
     pub hits: u128,
 }
 
+/// A single skill cast. SyncClientUseSkill carries no caster position, so none is recorded.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkillCast {
+    pub timestamp_ms: i64,
+    pub skill_level_id: i32,
+    // Target entity uid, when the cast was aimed at an entity
+    pub target_uid: Option<i64>,
+}
+
+impl SkillCast {
+    pub fn skill_id(&self) -> i32 {
+        self.skill_level_id / 100
+    }
+}
+
 // Monster names mapping (id -> name)
 static MONSTER_NAMES: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
     let data = include_str!("../../meter-data/MonsterName.json");
@@ -411,6 +570,7 @@ impl Encounter {
     ///
     /// Clears:
     /// - encounter totals and timestamps
+    /// - the data loss count
     /// - per-entity combat counters and per-encounter skill maps
     pub fn reset_combat_state(&mut self) {
         // Reset encounter-level combat state
@@ -443,7 +603,30 @@ impl Encounter {
             // Taken
             entity.taken = CombatStats::default();
             entity.skill_uid_to_taken_skill.clear();
+
+            // Casts
+            entity.casts.clear();
+
+            // Timeline
+            entity.timeline.clear();
+            entity.boss_damage_timeline.clear();
+
+            // Deaths
+            entity.deaths.clear();
+            entity.recent_received.clear();
+
+            // Boss HP
+            entity.hp_samples.clear();
+
+            // Buffs: drop finished intervals, keep the ones still running
+            for uptime in entity.buff_uptime.values_mut() {
+                uptime.intervals.retain(|interval| interval.end_ms.is_none());
+            }
+            entity
+                .buff_uptime
+                .retain(|_, uptime| !uptime.intervals.is_empty());
         }
+        self.data_loss_events = 0;
         // Clear any pending player death tracking for a fresh encounter
         self.pending_player_revives.clear();
         self.last_revive_ms.clear();
@@ -672,6 +855,101 @@ impl Entity {
         self.get_attr(AttrType::MaxHp).and_then(|v| v.as_int())
     }
 
+    /// Samples the current HP of a boss at `timestamp_ms`. Other entities, and bosses
+    /// without known HP, are skipped.
+    pub fn record_hp_sample(&mut self, timestamp_ms: i64) {
+        if !self.is_boss() {
+            return;
+        }
+        let (Some(hp), Some(max_hp)) = (self.hp(), self.max_hp()) else {
+            return;
+        };
+        let sample = HpSample {
+            timestamp_ms,
+            hp,
+            max_hp,
+        };
+        let len = self.hp_samples.len();
+        let in_window = len >= 2
+            && timestamp_ms - self.hp_samples[len - 2].timestamp_ms < HP_SAMPLE_INTERVAL_MS;
+        match self.hp_samples.last_mut() {
+            Some(last) if last.hp == hp && last.max_hp == max_hp => {}
+            Some(last) if in_window => *last = sample,
+            _ => self.hp_samples.push(sample),
+        }
+    }
+
+    /// Puts a buff instance on this entity, or updates its stacks, at `now_ms`.
+    pub fn set_buff(&mut self, buff_uuid: i32, base_id: i32, layers: i32, now_ms: i64) {
+        self.active_buff_layers.insert(buff_uuid, (base_id, layers));
+        self.refresh_buff_uptime(base_id, now_ms);
+    }
+
+    /// Changes the stacks of a buff instance already on this entity.
+    pub fn change_buff_layers(&mut self, buff_uuid: i32, layers: i32, now_ms: i64) {
+        if let Some((base_id, _)) = self.active_buff_layers.get(&buff_uuid).copied() {
+            self.set_buff(buff_uuid, base_id, layers, now_ms);
+        }
+    }
+
+    /// Takes a buff instance off this entity at `now_ms`.
+    pub fn remove_buff(&mut self, buff_uuid: i32, now_ms: i64) {
+        if let Some((base_id, _)) = self.active_buff_layers.remove(&buff_uuid) {
+            self.refresh_buff_uptime(base_id, now_ms);
+        }
+    }
+
+    fn refresh_buff_uptime(&mut self, base_id: i32, now_ms: i64) {
+        let layers: i32 = self
+            .active_buff_layers
+            .values()
+            .filter(|(id, _)| *id == base_id)
+            .map(|(_, layers)| (*layers).max(1))
+            .sum();
+        self.buff_uptime
+            .entry(base_id)
+            .or_default()
+            .set_layers(layers, now_ms);
+    }
+
+    /// Remembers a hit or heal received, dropping the oldest past [`DEATH_RECAP_EVENTS`].
+    pub fn push_recap_event(&mut self, event: RecapEvent) {
+        if self.recent_received.len() == DEATH_RECAP_EVENTS {
+            self.recent_received.pop_front();
+        }
+        self.recent_received.push_back(event);
+    }
+
+    /// The timeline bucket `timestamp_ms` falls into, for a fight that started at
+    /// `fight_start_ms`. Returns `None` past [`TIMELINE_MAX_BUCKETS`].
+    pub fn timeline_bucket_mut(
+        &mut self,
+        fight_start_ms: u128,
+        timestamp_ms: u128,
+    ) -> Option<&mut TimelineBucket> {
+        let index = timeline_index(fight_start_ms, timestamp_ms)?;
+        if self.timeline.len() <= index {
+            self.timeline.resize(index + 1, TimelineBucket::default());
+        }
+        self.timeline.get_mut(index)
+    }
+
+    /// Damage to the boss `boss_uid` in the timeline bucket `timestamp_ms` falls into.
+    /// Returns `None` past [`TIMELINE_MAX_BUCKETS`].
+    pub fn boss_damage_bucket_mut(
+        &mut self,
+        boss_uid: i64,
+        fight_start_ms: u128,
+        timestamp_ms: u128,
+    ) -> Option<&mut u128> {
+        let index = timeline_index(fight_start_ms, timestamp_ms)?;
+        let timeline = self.boss_damage_timeline.entry(boss_uid).or_default();
+        if timeline.len() <= index {
+            timeline.resize(index + 1, 0);
+        }
+        timeline.get_mut(index)
+    }
+
     /// Get rank level as i64.
     pub fn rank_level(&self) -> Option<i64> {
         self.get_attr(AttrType::RankLevel).and_then(|v| v.as_int())
@@ -856,6 +1134,15 @@ impl Entity {
         }
     }
 
+    /// Whether this entity did anything in the encounter: dealt, healed or took
+    /// a hit, or cast a skill. Only these entities are saved and shown.
+    pub fn has_combat(&self) -> bool {
+        self.damage.hits > 0
+            || self.healing.hits > 0
+            || self.taken.hits > 0
+            || !self.casts.is_empty()
+    }
+
     /// Determine whether this entity is a boss based on game data categorization.
     /// Uses MONSTER_NAMES_BOSS which contains IDs marked as main_category == "boss"
     /// in the game's quest log data.
@@ -919,6 +1206,91 @@ mod tests {
         assert!(!e.is_boss());
     }
 
+    #[test]
+    fn timeline_buckets_are_relative_to_fight_start() {
+        let mut e = Entity::default();
+        e.timeline_bucket_mut(10_000, 10_400).unwrap().damage += 5;
+        e.timeline_bucket_mut(10_000, 12_999).unwrap().damage += 7;
+        e.timeline_bucket_mut(10_000, 9_000).unwrap().taken += 1;
+
+        assert_eq!(e.timeline.len(), 3);
+        assert_eq!(e.timeline[0].damage, 5);
+        assert_eq!(e.timeline[0].taken, 1);
+        assert_eq!(e.timeline[1], TimelineBucket::default());
+        assert_eq!(e.timeline[2].damage, 7);
+
+        let past_cap = 10_000 + TIMELINE_BUCKET_MS * TIMELINE_MAX_BUCKETS as u128;
+        assert!(e.timeline_bucket_mut(10_000, past_cap).is_none());
+    }
+
+    #[test]
+    fn recap_keeps_only_the_latest_events() {
+        let mut e = Entity::default();
+        for timestamp_ms in 0..(DEATH_RECAP_EVENTS as i64 + 5) {
+            e.push_recap_event(RecapEvent {
+                timestamp_ms,
+                ..Default::default()
+            });
+        }
+        assert_eq!(e.recent_received.len(), DEATH_RECAP_EVENTS);
+        assert_eq!(e.recent_received.front().unwrap().timestamp_ms, 5);
+    }
+
+    #[test]
+    fn buff_uptime_merges_instances_of_a_buff() {
+        let mut e = Entity::default();
+        e.set_buff(1, 500, 1, 1_000);
+        e.set_buff(2, 500, 2, 2_000);
+        e.change_buff_layers(1, 3, 3_000);
+        e.remove_buff(1, 4_000);
+        e.remove_buff(2, 5_000);
+        e.set_buff(3, 500, 1, 8_000);
+
+        let layers: Vec<(i64, Option<i64>, i32)> = e.buff_uptime[&500]
+            .intervals
+            .iter()
+            .map(|i| (i.start_ms, i.end_ms, i.layers))
+            .collect();
+        assert_eq!(
+            layers,
+            [
+                (1_000, Some(2_000), 1),
+                (2_000, Some(3_000), 3),
+                (3_000, Some(4_000), 5),
+                (4_000, Some(5_000), 2),
+                (8_000, None, 1),
+            ]
+        );
+        // 4 s up between 1 s and 5 s, then from 8 s until the window ends at 10 s
+        assert_eq!(
+            e.buff_uptime[&500].up_ms_within(0, 10_000),
+            (6_000, 1_000 + 3_000 + 5_000 + 2_000 + 2_000)
+        );
+    }
+
+    #[test]
+    fn boss_hp_samples_are_coalesced() {
+        let mut e = Entity::default();
+        e.entity_type = EEntityType::EntMonster;
+        e.set_attr(AttrType::MaxHp, AttrValue::Int(1_000));
+        e.set_attr(AttrType::CurrentHp, AttrValue::Int(1_000));
+        e.record_hp_sample(0);
+        assert!(e.hp_samples.is_empty(), "not a boss yet");
+
+        e.set_attr(AttrType::EliteStatus, AttrValue::Int(1));
+        for (timestamp_ms, hp) in [(0, 1_000), (100, 900), (200, 800), (500, 800), (600, 700)] {
+            e.set_attr(AttrType::CurrentHp, AttrValue::Int(hp));
+            e.record_hp_sample(timestamp_ms);
+        }
+        let samples: Vec<(i64, i64)> = e
+            .hp_samples
+            .iter()
+            .map(|sample| (sample.timestamp_ms, sample.hp))
+            .collect();
+        // The pull and the latest HP of each burst are kept
+        assert_eq!(samples, [(0, 1_000), (200, 800), (600, 700)]);
+    }
+
     #[test]
     fn attr_value_float_conversion() {
         let val = AttrValue::Float(3.14);
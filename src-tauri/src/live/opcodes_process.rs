@@ -4,13 +4,18 @@ use crate::live::dungeon_log::{self, BattleStateMachine, DungeonLogRuntime, Enco
 use crate::live::opcodes_models::class::{
     ClassSpec, get_class_id_from_spec, get_class_spec_from_skill_id,
 };
-use crate::live::opcodes_models::{AttrType, AttrValue, Encounter, Entity, Skill, attr_type};
+use crate::live::opcodes_models::{
+    AttrType, AttrValue, DeathRecord, Encounter, Entity, RecapEvent, Skill, SkillCast, attr_type,
+};
 use crate::live::damage_id;
 use blueprotobuf_lib::blueprotobuf;
-use blueprotobuf_lib::blueprotobuf::{Attr, EDamageType, EEntityType};
+use blueprotobuf_lib::blueprotobuf::{
+    Attr, EBuffEffectLogicPbType, EBuffEventType, EDamageType, EEntityType,
+};
 use log::{info, warn};
 use std::collections::HashMap;
 use bytes::Buf;
+use prost::Message;
 use std::default::Default;
 
 /// Parses packed varints from ATTR_FIGHT_RESOURCES (50002) raw data.
@@ -52,9 +57,9 @@ pub fn parse_fight_resources(raw_data: &[u8]) -> Option<Vec<i64>> {
 
     Some(values)
 }
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::Instant;
 
-/// Record a death event into the encounter and enqueue a DB task.
+/// Record a player death, with the hits and heals that led to it, on the player's entity.
 fn record_death(
     encounter: &mut Encounter,
     actor_id: i64,
@@ -63,8 +68,8 @@ fn record_death(
     timestamp_ms: i64,
 ) {
     // Dedupe close-together events for the same actor (2s window) using a
-    // dedicated map for DB death inserts. We no longer use death tracking for
-    // wipe detection/UI; death events are still persisted to the DB.
+    // dedicated map for death records. We no longer use death tracking for
+    // wipe detection/UI; deaths are persisted with the encounter.
     let should_record = match encounter.last_death_db_ms.get(&actor_id) {
         Some(last_ms) => {
             let diff = (timestamp_ms as i128 - *last_ms as i128).abs();
@@ -81,9 +86,25 @@ fn record_death(
         .last_death_db_ms
         .insert(actor_id, timestamp_ms as u128);
 
-    // Enqueue DB task; mark as local player when matching tracked local UID
     let is_local = encounter.local_player_uid == actor_id;
-    let _ = (timestamp_ms, actor_id, killer_id, skill_id, is_local);
+    let Some(entity) = encounter.entity_uid_to_entity.get_mut(&actor_id) else {
+        return;
+    };
+    if entity.entity_type != EEntityType::EntChar {
+        return;
+    }
+
+    entity.deaths.push(DeathRecord {
+        timestamp_ms,
+        killer_uid: killer_id,
+        skill_id,
+        recent_events: entity.recent_received.drain(..).collect(),
+    });
+
+    info!(
+        "Recorded death for UID {} (killer={:?}, skill={:?}, local={})",
+        actor_id, killer_id, skill_id, is_local
+    );
 }
 
 /// Record a revive event into the encounter for UI emission.
@@ -113,6 +134,70 @@ fn record_revive(encounter: &mut Encounter, actor_id: i64, timestamp_ms: i64) {
     info!("Recorded revive for UID {}", actor_id);
 }
 
+/// Applies the buff adds, stack changes and removals of a `BuffEffectSync` to `entity`
+/// for uptime tracking.
+fn process_buff_effect_sync(
+    entity: &mut Entity,
+    buff_effect_sync: blueprotobuf::BuffEffectSync,
+    timestamp_ms: i64,
+) {
+    for buff_effect in buff_effect_sync.buff_effects {
+        let Some(buff_uuid) = buff_effect.buff_uuid else {
+            continue;
+        };
+
+        for logic_effect in buff_effect.logic_effect {
+            let (Some(effect_type), Some(raw)) = (logic_effect.effect_type, logic_effect.raw_data)
+            else {
+                continue;
+            };
+
+            if effect_type == EBuffEffectLogicPbType::BuffEffectAddBuff as i32 {
+                if let Ok(buff_info) = blueprotobuf::BuffInfo::decode(raw.as_slice())
+                    && let Some(base_id) = buff_info.base_id
+                {
+                    let layers = buff_info.layer.unwrap_or(1);
+                    entity.set_buff(buff_uuid, base_id, layers, timestamp_ms);
+                }
+            } else if effect_type == EBuffEffectLogicPbType::BuffEffectBuffChange as i32
+                && let Ok(change_info) = blueprotobuf::BuffChange::decode(raw.as_slice())
+                && let Some(layers) = change_info.layer
+            {
+                entity.change_buff_layers(buff_uuid, layers, timestamp_ms);
+            }
+        }
+
+        if buff_effect.r#type == Some(EBuffEventType::BuffEventRemove as i32) {
+            entity.remove_buff(buff_uuid, timestamp_ms);
+        }
+    }
+}
+
+/// Applies the buffs listed in a `BuffInfoSync` to their entity for uptime tracking.
+///
+/// Buffs missing from the list are left alone; removals arrive as `BuffEffectSync`.
+pub fn process_buff_info_sync(
+    encounter: &mut Encounter,
+    buff_info_sync: blueprotobuf::BuffInfoSync,
+    timestamp_ms: i64,
+) -> Option<()> {
+    let uuid = buff_info_sync.uuid?;
+    let entity = encounter
+        .entity_uid_to_entity
+        .entry(uuid >> 16)
+        .or_insert_with(|| Entity {
+            entity_type: EEntityType::from(uuid),
+            ..Default::default()
+        });
+    for buff_info in buff_info_sync.buff_infos {
+        let (Some(buff_uuid), Some(base_id)) = (buff_info.buff_uuid, buff_info.base_id) else {
+            continue;
+        };
+        entity.set_buff(buff_uuid, base_id, buff_info.layer.unwrap_or(1), timestamp_ms);
+    }
+    Some(())
+}
+
 /// Increment per-entity active damage time used for True DPS calculations.
 /// Adds a small grace window for single hits and ignores long idle gaps.
 fn update_active_damage_time(entity: &mut Entity, timestamp_ms: u128) {
@@ -243,6 +328,21 @@ pub fn on_server_change(encounter: &mut Encounter) {
     encounter.reset_combat_state();
 }
 
+/// Record that capture data was lost. Only a running fight is affected; loss
+/// between fights does not touch any counted numbers.
+pub fn on_data_loss(encounter: &mut Encounter) {
+    if encounter.time_fight_start_ms == 0 {
+        return;
+    }
+    encounter.data_loss_events += 1;
+    warn!(
+        target: "app::live",
+        "encounter_data_loss events={} fight_start_ms={}",
+        encounter.data_loss_events,
+        encounter.time_fight_start_ms
+    );
+}
+
 /// Process a NotifyReviveUser packet: record a revive for the actor.
 ///
 /// This will add a revive entry to the encounter's pending revives for UI emission
@@ -251,15 +351,15 @@ pub fn on_server_change(encounter: &mut Encounter) {
 pub fn process_notify_revive_user(
     encounter: &mut Encounter,
     notify_revive: blueprotobuf::NotifyReviveUser,
+    timestamp_ms: i64,
 ) -> Option<()> {
     let actor_uuid = notify_revive.v_actor_uuid?;
     // Actor UUID in protobuf is signed i64; interpret bits as u64 for shifting
     let actor_uuid_u = actor_uuid as u64;
     let uid = (actor_uuid_u >> 16) as i64;
 
-    // Record revive for UI emission (timestamp using now_ms helper)
-    let ts = now_ms();
-    record_revive(encounter, uid, ts);
+    // Record revive for UI emission
+    record_revive(encounter, uid, timestamp_ms);
     // Persist revive to DB (increment per-actor revive counter)
     let is_local = encounter.local_player_uid == uid;
     let _ = is_local;
@@ -270,10 +370,100 @@ pub fn process_notify_revive_user(
     Some(())
 }
 
+/// Process a SyncClientUseSkill packet: append a cast to the local player's cast log.
+///
+/// The packet is only sent for the local player's own casts and does not name the
+/// caster, so it is attributed to `encounter.local_player_uid`.
+pub fn process_sync_client_use_skill(
+    encounter: &mut Encounter,
+    use_skill: blueprotobuf::SyncClientUseSkill,
+    timestamp_ms: i64,
+) -> Option<()> {
+    let skill_level_id = use_skill.skill_level_id?;
+    if encounter.local_player_uid == 0 {
+        return None;
+    }
+    let target_uid = use_skill
+        .skill_target_uuid
+        .filter(|uuid| *uuid != 0)
+        .map(|uuid| uuid >> 16); // UUID =/= uid (have to >> 16)
+
+    let caster = encounter
+        .entity_uid_to_entity
+        .entry(encounter.local_player_uid)
+        .or_insert_with(|| Entity {
+            entity_type: EEntityType::EntChar,
+            ..Default::default()
+        });
+    caster.casts.push(SkillCast {
+        timestamp_ms,
+        skill_level_id,
+        target_uid,
+    });
+    Some(())
+}
+
+/// Process a GetSocialDataReply (player inspect): refresh the player's identity fields.
+///
+/// The reply comes from an RPC return rather than a notify, so the player may not
+/// be near the local player; it is still written to the entity cache.
+pub fn process_get_social_data_reply(
+    encounter: &mut Encounter,
+    entity_cache: &mut HashMap<i64, CachedEntity>,
+    reply: blueprotobuf::GetSocialDataReply,
+) -> Option<()> {
+    use crate::live::opcodes_models::{AttrType, AttrValue};
+
+    let social_data = reply.data?;
+    let player_uid = social_data.char_id?;
+
+    let target_entity = encounter
+        .entity_uid_to_entity
+        .entry(player_uid)
+        .or_default();
+    target_entity.entity_type = EEntityType::EntChar;
+    if let Some(basic_data) = social_data.basic_data {
+        if let Some(name) = basic_data.name.filter(|name| !name.is_empty()) {
+            target_entity.name = name;
+            target_entity.set_attr(
+                AttrType::Name,
+                AttrValue::String(target_entity.name.clone()),
+            );
+        }
+        if let Some(level) = basic_data.level {
+            target_entity.level = level;
+            target_entity.set_attr(AttrType::Level, AttrValue::Int(level as i64));
+        }
+    }
+    if let Some(class_id) = social_data
+        .profession_data
+        .and_then(|profession| profession.profession_id)
+    {
+        target_entity.class_id = class_id;
+        target_entity.set_attr(AttrType::ProfessionId, AttrValue::Int(class_id as i64));
+    }
+    if let Some(fight_point) = social_data
+        .user_attr_data
+        .and_then(|attrs| attrs.fight_point)
+    {
+        target_entity.ability_score = fight_point.min(i32::MAX as i64) as i32;
+        target_entity.set_attr(AttrType::FightPoint, AttrValue::Int(fight_point));
+    }
+
+    let name_opt = if target_entity.name.is_empty() {
+        None
+    } else {
+        Some(target_entity.name.clone())
+    };
+    upsert_entity_cache_entry(entity_cache, player_uid, target_entity, name_opt, now_ms());
+    Some(())
+}
+
 pub fn process_sync_near_entities(
     encounter: &mut Encounter,
     entity_cache: &mut HashMap<i64, CachedEntity>,
     sync_near_entities: blueprotobuf::SyncNearEntities,
+    timestamp_ms: i64,
 ) -> Option<()> {
     for pkt_entity in sync_near_entities.appear {
         let target_uuid = pkt_entity.uuid?;
@@ -296,7 +486,7 @@ pub fn process_sync_near_entities(
                 );
             }
             EEntityType::EntMonster => {
-                process_monster_attrs(target_entity, pkt_entity.attrs?.attrs);
+                process_monster_attrs(target_entity, pkt_entity.attrs?.attrs, timestamp_ms);
             }
             _ => {}
         }
@@ -404,6 +594,7 @@ pub fn process_sync_dungeon_data(
     battle_state: &mut BattleStateMachine,
     sync_dungeon_data: blueprotobuf::SyncDungeonData,
     encounter_has_stats: bool,
+    now: Instant,
 ) -> Option<EncounterResetReason> {
     let mut reset_reason = None;
     info!(
@@ -430,7 +621,7 @@ pub fn process_sync_dungeon_data(
                     complete,
                     nums
                 );
-                if let Some(reason) = battle_state.record_dungeon_target(target_id, nums, complete) {
+                if let Some(reason) = battle_state.record_dungeon_target(target_id, nums, complete, now) {
                     reset_reason = Some(reason);
                 }
             }
@@ -447,6 +638,7 @@ pub fn process_sync_dungeon_dirty_data(
     battle_state: &mut BattleStateMachine,
     sync_dungeon_dirty_data: blueprotobuf::SyncDungeonDirtyData,
     encounter_has_stats: bool,
+    now: Instant,
 ) -> Option<EncounterResetReason> {
     info!(
         target: "app::live",
@@ -499,7 +691,7 @@ pub fn process_sync_dungeon_dirty_data(
             complete,
             nums
         );
-        if let Some(reason) = battle_state.record_dungeon_target(target_id, nums, complete) {
+        if let Some(reason) = battle_state.record_dungeon_target(target_id, nums, complete, now) {
             reset_reason = Some(reason);
         }
     }
@@ -519,6 +711,7 @@ pub fn process_sync_to_me_delta_info(
     entity_cache: &mut HashMap<i64, CachedEntity>,
     sync_to_me_delta_info: blueprotobuf::SyncToMeDeltaInfo,
     dungeon_runtime: Option<&DungeonLogRuntime>,
+    timestamp_ms: i64,
 ) -> Option<()> {
     let delta_info = match sync_to_me_delta_info.delta_info {
         Some(info) => info,
@@ -533,7 +726,7 @@ pub fn process_sync_to_me_delta_info(
     }
 
     if let Some(base_delta) = delta_info.base_delta {
-        process_aoi_sync_delta(encounter, entity_cache, base_delta, dungeon_runtime);
+        process_aoi_sync_delta(encounter, entity_cache, base_delta, dungeon_runtime, timestamp_ms);
     }
 
     Some(())
@@ -544,6 +737,7 @@ pub fn process_aoi_sync_delta(
     entity_cache: &mut HashMap<i64, CachedEntity>,
     aoi_sync_delta: blueprotobuf::AoiSyncDelta,
     dungeon_runtime: Option<&DungeonLogRuntime>,
+    timestamp_ms: i64,
 ) -> Option<()> {
     let target_uuid = aoi_sync_delta.uuid?; // UUID =/= uid (have to >> 16)
     let target_uid = target_uuid >> 16;
@@ -569,7 +763,7 @@ pub fn process_aoi_sync_delta(
                 );
             }
             EEntityType::EntMonster => {
-                process_monster_attrs(&mut target_entity, attrs_collection.attrs);
+                process_monster_attrs(&mut target_entity, attrs_collection.attrs, timestamp_ms);
             }
             _ => {}
         }
@@ -601,18 +795,29 @@ pub fn process_aoi_sync_delta(
     //                     "BuffInfoSync (from AoiSyncDelta, target_uid={}, JSON failed: {}): {:?}",
     //                     target_uid, e, buff_info_sync
     //                 );
+    // Track buff intervals for uptime
+    if let Some(raw_bytes) = aoi_sync_delta.buff_effect.as_deref()
+        && let Ok(buff_effect_sync) = blueprotobuf::BuffEffectSync::decode(raw_bytes)
+        && let Some(target_entity) = encounter.entity_uid_to_entity.get_mut(&target_uid)
+    {
+        process_buff_effect_sync(target_entity, buff_effect_sync, timestamp_ms);
+    }
+
     let Some(skill_effect) = aoi_sync_delta.skill_effects else {
         return Some(()); // return ok since this variable usually doesn't exist
     };
 
+    // Timeline buckets count from the first combat packet of the encounter
+    let fight_start_ms = match encounter.time_fight_start_ms {
+        0 => u128::try_from(timestamp_ms).unwrap_or_default(),
+        start_ms => start_ms,
+    };
+
     // Process Damage
     for sync_damage_info in skill_effect.damages {
         // Timestamp for this event
-        let timestamp_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_millis();
-        let timestamp_ms_i64 = timestamp_ms.min(i64::MAX as u128) as i64;
+        let timestamp_ms_i64 = timestamp_ms;
+        let timestamp_ms = u128::try_from(timestamp_ms).unwrap_or_default();
         let non_lucky_dmg = sync_damage_info.value;
         let lucky_value = sync_damage_info.lucky_value;
 
@@ -699,6 +904,11 @@ pub fn process_aoi_sync_delta(
                 attacker_entity.healing.total += actual_value;
                 skill.hits += 1;
                 skill.total_value += actual_value;
+                if let Some(bucket) =
+                    attacker_entity.timeline_bucket_mut(fight_start_ms, timestamp_ms)
+                {
+                    bucket.healing += actual_value;
+                }
 
                 // Track per-skill per-target stats for healing
                 let key = (skill_key, target_uid);
@@ -748,6 +958,11 @@ pub fn process_aoi_sync_delta(
                 skill.hits += 1;
                 skill.total_value += actual_value;
                 update_active_damage_time(attacker_entity, timestamp_ms);
+                if let Some(bucket) =
+                    attacker_entity.timeline_bucket_mut(fight_start_ms, timestamp_ms)
+                {
+                    bucket.damage += actual_value;
+                }
 
                 if is_boss_target {
                     let skill_boss_only = attacker_entity
@@ -773,6 +988,13 @@ pub fn process_aoi_sync_delta(
                     attacker_entity.damage_boss_only.total += actual_value;
                     skill_boss_only.hits += 1;
                     skill_boss_only.total_value += actual_value;
+                    if let Some(damage) = attacker_entity.boss_damage_bucket_mut(
+                        target_uid,
+                        fight_start_ms,
+                        timestamp_ms,
+                    ) {
+                        *damage += actual_value;
+                    }
                 }
 
                 // Track per-target totals
@@ -857,6 +1079,22 @@ pub fn process_aoi_sync_delta(
                 max_hp_opt,
             );
 
+            // Players keep their last hits and heals received for death recaps
+            if defender_entity.entity_type == EEntityType::EntChar {
+                defender_entity.push_recap_event(RecapEvent {
+                    timestamp_ms: timestamp_ms_i64,
+                    source_uid: attacker_uid,
+                    skill_id: owner_id,
+                    value: if was_heal_event {
+                        actual_value
+                    } else {
+                        effective_value
+                    },
+                    is_heal: was_heal_event,
+                    hp_before: prev_hp_opt,
+                });
+            }
+
             // Only record damage/taken stats if this event is not a heal
             if !was_heal_event {
                 // Insert damage event
@@ -883,6 +1121,11 @@ pub fn process_aoi_sync_delta(
                     defender_entity.taken.total += effective_value;
                     taken_skill.hits += 1;
                     taken_skill.total_value += effective_value;
+                    if let Some(bucket) =
+                        defender_entity.timeline_bucket_mut(fight_start_ms, timestamp_ms)
+                    {
+                        bucket.taken += effective_value;
+                    }
                 }
             }
 
@@ -923,7 +1166,7 @@ pub fn process_aoi_sync_delta(
 
                 // Persist segments if a boss died or a new boss started (implies previous segment closed)
                 if boss_died || new_boss_started {
-                    dungeon_log::persist_segments(&runtime.shared_log, false);
+                    runtime.persist_segments(false);
                 }
             }
         }
@@ -935,10 +1178,7 @@ pub fn process_aoi_sync_delta(
     }
 
     // Figure out timestamps.
-    let timestamp_ms = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_millis();
+    let timestamp_ms = u128::try_from(timestamp_ms).unwrap_or_default();
 
     if encounter.time_fight_start_ms == Default::default() {
         encounter.time_fight_start_ms = timestamp_ms;
@@ -1483,7 +1723,7 @@ fn process_player_attrs(
     }
 }
 
-fn process_monster_attrs(monster_entity: &mut Entity, attrs: Vec<Attr>) {
+fn process_monster_attrs(monster_entity: &mut Entity, attrs: Vec<Attr>, timestamp_ms: i64) {
     use crate::live::opcodes_models::attr_type;
     for attr in attrs {
         let Some(mut raw_bytes) = attr.raw_data else {
@@ -1532,11 +1772,18 @@ fn process_monster_attrs(monster_entity: &mut Entity, attrs: Vec<Attr>) {
             _ => {}
         }
     }
+    monster_entity.record_hp_sample(timestamp_ms);
 }
 
 #[cfg(test)]
 mod tests {
-    use super::did_target_die;
+    use super::{
+        did_target_die, on_data_loss, process_aoi_sync_delta, process_sync_client_use_skill,
+    };
+    use crate::live::opcodes_models::Encounter;
+    use crate::packets::encoder;
+    use blueprotobuf_lib::blueprotobuf;
+    use std::collections::HashMap;
 
     #[test]
     fn uses_packet_flag_when_present() {
@@ -1560,4 +1807,155 @@ mod tests {
     fn zero_loss_never_marks_death() {
         assert!(!did_target_die(None, 0, 0, Some(1), Some(2)));
     }
+
+    #[test]
+    fn damage_is_bucketed_per_second_of_the_fight() {
+        let mut encounter = Encounter::default();
+        let mut entity_cache = HashMap::new();
+        let player = (7 << 16) | 640;
+        let monster = (9 << 16) | 64;
+        for (value, timestamp_ms) in [(100, 5_000), (50, 5_900), (30, 7_200)] {
+            let hit = blueprotobuf::SyncDamageInfo {
+                attacker_uuid: Some(player),
+                value: Some(value),
+                owner_id: Some(1),
+                ..Default::default()
+            };
+            let delta = encoder::near_delta_damage(monster, vec![hit]).delta_infos.remove(0);
+            process_aoi_sync_delta(&mut encounter, &mut entity_cache, delta, None, timestamp_ms)
+                .unwrap();
+        }
+
+        let damage: Vec<u128> = encounter.entity_uid_to_entity[&7]
+            .timeline
+            .iter()
+            .map(|bucket| bucket.damage)
+            .collect();
+        assert_eq!(damage, [150, 0, 30]);
+        // Damage from players is not counted as taken
+        assert!(encounter.entity_uid_to_entity[&9].timeline.is_empty());
+    }
+
+    #[test]
+    fn player_deaths_keep_the_hits_and_heals_before_them() {
+        let mut encounter = Encounter::default();
+        let mut entity_cache = HashMap::new();
+        let player = (7 << 16) | 640;
+        let healer = (8 << 16) | 640;
+        let monster = (9 << 16) | 64;
+        let events = [
+            (monster, 300, None, None, 1_000),
+            (healer, 200, Some(blueprotobuf::EDamageType::Heal as i32), None, 1_500),
+            (monster, 900, None, Some(true), 2_000),
+        ];
+        for (attacker, value, r#type, is_dead, timestamp_ms) in events {
+            let hit = blueprotobuf::SyncDamageInfo {
+                attacker_uuid: Some(attacker),
+                value: Some(value),
+                owner_id: Some(55),
+                r#type,
+                is_dead,
+                ..Default::default()
+            };
+            let delta = encoder::near_delta_damage(player, vec![hit]).delta_infos.remove(0);
+            process_aoi_sync_delta(&mut encounter, &mut entity_cache, delta, None, timestamp_ms)
+                .unwrap();
+        }
+
+        let entity = &encounter.entity_uid_to_entity[&7];
+        assert_eq!(entity.deaths.len(), 1);
+        let death = &entity.deaths[0];
+        assert_eq!(death.timestamp_ms, 2_000);
+        assert_eq!(death.killer_uid, Some(9));
+        assert_eq!(death.skill_id, Some(55));
+        let values: Vec<(u128, bool)> = death
+            .recent_events
+            .iter()
+            .map(|event| (event.value, event.is_heal))
+            .collect();
+        assert_eq!(values, [(300, false), (200, true), (900, false)]);
+        assert!(entity.recent_received.is_empty());
+    }
+
+    #[test]
+    fn buff_effects_in_deltas_track_uptime() {
+        use prost::Message;
+
+        let mut encounter = Encounter::default();
+        let mut entity_cache = HashMap::new();
+        let add = blueprotobuf::BuffEffect {
+            buff_uuid: Some(3),
+            logic_effect: vec![blueprotobuf::BuffEffectLogicInfo {
+                effect_type: Some(blueprotobuf::EBuffEffectLogicPbType::BuffEffectAddBuff as i32),
+                raw_data: Some(
+                    blueprotobuf::BuffInfo {
+                        base_id: Some(2_110_051),
+                        layer: Some(2),
+                        ..Default::default()
+                    }
+                    .encode_to_vec(),
+                ),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let remove = blueprotobuf::BuffEffect {
+            r#type: Some(blueprotobuf::EBuffEventType::BuffEventRemove as i32),
+            buff_uuid: Some(3),
+            ..Default::default()
+        };
+        for (buff_effect, timestamp_ms) in [(add, 1_000), (remove, 4_000)] {
+            let delta = blueprotobuf::AoiSyncDelta {
+                uuid: Some((7 << 16) | 640),
+                buff_effect: Some(
+                    blueprotobuf::BuffEffectSync {
+                        buff_effects: vec![buff_effect],
+                        ..Default::default()
+                    }
+                    .encode_to_vec(),
+                ),
+                ..Default::default()
+            };
+            process_aoi_sync_delta(&mut encounter, &mut entity_cache, delta, None, timestamp_ms)
+                .unwrap();
+        }
+
+        let uptime = &encounter.entity_uid_to_entity[&7].buff_uptime[&2_110_051];
+        assert_eq!(uptime.up_ms_within(0, 10_000), (3_000, 6_000));
+    }
+
+    #[test]
+    fn casts_are_logged_on_local_player() {
+        let mut encounter = Encounter::default();
+        let cast = blueprotobuf::SyncClientUseSkill {
+            skill_target_uuid: Some(42 << 16),
+            skill_level_id: Some(1_234_501),
+        };
+        // Dropped until the local player is known
+        assert!(process_sync_client_use_skill(&mut encounter, cast, 1_000).is_none());
+
+        encounter.local_player_uid = 7;
+        process_sync_client_use_skill(&mut encounter, cast, 1_000).unwrap();
+        let casts = &encounter.entity_uid_to_entity[&7].casts;
+        assert_eq!(casts.len(), 1);
+        assert_eq!(casts[0].skill_id(), 12_345);
+        assert_eq!(casts[0].target_uid, Some(42));
+
+        encounter.reset_combat_state();
+        assert!(encounter.entity_uid_to_entity[&7].casts.is_empty());
+    }
+
+    #[test]
+    fn data_loss_only_flags_a_running_fight() {
+        let mut encounter = Encounter::default();
+        on_data_loss(&mut encounter);
+        assert_eq!(encounter.data_loss_events, 0);
+
+        encounter.time_fight_start_ms = 1_000;
+        on_data_loss(&mut encounter);
+        assert_eq!(encounter.data_loss_events, 1);
+
+        encounter.reset_combat_state();
+        assert_eq!(encounter.data_loss_events, 0);
+    }
 }
@@ -3,6 +3,7 @@ use crate::database::{
     now_ms, save_encounter,
 };
 use crate::live::cd_calc::calculate_skill_cd;
+use crate::live::clock::Clock;
 use crate::live::commands_models::{
     BuffUpdatePayload, BuffUpdateState, FightResourceState, FightResourceUpdatePayload,
     SkillCdState, SkillCdUpdatePayload,
@@ -11,59 +12,31 @@ use crate::live::dungeon_log::{
     self, BattleStateMachine, DungeonLogRuntime, EncounterResetReason, SegmentType,
     SharedDungeonLog,
 };
-use crate::live::event_manager::EventManager;
+use crate::live::event_manager::{EventManager, TauriEventSink};
+use crate::live::event_sink::EventSink;
+use crate::live::handlers;
 use crate::live::opcodes_models::Encounter;
+use crate::live::ttk::KillRateTracker;
+use crate::packets::opcodes::Pkt;
+use crate::packets::session::SessionRecorder;
+use crate::packets::stats;
 use blueprotobuf_lib::blueprotobuf;
 use blueprotobuf_lib::blueprotobuf::{
     BuffChange, BuffEffectSync, BuffInfo, EBuffEffectLogicPbType, EBuffEventType, EEntityType,
 };
-use log::{info, trace, warn};
+use bytes::Bytes;
+use log::{info, warn};
 use prost::Message;
-use serde::Serialize;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tauri::{AppHandle, Emitter, Manager};
+use tauri::AppHandle;
 use tokio::sync::{
     mpsc::{UnboundedReceiver, UnboundedSender, error::TryRecvError, unbounded_channel},
     watch,
 };
 
-/// Safely emits an event to the frontend, handling WebView2 state errors gracefully.
-/// This prevents the app from freezing when the WebView is in an invalid state, maybe.
-/// Returns `true` if the event was emitted successfully, `false` otherwise.
-fn safe_emit<S: Serialize + Clone>(app_handle: &AppHandle, event: &str, payload: S) -> bool {
-    // First check if the live window exists and is valid
-    let live_window = app_handle.get_webview_window(crate::WINDOW_LIVE_LABEL);
-    let main_window = app_handle.get_webview_window(crate::WINDOW_MAIN_LABEL);
-
-    // If no windows are available, skip emitting
-    if live_window.is_none() && main_window.is_none() {
-        trace!("Skipping emit for '{}': no windows available", event);
-        return false;
-    }
-
-    // Try to emit the event, catching WebView2 errors
-    match app_handle.emit(event, payload) {
-        Ok(_) => true,
-        Err(e) => {
-            // Check if this is a WebView2 state error (0x8007139F)
-            let error_str = format!("{:?}", e);
-            if error_str.contains("0x8007139F") || error_str.contains("not in the correct state") {
-                // This is expected when windows are minimized/hidden - don't spam logs
-                trace!(
-                    "WebView2 not ready for '{}' (window may be minimized/hidden)",
-                    event
-                );
-            } else {
-                // Log other errors as warnings
-                warn!("Failed to emit '{}': {}", event, e);
-            }
-            false
-        }
-    }
-}
-
 /// Returns true only for damage entries that are meaningful for encounter segmentation.
 /// restricts trigger to player attackers.
 fn is_valid_player_damage(dmg: &blueprotobuf::SyncDamageInfo) -> bool {
@@ -91,29 +64,17 @@ fn is_valid_player_damage(dmg: &blueprotobuf::SyncDamageInfo) -> bool {
 pub enum StateEvent {
     /// A server change event.
     ServerChange,
-    /// An enter scene event.
-    EnterScene(blueprotobuf::EnterScene),
-    /// A sync near entities event.
-    SyncNearEntities(blueprotobuf::SyncNearEntities),
-    /// A sync container data event.
-    SyncContainerData(blueprotobuf::SyncContainerData),
-    /// A sync container dirty data event.
-    SyncContainerDirtyData(blueprotobuf::SyncContainerDirtyData),
-    /// A sync server time event.
-    SyncServerTime(blueprotobuf::SyncServerTime),
-    /// A sync dungeon data event.
-    SyncDungeonData(blueprotobuf::SyncDungeonData),
-    /// A sync dungeon dirty data event.
-    SyncDungeonDirtyData(blueprotobuf::SyncDungeonDirtyData),
-    /// A sync to me delta info event.
-    SyncToMeDeltaInfo(blueprotobuf::SyncToMeDeltaInfo),
-    /// A sync near delta info event.
-    SyncNearDeltaInfo(blueprotobuf::SyncNearDeltaInfo),
-    /// A notify revive user event.
-    NotifyReviveUser(blueprotobuf::NotifyReviveUser),
-    /// A sync scene attrs event.
-    #[allow(dead_code)]
-    SyncSceneAttrs(blueprotobuf::SyncSceneAttrs),
+    /// Capture data was lost; the running fight is no longer accurate.
+    DataLoss,
+    /// Replayed messages after this were captured at the given ms since the Unix epoch.
+    CaptureTime(i64),
+    /// A game message with a registered handler (see [`crate::live::handlers`]).
+    Message {
+        /// The opcode the message was received as.
+        pkt: Pkt,
+        /// The undecoded protobuf payload.
+        data: Bytes,
+    },
     /// A pause encounter event.
     PauseEncounter(bool),
     /// A reset encounter event. Contains whether this was a manual reset by the user.
@@ -145,12 +106,12 @@ pub struct AppState {
     pub ordered_buff_uuids: Vec<i32>,
     /// Whether ordered_buff_uuids needs recomputing.
     pub buff_order_dirty: bool,
-    /// A handle to the Tauri application instance.
-    pub app_handle: AppHandle,
     /// Whether to only show boss DPS.
     pub boss_only_dps: bool,
     /// A map of low HP bosses.
     pub low_hp_bosses: HashMap<i64, u128>,
+    /// Damage rate trackers for boss time-to-kill, keyed by boss uid.
+    pub kill_rate_trackers: HashMap<i64, KillRateTracker>,
     /// Whether we've already handled the first scene change after startup.
     pub initial_scene_change_handled: bool,
     /// Shared dungeon log used for segment tracking.
@@ -182,6 +143,22 @@ pub struct AppState {
     pub battle_state: BattleStateMachine,
     /// If set, automatic reset can execute only after this timestamp.
     pub pending_auto_reset: Option<Instant>,
+    /// Time source for combat timestamps, segment timeouts and deferred resets.
+    pub clock: Clock,
+    /// Active session recording, if any. Every received message is teed into it.
+    pub session_recorder: Option<SessionRecorder>,
+    /// Handler run times not yet published to the capture stats.
+    pub handler_times: stats::HandlerTimes,
+    /// When set, finished encounters are collected here instead of being saved to the database.
+    pub completed_encounters: Option<Vec<CompletedEncounter>>,
+}
+
+/// An encounter captured at the moment it was closed.
+#[derive(Debug, Clone)]
+pub struct CompletedEncounter {
+    pub metadata: EncounterMetadata,
+    pub encounter: Encounter,
+    pub dungeon_log: Option<crate::live::dungeon_log::DungeonLog>,
 }
 
 #[derive(Debug, Clone)]
@@ -217,16 +194,53 @@ pub enum LiveControlCommand {
         monitored_skill_ids: Vec<i32>,
         monitored_buff_ids: Vec<i32>,
     },
+    StartSessionRecording(PathBuf),
+    StopSessionRecording,
 }
 
 impl AppState {
-    /// Creates a new `AppState`.
+    /// Creates a new `AppState` that emits to the app's windows.
     ///
     /// # Arguments
     ///
     /// * `app_handle` - A handle to the Tauri application instance.
     pub fn new(app_handle: AppHandle) -> Self {
-        Self {
+        Self::with_sink(
+            Some(Arc::new(TauriEventSink::new(app_handle))),
+            crate::database::load_initial_entity_cache(),
+        )
+    }
+
+    /// Creates an `AppState` that emits into `sink` and runs without a window or database.
+    ///
+    /// Finished encounters are collected into `completed_encounters`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Receives every event the live state emits.
+    pub fn with_event_sink(sink: Arc<dyn EventSink>) -> Self {
+        let mut state = Self::with_sink(Some(sink), HashMap::new());
+        state.completed_encounters = Some(Vec::new());
+        state
+    }
+
+    /// Creates an `AppState` that runs without a window or database.
+    ///
+    /// Nothing is emitted, segment tracking is on, time follows the replayed
+    /// capture, and finished encounters are collected into `completed_encounters`.
+    pub fn headless() -> Self {
+        let mut state = Self::with_sink(None, HashMap::new());
+        state.dungeon_segments_enabled = true;
+        state.clock = Clock::replay();
+        state.completed_encounters = Some(Vec::new());
+        state
+    }
+
+    fn with_sink(
+        sink: Option<Arc<dyn EventSink>>,
+        entity_cache: HashMap<i64, CachedEntity>,
+    ) -> Self {
+        let mut state = Self {
             encounter: Encounter::default(),
             event_manager: EventManager::new(),
             skill_cd_map: HashMap::new(),
@@ -237,9 +251,9 @@ impl AppState {
             active_buffs: HashMap::new(),
             ordered_buff_uuids: Vec::new(),
             buff_order_dirty: true,
-            app_handle,
             boss_only_dps: false,
             low_hp_bosses: HashMap::new(),
+            kill_rate_trackers: HashMap::new(),
             initial_scene_change_handled: false,
             dungeon_log: dungeon_log::create_shared_log(),
             dungeon_segments_enabled: false,
@@ -250,10 +264,70 @@ impl AppState {
             attr_skill_cd_pct: 0,
             attr_cd_accelerate_pct: 0,
             server_clock_offset: 0,
-            entity_cache: crate::database::load_initial_entity_cache(),
+            entity_cache,
             playerdata_cache: None,
             battle_state: BattleStateMachine::default(),
             pending_auto_reset: None,
+            clock: Clock::Wall,
+            session_recorder: None,
+            handler_times: stats::HandlerTimes::default(),
+            completed_encounters: None,
+        };
+        if let Some(sink) = sink {
+            state.event_manager.initialize(sink);
+        }
+        state
+    }
+
+    /// Hands a finished encounter to the headless collector, if one is attached.
+    ///
+    /// Returns `false` when the encounter should be persisted as usual.
+    fn collect_completed_encounter(&mut self, metadata: &EncounterMetadata) -> bool {
+        let Some(completed) = self.completed_encounters.as_mut() else {
+            return false;
+        };
+        completed.push(CompletedEncounter {
+            metadata: metadata.clone(),
+            encounter: self.encounter.clone(),
+            dungeon_log: dungeon_log::snapshot(&self.dungeon_log),
+        });
+        true
+    }
+
+    /// Tees a received message into the active session recording, if any.
+    ///
+    /// A write failure stops the recording rather than retrying on every packet.
+    pub fn record_session_message(&mut self, op: Pkt, data: &[u8]) {
+        let Some(recorder) = self.session_recorder.as_mut() else {
+            return;
+        };
+        if let Err(e) = recorder.record(op, data) {
+            warn!(target: "app::live", "session_recording_failed err={}", e);
+            self.session_recorder = None;
+        }
+    }
+
+    fn start_session_recording(&mut self, path: PathBuf) {
+        self.stop_session_recording();
+        match SessionRecorder::create(&path) {
+            Ok(recorder) => {
+                info!(target: "app::live", "session_recording_started path={}", path.display());
+                self.session_recorder = Some(recorder);
+            }
+            Err(e) => {
+                warn!(target: "app::live", "session_recording_start_failed err={}", e);
+            }
+        }
+    }
+
+    fn stop_session_recording(&mut self) {
+        let Some(recorder) = self.session_recorder.take() else {
+            return;
+        };
+        let records = recorder.records();
+        match recorder.finish() {
+            Ok(_) => info!(target: "app::live", "session_recording_stopped messages={}", records),
+            Err(e) => warn!(target: "app::live", "session_recording_finish_failed err={}", e),
         }
     }
 
@@ -353,15 +427,14 @@ fn emit_skill_cd_update_if_needed(state: &AppState, payload: Vec<SkillCdState>)
     if payload.is_empty() {
         return;
     }
-    if let Some(app_handle) = state.event_manager.get_app_handle() {
+    if state.event_manager.should_emit_events() {
         info!(
             "[skill-cd] emit update for {} skills (monitored={:?})",
             payload.len(),
             state.monitored_skill_ids
         );
         info!("[skill-cd] payload={:?}", payload);
-        safe_emit(
-            &app_handle,
+        state.event_manager.emit(
             "skill-cd-update",
             SkillCdUpdatePayload { skill_cds: payload },
         );
@@ -502,8 +575,29 @@ impl AppStateManager {
     ///
     /// * `app_handle` - A handle to the Tauri application instance.
     pub fn new(app_handle: AppHandle) -> Self {
-        let initial_state = AppState::new(app_handle);
-        let initial_snapshot = Arc::new(build_live_state_snapshot(&initial_state));
+        Self::from_initial_state(&AppState::new(app_handle))
+    }
+
+    /// Creates an `AppStateManager` without an app handle, for headless runs
+    /// (see [`AppState::headless`]).
+    pub fn headless() -> Self {
+        Self::from_initial_state(&AppState::headless())
+    }
+
+    /// Creates an `AppStateManager` and the `AppState` it drives, emitting into `sink`.
+    ///
+    /// Runs without a window or database; see [`AppState::with_event_sink`].
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Receives every event the live state emits.
+    pub fn with_event_sink(sink: Arc<dyn EventSink>) -> (Self, AppState) {
+        let state = AppState::with_event_sink(sink);
+        (Self::from_initial_state(&state), state)
+    }
+
+    fn from_initial_state(initial_state: &AppState) -> Self {
+        let initial_snapshot = Arc::new(build_live_state_snapshot(initial_state));
         let (snapshot_tx, snapshot_rx) = watch::channel(initial_snapshot);
         let (control_tx, control_rx) = unbounded_channel();
         Self {
@@ -538,7 +632,7 @@ impl AppStateManager {
             return;
         }
         for event in events {
-            self.apply_event(state, event).await;
+            self.apply_event(state, event);
         }
         self.publish_snapshot_from_state(state);
     }
@@ -570,79 +664,39 @@ impl AppStateManager {
         self.send_control(LiveControlCommand::StateEvent(event))
     }
 
-    async fn apply_event(&self, state: &mut AppState, event: StateEvent) {
-        // Check if encounter is paused for events that should be dropped
-        if state.is_encounter_paused()
-            && matches!(
-                event,
-                StateEvent::SyncNearEntities(_)
-                    | StateEvent::SyncContainerData(_)
-                    | StateEvent::SyncContainerDirtyData(_)
-                    | StateEvent::SyncToMeDeltaInfo(_)
-                    | StateEvent::SyncNearDeltaInfo(_)
-            )
-        {
-            info!("packet dropped due to encounter paused");
-            return;
-        }
-
+    fn apply_event(&self, state: &mut AppState, event: StateEvent) {
         match event {
             StateEvent::ServerChange => {
-                self.on_server_change(state).await;
-            }
-            StateEvent::EnterScene(data) => {
-                self.process_enter_scene(state, data).await;
-            }
-            StateEvent::SyncNearEntities(data) => {
-                self.process_sync_near_entities(state, data).await;
-                // Note: Player names are automatically stored in the database via UpsertEntity tasks
-                // No need to maintain a separate cache anymore
-            }
-            StateEvent::SyncContainerData(data) => {
-                // store local_player copy
-                state.encounter.local_player = data.clone();
-
-                self.process_sync_container_data(state, data).await;
-                // Note: Player names are automatically stored in the database via UpsertEntity tasks
-                // No need to maintain a separate cache anymore
-            }
-            StateEvent::SyncContainerDirtyData(data) => {
-                self.process_sync_container_dirty_data(state, data).await;
-            }
-            StateEvent::SyncServerTime(_data) => {
-                // todo: this is skipped, not sure what info it has
-            }
-            StateEvent::SyncDungeonData(data) => {
-                self.process_sync_dungeon_data(state, data).await;
-                self.apply_battle_state_resets_if_needed(state).await;
-            }
-            StateEvent::SyncDungeonDirtyData(data) => {
-                self.process_sync_dungeon_dirty_data(state, data).await;
-                self.apply_battle_state_resets_if_needed(state).await;
+                self.on_server_change(state);
             }
-            StateEvent::SyncToMeDeltaInfo(data) => {
-                self.process_sync_to_me_delta_info(state, data).await;
-                self.apply_battle_state_resets_if_needed(state).await;
-                // Note: Player names are automatically stored in the database via UpsertEntity tasks
-                // No need to maintain a separate cache anymore
+            StateEvent::DataLoss => {
+                crate::live::opcodes_process::on_data_loss(&mut state.encounter);
             }
-            StateEvent::SyncNearDeltaInfo(data) => {
-                self.process_sync_near_delta_info(state, data).await;
-                // Note: Player names are automatically stored in the database via UpsertEntity tasks
-                // No need to maintain a separate cache anymore
+            StateEvent::CaptureTime(timestamp_ms) => {
+                state.clock.advance_to(timestamp_ms);
             }
-            StateEvent::NotifyReviveUser(data) => {
-                self.process_notify_revive_user(state, data).await;
-            }
-            StateEvent::SyncSceneAttrs(_) => {
-                // SyncSceneAttrs handling is disabled to possibly remedy crashing bug.
+            StateEvent::Message { pkt, data } => {
+                let Some(handler) = handlers::handler(pkt) else {
+                    return;
+                };
+                // Check if encounter is paused for events that should be dropped
+                if state.is_encounter_paused() && handler.pausable() {
+                    info!("packet dropped due to encounter paused");
+                    return;
+                }
+                let started = Instant::now();
+                if let Err(e) = handler.handle(self, state, data) {
+                    warn!("Error decoding {pkt:?}.. ignoring: {e}");
+                    stats::record_decode_failure(pkt);
+                }
+                state.handler_times.record(pkt, started.elapsed());
             }
             StateEvent::PauseEncounter(paused) => {
                 state.set_encounter_paused(paused);
             }
             StateEvent::ResetEncounter { is_manual } => {
                 state.pending_auto_reset = None;
-                self.reset_encounter(state, is_manual).await;
+                self.reset_encounter(state, is_manual);
             }
         }
     }
@@ -650,7 +704,7 @@ impl AppStateManager {
     async fn apply_control_command(&self, state: &mut AppState, command: LiveControlCommand) {
         match command {
             LiveControlCommand::StateEvent(event) => {
-                self.apply_event(state, event).await;
+                self.apply_event(state, event);
             }
             LiveControlCommand::SetBossOnlyDps(enabled) => {
                 state.boss_only_dps = enabled;
@@ -658,10 +712,10 @@ impl AppStateManager {
             }
             LiveControlCommand::SetDungeonSegmentsEnabled(enabled) => {
                 state.dungeon_segments_enabled = enabled;
-                let runtime =
-                    DungeonLogRuntime::new(state.dungeon_log.clone(), state.app_handle.clone());
-                let snapshot = runtime.snapshot();
-                dungeon_log::emit_if_changed(&runtime.app_handle, snapshot);
+                if let Some(sink) = state.event_manager.sink() {
+                    let snapshot = dungeon_log::snapshot(&state.dungeon_log);
+                    dungeon_log::emit_if_changed(sink.as_ref(), snapshot);
+                }
             }
             LiveControlCommand::SetEventUpdateRateMs(rate_ms) => {
                 state.event_update_rate_ms = rate_ms;
@@ -690,18 +744,24 @@ impl AppStateManager {
                 state.monitored_skill_ids = monitored_skill_ids;
                 state.monitored_buff_ids = monitored_buff_ids;
             }
+            LiveControlCommand::StartSessionRecording(path) => {
+                state.start_session_recording(path);
+            }
+            LiveControlCommand::StopSessionRecording => {
+                state.stop_session_recording();
+            }
         }
 
         self.publish_snapshot_from_state(state);
     }
 
-    async fn on_server_change(&self, state: &mut AppState) {
+    fn on_server_change(&self, state: &mut AppState) {
         use crate::live::opcodes_process::on_server_change;
         state.pending_auto_reset = None;
 
         // Persist dungeon segments if enabled
         if state.dungeon_segments_enabled {
-            dungeon_log::persist_segments(&state.dungeon_log, true);
+            dungeon_log::persist_segments(&state.dungeon_log, true, state.clock.now_ms());
         }
 
         // Persist encounter directly on server change.
@@ -711,9 +771,7 @@ impl AppStateManager {
             .entity_uid_to_entity
             .values()
             .filter(|e| {
-                e.entity_type == EEntityType::EntChar
-                    && !e.name.is_empty()
-                    && (e.damage.hits > 0 || e.healing.hits > 0 || e.taken.hits > 0)
+                e.entity_type == EEntityType::EntChar && !e.name.is_empty() && e.has_combat()
             })
             .map(|e| e.name.clone())
             .collect();
@@ -721,7 +779,7 @@ impl AppStateManager {
         player_names.dedup();
         let metadata = EncounterMetadata {
             started_at_ms: state.encounter.time_fight_start_ms as i64,
-            ended_at_ms: Some(now_ms()),
+            ended_at_ms: Some(state.clock.now_ms()),
             local_player_id: Some(state.encounter.local_player_uid),
             total_dmg: state.encounter.total_dmg.min(i64::MAX as u128) as i64,
             total_heal: state.encounter.total_heal.min(i64::MAX as u128) as i64,
@@ -734,6 +792,7 @@ impl AppStateManager {
                 as f64)
                 / 1000.0,
             is_manually_reset: false,
+            is_incomplete: state.encounter.data_loss_events > 0,
             boss_names: defeated,
             player_names,
         };
@@ -749,31 +808,33 @@ impl AppStateManager {
                 metadata.player_names.len(),
                 metadata.boss_names.len()
             );
-            match save_encounter(&state.encounter, &metadata) {
-                Ok(encounter_id) => {
-                    info!(
-                        target: "app::live",
-                        "persist_encounter_on_server_change_ok encounter_id={}",
-                        encounter_id
-                    );
-                }
-                Err(e) => {
-                    warn!(
-                        target: "app::live",
-                        "persist_encounter_on_server_change_failed error={}",
-                        e
-                    );
+            if !state.collect_completed_encounter(&metadata) {
+                match save_encounter(&state.encounter, &metadata) {
+                    Ok(encounter_id) => {
+                        info!(
+                            target: "app::live",
+                            "persist_encounter_on_server_change_ok encounter_id={}",
+                            encounter_id
+                        );
+                    }
+                    Err(e) => {
+                        warn!(
+                            target: "app::live",
+                            "persist_encounter_on_server_change_failed error={}",
+                            e
+                        );
+                    }
                 }
-            }
-            let dirty_entities = state.collect_dirty_entity_cache();
-            if !dirty_entities.is_empty() {
-                if let Err(e) = flush_entity_cache(dirty_entities) {
-                    warn!(target: "app::live", "flush_entity_cache_failed error={}", e);
+                let dirty_entities = state.collect_dirty_entity_cache();
+                if !dirty_entities.is_empty() {
+                    if let Err(e) = flush_entity_cache(dirty_entities) {
+                        warn!(target: "app::live", "flush_entity_cache_failed error={}", e);
+                    }
                 }
-            }
-            if let Some(playerdata) = state.take_dirty_playerdata() {
-                if let Err(e) = flush_playerdata(playerdata) {
-                    warn!(target: "app::live", "flush_playerdata_failed error={}", e);
+                if let Some(playerdata) = state.take_dirty_playerdata() {
+                    if let Err(e) = flush_playerdata(playerdata) {
+                        warn!(target: "app::live", "flush_playerdata_failed error={}", e);
+                    }
                 }
             }
         } else {
@@ -795,10 +856,11 @@ impl AppStateManager {
         }
 
         state.low_hp_bosses.clear();
+        state.kill_rate_trackers.clear();
         state.battle_state = BattleStateMachine::default();
     }
 
-    async fn snapshot_segment_and_reset_live_meter(&self, state: &mut AppState) {
+    fn snapshot_segment_and_reset_live_meter(&self, state: &mut AppState) {
         // Persist dungeon segments
         // dungeon_log::persist_segments(&state.dungeon_log, true);
 
@@ -835,9 +897,10 @@ impl AppStateManager {
         }
 
         state.low_hp_bosses.clear();
+        state.kill_rate_trackers.clear();
     }
     // all scene id extraction logic is here (its pretty rough)
-    async fn process_enter_scene(
+    pub(crate) fn process_enter_scene(
         &self,
         state: &mut AppState,
         enter_scene: blueprotobuf::EnterScene,
@@ -929,10 +992,10 @@ impl AppStateManager {
                     info!(
                         "Dungeon segments enabled: snapshotting segment and resetting live meter"
                     );
-                    self.snapshot_segment_and_reset_live_meter(state).await;
+                    self.snapshot_segment_and_reset_live_meter(state);
                 } else {
                     info!("Standard mode: ending active encounter");
-                    self.reset_encounter(state, false).await;
+                    self.reset_encounter(state, false);
                 }
             }
 
@@ -1061,7 +1124,7 @@ impl AppStateManager {
         }
     }
 
-    async fn process_sync_near_entities(
+    pub(crate) fn process_sync_near_entities(
         &self,
         state: &mut AppState,
         sync_near_entities: blueprotobuf::SyncNearEntities,
@@ -1071,6 +1134,7 @@ impl AppStateManager {
             &mut state.encounter,
             &mut state.entity_cache,
             sync_near_entities,
+            state.clock.now_ms(),
         )
         .is_none()
         {
@@ -1079,7 +1143,7 @@ impl AppStateManager {
     }
 
     #[allow(dead_code)]
-    async fn process_sync_scene_attrs(
+    fn process_sync_scene_attrs(
         &self,
         state: &mut AppState,
         sync_scene_attrs: blueprotobuf::SyncSceneAttrs,
@@ -1151,7 +1215,7 @@ impl AppStateManager {
         }
     }
 
-    async fn process_sync_container_data(
+    pub(crate) fn process_sync_container_data(
         &self,
         state: &mut AppState,
         sync_container_data: blueprotobuf::SyncContainerData,
@@ -1170,7 +1234,7 @@ impl AppStateManager {
         }
     }
 
-    async fn process_sync_container_dirty_data(
+    pub(crate) fn process_sync_container_dirty_data(
         &self,
         state: &mut AppState,
         sync_container_dirty_data: blueprotobuf::SyncContainerDirtyData,
@@ -1183,7 +1247,7 @@ impl AppStateManager {
         }
     }
 
-    async fn process_sync_dungeon_data(
+    pub(crate) fn process_sync_dungeon_data(
         &self,
         state: &mut AppState,
         sync_dungeon_data: blueprotobuf::SyncDungeonData,
@@ -1202,17 +1266,18 @@ impl AppStateManager {
             &mut state.battle_state,
             sync_dungeon_data,
             encounter_has_stats,
+            state.clock.now(),
         ) {
             info!(
                 target: "app::live",
                 "State layer applying reset from SyncDungeonData: {:?}",
                 reason
             );
-            self.apply_reset_reason(state, reason).await;
+            self.apply_reset_reason(state, reason);
         }
     }
 
-    async fn process_sync_dungeon_dirty_data(
+    pub(crate) fn process_sync_dungeon_dirty_data(
         &self,
         state: &mut AppState,
         sync_dungeon_dirty_data: blueprotobuf::SyncDungeonDirtyData,
@@ -1231,17 +1296,18 @@ impl AppStateManager {
             &mut state.battle_state,
             sync_dungeon_dirty_data,
             encounter_has_stats,
+            state.clock.now(),
         ) {
             info!(
                 target: "app::live",
                 "State layer applying reset from SyncDungeonDirtyData: {:?}",
                 reason
             );
-            self.apply_reset_reason(state, reason).await;
+            self.apply_reset_reason(state, reason);
         }
     }
 
-    async fn process_sync_to_me_delta_info(
+    pub(crate) fn process_sync_to_me_delta_info(
         &self,
         state: &mut AppState,
         sync_to_me_delta_info: blueprotobuf::SyncToMeDeltaInfo,
@@ -1302,15 +1368,12 @@ impl AppStateManager {
             };
             state.fight_res_state = Some(new_state.clone());
 
-            if let Some(app_handle) = state.event_manager.get_app_handle() {
-                safe_emit(
-                    &app_handle,
-                    "fight-res-update",
-                    FightResourceUpdatePayload {
-                        fight_res: new_state,
-                    },
-                );
-            }
+            state.event_manager.emit(
+                "fight-res-update",
+                FightResourceUpdatePayload {
+                    fight_res: new_state,
+                },
+            );
         }
 
         let mut should_recalculate = false;
@@ -1354,7 +1417,7 @@ impl AppStateManager {
 
         if state
             .pending_auto_reset
-            .is_some_and(|trigger_at| Instant::now() >= trigger_at)
+            .is_some_and(|trigger_at| state.clock.now() >= trigger_at)
         {
             let has_damage = sync_to_me_delta_info
                 .delta_info
@@ -1371,7 +1434,7 @@ impl AppStateManager {
                         target: "app::live",
                         "Deferred reset executing: damage in SyncToMeDeltaInfo"
                     );
-                    self.reset_encounter(state, false).await;
+                    self.reset_encounter(state, false);
                 } else {
                     info!(
                         target: "app::live",
@@ -1390,6 +1453,7 @@ impl AppStateManager {
             &mut state.entity_cache,
             sync_to_me_delta_info,
             dungeon_ctx.as_ref(),
+            state.clock.now_ms(),
         );
 
         if let Some(raw_bytes) = buff_effect_bytes {
@@ -1403,13 +1467,9 @@ impl AppStateManager {
                 &mut state.buff_order_dirty,
                 &mut state.server_clock_offset,
             ) {
-                if let Some(app_handle) = state.event_manager.get_app_handle() {
-                    safe_emit(
-                        &app_handle,
-                        "buff-update",
-                        BuffUpdatePayload { buffs: payload },
-                    );
-                }
+                state
+                    .event_manager
+                    .emit("buff-update", BuffUpdatePayload { buffs: payload });
             }
         }
 
@@ -1460,7 +1520,7 @@ impl AppStateManager {
         }
     }
 
-    async fn process_sync_near_delta_info(
+    pub(crate) fn process_sync_near_delta_info(
         &self,
         state: &mut AppState,
         sync_near_delta_info: blueprotobuf::SyncNearDeltaInfo,
@@ -1468,7 +1528,7 @@ impl AppStateManager {
         use crate::live::opcodes_process::process_aoi_sync_delta;
         if state
             .pending_auto_reset
-            .is_some_and(|trigger_at| Instant::now() >= trigger_at)
+            .is_some_and(|trigger_at| state.clock.now() >= trigger_at)
         {
             let has_damage = sync_near_delta_info.delta_infos.iter().any(|d| {
                 d.skill_effects.as_ref().is_some_and(|effects| {
@@ -1482,7 +1542,7 @@ impl AppStateManager {
                         target: "app::live",
                         "Deferred reset executing: damage in SyncNearDeltaInfo"
                     );
-                    self.reset_encounter(state, false).await;
+                    self.reset_encounter(state, false);
                 } else {
                     info!(
                         target: "app::live",
@@ -1502,22 +1562,61 @@ impl AppStateManager {
                 &mut state.entity_cache,
                 aoi_sync_delta,
                 dungeon_ctx.as_ref(),
+                state.clock.now_ms(),
             );
         }
     }
 
-    async fn process_notify_revive_user(
+    pub(crate) fn process_notify_revive_user(
         &self,
         state: &mut AppState,
         notify: blueprotobuf::NotifyReviveUser,
     ) {
         use crate::live::opcodes_process::process_notify_revive_user;
-        if process_notify_revive_user(&mut state.encounter, notify).is_none() {
+        let now_ms = state.clock.now_ms();
+        if process_notify_revive_user(&mut state.encounter, notify, now_ms).is_none() {
             warn!("Error processing NotifyReviveUser.. ignoring.");
         }
     }
 
-    async fn apply_reset_reason(&self, state: &mut AppState, reason: EncounterResetReason) {
+    pub(crate) fn process_sync_client_use_skill(
+        &self,
+        state: &mut AppState,
+        use_skill: blueprotobuf::SyncClientUseSkill,
+    ) {
+        use crate::live::opcodes_process::process_sync_client_use_skill;
+        let now_ms = state.clock.now_ms();
+        if process_sync_client_use_skill(&mut state.encounter, use_skill, now_ms).is_none() {
+            warn!("Error processing SyncClientUseSkill.. ignoring.");
+        }
+    }
+
+    pub(crate) fn process_buff_info_sync(
+        &self,
+        state: &mut AppState,
+        buff_info_sync: blueprotobuf::BuffInfoSync,
+    ) {
+        use crate::live::opcodes_process::process_buff_info_sync;
+        let now_ms = state.clock.now_ms();
+        if process_buff_info_sync(&mut state.encounter, buff_info_sync, now_ms).is_none() {
+            warn!("Error processing BuffInfoSync.. ignoring.");
+        }
+    }
+
+    pub(crate) fn process_get_social_data_reply(
+        &self,
+        state: &mut AppState,
+        reply: blueprotobuf::GetSocialDataReply,
+    ) {
+        use crate::live::opcodes_process::process_get_social_data_reply;
+        if process_get_social_data_reply(&mut state.encounter, &mut state.entity_cache, reply)
+            .is_none()
+        {
+            warn!("Error processing GetSocialDataReply.. ignoring.");
+        }
+    }
+
+    fn apply_reset_reason(&self, state: &mut AppState, reason: EncounterResetReason) {
         let encounter_has_stats = state.encounter.total_dmg > 0
             || state
                 .encounter
@@ -1536,7 +1635,7 @@ impl AppStateManager {
             EncounterResetReason::NewObjective
             | EncounterResetReason::Wipe
             => {
-                let trigger_at = Instant::now() + Duration::from_secs(3);
+                let trigger_at = state.clock.now() + Duration::from_secs(3);
                 state.pending_auto_reset = Some(trigger_at);
                 info!(
                     target: "app::live",
@@ -1547,22 +1646,22 @@ impl AppStateManager {
         }
     }
 
-    async fn apply_battle_state_resets_if_needed(&self, state: &mut AppState) {
-        if let Some(reason) = state.battle_state.check_deferred_calls() {
-            self.apply_reset_reason(state, reason).await;
+    pub(crate) fn apply_battle_state_resets_if_needed(&self, state: &mut AppState) {
+        if let Some(reason) = state.battle_state.check_deferred_calls(state.clock.now()) {
+            self.apply_reset_reason(state, reason);
             return;
         }
 
         if let Some(reason) = state.battle_state.check_for_wipe(&mut state.active_buffs)
         {
-            self.apply_reset_reason(state, reason).await;
+            self.apply_reset_reason(state, reason);
         }
     }
 
-    async fn reset_encounter(&self, state: &mut AppState, is_manual: bool) {
+    fn reset_encounter(&self, state: &mut AppState, is_manual: bool) {
         // Persist dungeon segments if enabled
         if state.dungeon_segments_enabled {
-            dungeon_log::persist_segments(&state.dungeon_log, true);
+            dungeon_log::persist_segments(&state.dungeon_log, true, state.clock.now_ms());
         }
 
         // Persist encounter directly on reset.
@@ -1572,9 +1671,7 @@ impl AppStateManager {
             .entity_uid_to_entity
             .values()
             .filter(|e| {
-                e.entity_type == EEntityType::EntChar
-                    && !e.name.is_empty()
-                    && (e.damage.hits > 0 || e.healing.hits > 0 || e.taken.hits > 0)
+                e.entity_type == EEntityType::EntChar && !e.name.is_empty() && e.has_combat()
             })
             .map(|e| e.name.clone())
             .collect();
@@ -1582,7 +1679,7 @@ impl AppStateManager {
         player_names.dedup();
         let metadata = EncounterMetadata {
             started_at_ms: state.encounter.time_fight_start_ms as i64,
-            ended_at_ms: Some(now_ms()),
+            ended_at_ms: Some(state.clock.now_ms()),
             local_player_id: Some(state.encounter.local_player_uid),
             total_dmg: state.encounter.total_dmg.min(i64::MAX as u128) as i64,
             total_heal: state.encounter.total_heal.min(i64::MAX as u128) as i64,
@@ -1595,6 +1692,7 @@ impl AppStateManager {
                 as f64)
                 / 1000.0,
             is_manually_reset: is_manual,
+            is_incomplete: state.encounter.data_loss_events > 0,
             boss_names: defeated,
             player_names,
         };
@@ -1611,31 +1709,33 @@ impl AppStateManager {
                 metadata.boss_names.len(),
                 metadata.is_manually_reset
             );
-            match save_encounter(&state.encounter, &metadata) {
-                Ok(encounter_id) => {
-                    info!(
-                        target: "app::live",
-                        "persist_encounter_on_reset_ok encounter_id={}",
-                        encounter_id
-                    );
-                }
-                Err(e) => {
-                    warn!(
-                        target: "app::live",
-                        "persist_encounter_on_reset_failed error={}",
-                        e
-                    );
+            if !state.collect_completed_encounter(&metadata) {
+                match save_encounter(&state.encounter, &metadata) {
+                    Ok(encounter_id) => {
+                        info!(
+                            target: "app::live",
+                            "persist_encounter_on_reset_ok encounter_id={}",
+                            encounter_id
+                        );
+                    }
+                    Err(e) => {
+                        warn!(
+                            target: "app::live",
+                            "persist_encounter_on_reset_failed error={}",
+                            e
+                        );
+                    }
                 }
-            }
-            let dirty_entities = state.collect_dirty_entity_cache();
-            if !dirty_entities.is_empty() {
-                if let Err(e) = flush_entity_cache(dirty_entities) {
-                    warn!(target: "app::live", "flush_entity_cache_failed error={}", e);
+                let dirty_entities = state.collect_dirty_entity_cache();
+                if !dirty_entities.is_empty() {
+                    if let Err(e) = flush_entity_cache(dirty_entities) {
+                        warn!(target: "app::live", "flush_entity_cache_failed error={}", e);
+                    }
                 }
-            }
-            if let Some(playerdata) = state.take_dirty_playerdata() {
-                if let Err(e) = flush_playerdata(playerdata) {
-                    warn!(target: "app::live", "flush_playerdata_failed error={}", e);
+                if let Some(playerdata) = state.take_dirty_playerdata() {
+                    if let Err(e) = flush_playerdata(playerdata) {
+                        warn!(target: "app::live", "flush_playerdata_failed error={}", e);
+                    }
                 }
             }
         } else {
@@ -1673,6 +1773,7 @@ impl AppStateManager {
         }
 
         state.low_hp_bosses.clear();
+        state.kill_rate_trackers.clear();
         if is_manual {
             state.battle_state = BattleStateMachine::default();
         }
@@ -1784,6 +1885,14 @@ impl AppStateManager {
         })
     }
 
+    pub async fn start_session_recording(&self, path: PathBuf) -> Result<(), String> {
+        self.send_control(LiveControlCommand::StartSessionRecording(path))
+    }
+
+    pub async fn stop_session_recording(&self) -> Result<(), String> {
+        self.send_control(LiveControlCommand::StopSessionRecording)
+    }
+
     pub fn current_event_update_rate_ms(&self) -> u64 {
         self.snapshot_rx.borrow().event_update_rate_ms
     }
@@ -1919,7 +2028,8 @@ fn dungeon_runtime_if_enabled(state: &AppState) -> Option<DungeonLogRuntime> {
     if state.dungeon_segments_enabled {
         Some(DungeonLogRuntime::new(
             state.dungeon_log.clone(),
-            state.app_handle.clone(),
+            state.event_manager.sink(),
+            state.clock,
         ))
     } else {
         None
@@ -1991,8 +2101,15 @@ impl AppStateManager {
             active_segment.as_ref().and_then(|(_, segment_name)| segment_name.clone()),
         );
 
+        crate::live::ttk::predict_time_to_kill(
+            &mut state.kill_rate_trackers,
+            &state.encounter,
+            &mut payload.bosses,
+            state.clock.now_ms(),
+        );
+
         let mut boss_deaths: Vec<(i64, String)> = Vec::new();
-        let current_time_ms = now_ms() as u128;
+        let current_time_ms = state.clock.now_ms() as u128;
         for boss in &mut payload.bosses {
             let hp_percent = if let (Some(curr_hp), Some(max_hp)) = (boss.current_hp, boss.max_hp) {
                 if max_hp > 0 {
@@ -2015,30 +2132,26 @@ impl AppStateManager {
             }
         }
 
-        let app_handle_opt = state.event_manager.get_app_handle();
         self.publish_snapshot_from_state(state);
-
-        if let Some(app_handle) = app_handle_opt {
-            safe_emit(&app_handle, "live-data", payload);
-
-            if !boss_deaths.is_empty() {
-                let mut any_new_death = false;
-                for (boss_uid, boss_name) in boss_deaths {
-                    let first_time = state.event_manager.emit_boss_death(boss_name, boss_uid);
-                    if first_time {
-                        any_new_death = true;
-                    }
+        state.event_manager.emit("live-data", payload);
+
+        if !boss_deaths.is_empty() {
+            let mut any_new_death = false;
+            for (boss_uid, boss_name) in boss_deaths {
+                let first_time = state.event_manager.emit_boss_death(boss_name, boss_uid);
+                if first_time {
+                    any_new_death = true;
                 }
+            }
 
-                if any_new_death && state.dungeon_segments_enabled {
-                    dungeon_log::persist_segments(&state.dungeon_log, true);
-                }
-                self.publish_snapshot_from_state(state);
+            if any_new_death && state.dungeon_segments_enabled {
+                dungeon_log::persist_segments(&state.dungeon_log, true, state.clock.now_ms());
             }
+            self.publish_snapshot_from_state(state);
         }
 
         if let Some(runtime) = dungeon_ctx {
-            runtime.check_for_timeout(Instant::now());
+            runtime.check_for_timeout();
         }
     }
 }
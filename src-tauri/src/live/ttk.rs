@@ -0,0 +1,263 @@
+//! Time-to-kill prediction for bosses.
+//!
+//! The raid's cumulative damage against a boss is sampled about once a second
+//! and its rate smoothed with a time-weighted moving average, so burst windows
+//! and short downtimes move the prediction without making it jump.
+//!
+//! Enrage times come from `meter-data/BossEnrage.json`, which maps monster type
+//! ids to seconds from the pull. A `BossEnrage.json` in the app data dir
+//! overrides the bundled table, so timers can be added as they are verified
+//! without rebuilding the meter. Bosses without an entry get no enrage check.
+
+use crate::live::commands_models::BossHealth;
+use crate::live::opcodes_models::Encounter;
+use blueprotobuf_lib::blueprotobuf::EEntityType;
+use log::info;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, RwLock};
+
+/// Minimum time between two samples of a boss's damage taken.
+pub const SAMPLE_INTERVAL_MS: i64 = 1_000;
+
+/// Time constant of the rate smoothing; older samples lose weight on this scale.
+pub const SMOOTHING_MS: f64 = 10_000.0;
+
+const BUNDLED_ENRAGE: &str = include_str!("../../meter-data/BossEnrage.json");
+/// File name of the enrage table override in the app data dir.
+pub const ENRAGE_FILE_NAME: &str = "BossEnrage.json";
+
+static ENRAGE_MS: LazyLock<RwLock<Arc<HashMap<i32, u64>>>> = LazyLock::new(|| {
+    let table = parse_enrage_table(BUNDLED_ENRAGE).expect("invalid BossEnrage.json");
+    RwLock::new(Arc::new(table))
+});
+
+/// Parses a `BossEnrage.json` map of monster type id to seconds into milliseconds by id.
+fn parse_enrage_table(json: &str) -> Result<HashMap<i32, u64>, String> {
+    let table: HashMap<String, u64> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    table
+        .into_iter()
+        .map(|(id, secs)| {
+            let id = id
+                .parse::<i32>()
+                .map_err(|_| format!("monster type id {id:?} is not a number"))?;
+            Ok((id, secs * 1_000))
+        })
+        .collect()
+}
+
+/// Path of the enrage table override, if the app data dir is known.
+pub fn override_path(app: &tauri::AppHandle) -> Option<PathBuf> {
+    use tauri::Manager;
+
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(ENRAGE_FILE_NAME))
+}
+
+/// Loads the enrage table from `path` if it exists, or the bundled file otherwise.
+///
+/// The table in use is kept when the file cannot be read or parsed.
+pub fn reload(path: Option<&Path>) -> Result<(), String> {
+    let (table, source) = match path.filter(|path| path.exists()) {
+        Some(path) => {
+            let json = std::fs::read_to_string(path)
+                .map_err(|e| format!("read {}: {}", path.display(), e))?;
+            let table = parse_enrage_table(&json)
+                .map_err(|e| format!("parse {}: {}", path.display(), e))?;
+            (table, path.display().to_string())
+        }
+        None => (parse_enrage_table(BUNDLED_ENRAGE)?, "bundled".to_string()),
+    };
+    info!(
+        target: "app::live",
+        "enrage_table_loaded source={} bosses={}",
+        source,
+        table.len()
+    );
+    *ENRAGE_MS.write().map_err(|e| e.to_string())? = Arc::new(table);
+    Ok(())
+}
+
+/// Time from the pull until a boss of `monster_type_id` enrages, if known.
+pub fn enrage_ms(monster_type_id: i32) -> Option<u64> {
+    ENRAGE_MS
+        .read()
+        .ok()
+        .and_then(|table| table.get(&monster_type_id).copied())
+}
+
+/// Smoothed rate at which the raid damages one boss.
+#[derive(Debug, Clone, Copy)]
+pub struct KillRateTracker {
+    /// When damage against the boss was first seen, standing for the pull.
+    started_at_ms: i64,
+    last_sample_ms: i64,
+    last_damage: u128,
+    rate_per_ms: Option<f64>,
+}
+
+impl KillRateTracker {
+    pub fn new(now_ms: i64, damage: u128) -> Self {
+        Self {
+            started_at_ms: now_ms,
+            last_sample_ms: now_ms,
+            last_damage: damage,
+            rate_per_ms: None,
+        }
+    }
+
+    /// Feeds the raid's cumulative damage against the boss at `now_ms`.
+    pub fn update(&mut self, now_ms: i64, damage: u128) {
+        let elapsed_ms = now_ms - self.last_sample_ms;
+        if elapsed_ms < SAMPLE_INTERVAL_MS {
+            return;
+        }
+        let rate = damage.saturating_sub(self.last_damage) as f64 / elapsed_ms as f64;
+        self.rate_per_ms = Some(match self.rate_per_ms {
+            Some(previous) => {
+                let weight = 1.0 - (-(elapsed_ms as f64) / SMOOTHING_MS).exp();
+                previous + weight * (rate - previous)
+            }
+            None => rate,
+        });
+        self.last_sample_ms = now_ms;
+        self.last_damage = damage;
+    }
+
+    /// Time until `current_hp` is gone at the smoothed rate; `None` until the raid
+    /// has a rate above zero.
+    pub fn time_to_kill_ms(&self, current_hp: i64) -> Option<u64> {
+        let rate = self.rate_per_ms.filter(|rate| *rate > 0.0)?;
+        Some((current_hp.max(0) as f64 / rate).round() as u64)
+    }
+
+    /// Time since the pull.
+    pub fn fight_elapsed_ms(&self, now_ms: i64) -> u64 {
+        u64::try_from(now_ms - self.started_at_ms).unwrap_or(0)
+    }
+}
+
+/// Fills in the time-to-kill and enrage check of `bosses`, updating the trackers
+/// in `trackers` with the raid's damage against each boss so far.
+pub fn predict_time_to_kill(
+    trackers: &mut HashMap<i64, KillRateTracker>,
+    encounter: &Encounter,
+    bosses: &mut [BossHealth],
+    now_ms: i64,
+) {
+    for boss in bosses {
+        let damage: u128 = encounter
+            .entity_uid_to_entity
+            .values()
+            .filter(|entity| entity.entity_type == EEntityType::EntChar)
+            .filter_map(|entity| entity.dmg_to_target.get(&boss.uid))
+            .sum();
+        if damage == 0 {
+            continue;
+        }
+
+        let tracker = trackers
+            .entry(boss.uid)
+            .or_insert_with(|| KillRateTracker::new(now_ms, damage));
+        tracker.update(now_ms, damage);
+
+        boss.time_to_kill_ms = boss
+            .current_hp
+            .and_then(|current_hp| tracker.time_to_kill_ms(current_hp));
+        boss.on_track = boss
+            .enrage_ms
+            .zip(boss.time_to_kill_ms)
+            .map(|(enrage_ms, ttk_ms)| tracker.fight_elapsed_ms(now_ms) + ttk_ms <= enrage_ms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KillRateTracker, parse_enrage_table, predict_time_to_kill};
+    use crate::live::commands_models::BossHealth;
+    use crate::live::opcodes_models::{Encounter, Entity};
+    use blueprotobuf_lib::blueprotobuf::EEntityType;
+    use std::collections::HashMap;
+
+    #[test]
+    fn parses_enrage_table() {
+        let table = parse_enrage_table(r#"{"203": 480}"#).unwrap();
+        assert_eq!(table, HashMap::from([(203, 480_000)]));
+        assert!(parse_enrage_table(r#"{"goblin king": 480}"#).is_err());
+        assert!(parse_enrage_table(include_str!("../../meter-data/BossEnrage.json")).is_ok());
+    }
+
+    #[test]
+    fn checks_time_to_kill_against_enrage() {
+        let player = Entity {
+            entity_type: EEntityType::EntChar,
+            dmg_to_target: HashMap::from([(1, 100), (2, 100), (3, 100)]),
+            ..Default::default()
+        };
+        let mut encounter = Encounter::default();
+        encounter.entity_uid_to_entity.insert(10, player);
+        let boss = |uid, enrage_ms| BossHealth {
+            uid,
+            current_hp: Some(1_000),
+            enrage_ms,
+            ..Default::default()
+        };
+        let mut bosses = [boss(1, Some(60_000)), boss(2, Some(5_000)), boss(3, None)];
+        let mut trackers = HashMap::new();
+
+        predict_time_to_kill(&mut trackers, &encounter, &mut bosses, 0);
+        for damage in encounter
+            .entity_uid_to_entity
+            .get_mut(&10)
+            .unwrap()
+            .dmg_to_target
+            .values_mut()
+        {
+            *damage += 100;
+        }
+        // 100 damage per second: 10 s left, 11 s after the pull
+        predict_time_to_kill(&mut trackers, &encounter, &mut bosses, 1_000);
+
+        let checks: Vec<(Option<u64>, Option<bool>)> = bosses
+            .iter()
+            .map(|boss| (boss.time_to_kill_ms, boss.on_track))
+            .collect();
+        assert_eq!(
+            checks,
+            [
+                (Some(10_000), Some(true)),
+                (Some(10_000), Some(false)),
+                (Some(10_000), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn steady_damage_predicts_remaining_time() {
+        let mut tracker = KillRateTracker::new(0, 0);
+        assert_eq!(tracker.time_to_kill_ms(1_000), None);
+
+        // 100 damage per second
+        for second in 1..=5 {
+            tracker.update(second * 1_000, second as u128 * 100);
+        }
+        assert_eq!(tracker.time_to_kill_ms(1_000), Some(10_000));
+        assert_eq!(tracker.fight_elapsed_ms(5_000), 5_000);
+    }
+
+    #[test]
+    fn rate_changes_are_smoothed() {
+        let mut tracker = KillRateTracker::new(0, 0);
+        tracker.update(1_000, 100);
+        // Too soon for a new sample
+        tracker.update(1_200, 100);
+        assert_eq!(tracker.time_to_kill_ms(1_000), Some(10_000));
+
+        // The raid stops hitting: the rate decays instead of dropping to zero
+        tracker.update(2_000, 100);
+        let ttk_ms = tracker.time_to_kill_ms(1_000).unwrap();
+        assert!(ttk_ms > 10_000 && ttk_ms < 20_000, "{ttk_ms}");
+    }
+}
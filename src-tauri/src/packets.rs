@@ -1,9 +1,20 @@
 // https://doc.rust-lang.org/reference/items/modules.html#module-source-filenames
 // Preferred way is to name modules with their subfolder name now (no longer mod.rs)
+pub mod af_packet;
+pub mod channel;
+pub mod detection;
+pub mod discovery;
+pub mod encoder;
+pub mod flows;
 pub mod npcap;
 pub mod opcodes;
 pub mod packet_capture;
-mod packet_process;
+pub(crate) mod packet_process;
+pub mod pcap_file;
 pub mod parser;
 pub mod reassembler;
+pub mod rpc;
+pub mod session;
+pub mod stats;
 pub mod utils;
+pub mod wire;
@@ -0,0 +1,222 @@
+//! Native Linux capture through AF_PACKET sockets.
+//!
+//! The socket is opened in cooked mode (`SOCK_DGRAM`), so the kernel strips
+//! the link-layer header for us and every interface type (Ethernet, Wi-Fi,
+//! PPP, tun, loopback) hands back plain network-layer packets.
+
+/// Receive buffer large enough for GRO-merged segments.
+#[cfg(target_os = "linux")]
+const RECV_BUFFER_SIZE: usize = 256 * 1024;
+/// Kernel socket buffer so short stalls in our thread do not drop packets.
+#[cfg(target_os = "linux")]
+const SOCKET_RCVBUF_BYTES: libc::c_int = 8 * 1024 * 1024;
+/// `recv` wakes up this often even when idle so restarts are noticed.
+#[cfg(target_os = "linux")]
+const RECV_TIMEOUT_MS: libc::suseconds_t = 500;
+#[cfg(target_os = "linux")]
+const ETH_P_IP: u16 = 0x0800;
+#[cfg(target_os = "linux")]
+const ETH_P_IPV6: u16 = 0x86dd;
+
+/// Lists the names of the network interfaces available for AF_PACKET capture.
+#[tauri::command]
+#[specta::specta]
+pub fn get_af_packet_interfaces() -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir("/sys/class/net")
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// Classic BPF program accepting only IPv4/TCP and IPv6/TCP packets.
+///
+/// Offsets are relative to the network header because the socket runs in
+/// cooked mode; the EtherType comes from the `SKF_AD_PROTOCOL` ancillary load.
+/// IPv6 packets whose first header is an extension header are not matched.
+#[cfg(target_os = "linux")]
+fn tcp_filter_program() -> [libc::sock_filter; 9] {
+    const SKF_AD_PROTOCOL: u32 = 0xffff_f000; // SKF_AD_OFF + SKF_AD_PROTOCOL
+    const IPPROTO_TCP: u32 = 6;
+    const ACCEPT_SNAPLEN: u32 = 0x0004_0000;
+
+    let op = |code: u32, jt: u8, jf: u8, k: u32| libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    };
+    [
+        op(libc::BPF_LD | libc::BPF_H | libc::BPF_ABS, 0, 0, SKF_AD_PROTOCOL),
+        // IPv4: protocol byte at offset 9.
+        op(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, 0, 2, u32::from(ETH_P_IP)),
+        op(libc::BPF_LD | libc::BPF_B | libc::BPF_ABS, 0, 0, 9),
+        op(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, 3, 4, IPPROTO_TCP),
+        // IPv6: next-header byte at offset 6.
+        op(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, 0, 3, u32::from(ETH_P_IPV6)),
+        op(libc::BPF_LD | libc::BPF_B | libc::BPF_ABS, 0, 0, 6),
+        op(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, 0, 1, IPPROTO_TCP),
+        op(libc::BPF_RET | libc::BPF_K, 0, 0, ACCEPT_SNAPLEN),
+        op(libc::BPF_RET | libc::BPF_K, 0, 0, 0),
+    ]
+}
+
+#[cfg(target_os = "linux")]
+pub struct AfPacketCapture {
+    fd: libc::c_int,
+    buffer: Vec<u8>,
+}
+
+#[cfg(target_os = "linux")]
+impl AfPacketCapture {
+    /// Opens a capture socket on `device` (all interfaces when empty).
+    ///
+    /// Requires `CAP_NET_RAW` (or root).
+    pub fn new(device: &str, tcp_filter: bool) -> Result<Self, String> {
+        let protocol = (libc::ETH_P_ALL as u16).to_be();
+        let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_DGRAM, i32::from(protocol)) };
+        if fd < 0 {
+            return Err(format!(
+                "Failed to open AF_PACKET socket (needs CAP_NET_RAW): {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        // From here on `capture` owns the fd and closes it on every error path.
+        let capture = Self {
+            fd,
+            buffer: vec![0u8; RECV_BUFFER_SIZE],
+        };
+
+        if tcp_filter {
+            // Attach the filter before binding so no unfiltered packets get queued.
+            let mut program = tcp_filter_program();
+            let fprog = libc::sock_fprog {
+                len: program.len() as u16,
+                filter: program.as_mut_ptr(),
+            };
+            capture.set_option(libc::SO_ATTACH_FILTER, &fprog, "SO_ATTACH_FILTER")?;
+        }
+
+        capture.set_option(libc::SO_RCVBUF, &SOCKET_RCVBUF_BYTES, "SO_RCVBUF")?;
+        let timeout = libc::timeval {
+            tv_sec: 0,
+            tv_usec: RECV_TIMEOUT_MS * 1000,
+        };
+        capture.set_option(libc::SO_RCVTIMEO, &timeout, "SO_RCVTIMEO")?;
+
+        if !device.is_empty() {
+            let name = std::ffi::CString::new(device)
+                .map_err(|_| format!("Invalid interface name {:?}", device))?;
+            let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+            if ifindex == 0 {
+                return Err(format!("Unknown network interface {}", device));
+            }
+            let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+            addr.sll_family = libc::AF_PACKET as u16;
+            addr.sll_protocol = protocol;
+            addr.sll_ifindex = ifindex as i32;
+            let rc = unsafe {
+                libc::bind(
+                    capture.fd,
+                    &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+                )
+            };
+            if rc < 0 {
+                return Err(format!(
+                    "Failed to bind AF_PACKET socket to {}: {}",
+                    device,
+                    std::io::Error::last_os_error()
+                ));
+            }
+        }
+
+        Ok(capture)
+    }
+
+    fn set_option<T>(&self, option: libc::c_int, value: &T, name: &str) -> Result<(), String> {
+        let rc = unsafe {
+            libc::setsockopt(
+                self.fd,
+                libc::SOL_SOCKET,
+                option,
+                value as *const T as *const libc::c_void,
+                std::mem::size_of::<T>() as libc::socklen_t,
+            )
+        };
+        if rc < 0 {
+            return Err(format!(
+                "setsockopt({}) failed: {}",
+                name,
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the next IPv4/IPv6 packet, or `Ok(None)` on timeout / other traffic.
+    ///
+    /// The packet stays in the receive buffer and is valid until the next call.
+    pub fn next_packet(&mut self) -> Result<Option<&[u8]>, String> {
+        let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        let mut addr_len = std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+        let received = unsafe {
+            libc::recvfrom(
+                self.fd,
+                self.buffer.as_mut_ptr() as *mut libc::c_void,
+                self.buffer.len(),
+                0,
+                &mut addr as *mut libc::sockaddr_ll as *mut libc::sockaddr,
+                &mut addr_len,
+            )
+        };
+        if received < 0 {
+            let err = std::io::Error::last_os_error();
+            return match err.kind() {
+                std::io::ErrorKind::WouldBlock
+                | std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::Interrupted => Ok(None),
+                _ => Err(format!("AF_PACKET recv failed: {}", err)),
+            };
+        }
+        if !matches!(u16::from_be(addr.sll_protocol), ETH_P_IP | ETH_P_IPV6) {
+            return Ok(None);
+        }
+        // Loopback delivers every packet twice (outgoing + incoming); keep one copy.
+        if addr.sll_hatype == libc::ARPHRD_LOOPBACK
+            && addr.sll_pkttype == libc::PACKET_OUTGOING
+        {
+            return Ok(None);
+        }
+        Ok(Some(&self.buffer[..received as usize]))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for AfPacketCapture {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Placeholder so callers compile on platforms without AF_PACKET.
+#[cfg(not(target_os = "linux"))]
+pub struct AfPacketCapture;
+
+#[cfg(not(target_os = "linux"))]
+impl AfPacketCapture {
+    pub fn new(_device: &str, _tcp_filter: bool) -> Result<Self, String> {
+        Err("AF_PACKET capture is only available on Linux".to_string())
+    }
+
+    pub fn next_packet(&mut self) -> Result<Option<&[u8]>, String> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,356 @@
+//! Bounded queue between the capture thread and the live loop.
+//!
+//! The capture thread produces messages as fast as the network delivers them;
+//! when the consumer falls behind, the [`OverflowPolicy`] decides whether the
+//! producer waits or sheds messages. Every shed message is counted in
+//! [`stats`](crate::packets::stats) so the meter's numbers can be trusted.
+
+use crate::packets::opcodes::Pkt;
+use crate::packets::stats;
+use bytes::Bytes;
+use log::{info, warn};
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// Messages the live loop may fall behind by before the policy kicks in.
+pub const CAPTURE_CHANNEL_CAPACITY: usize = 4096;
+/// Minimum time between two overflow warnings.
+const OVERFLOW_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+pub type PacketReceiver = mpsc::Receiver<(Pkt, Bytes)>;
+
+/// What the capture thread does when the live loop cannot keep up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for the consumer; nothing is lost but capture may fall behind the wire.
+    Block,
+    /// Drop low-value opcodes once the queue is three quarters full; combat
+    /// messages still wait for room.
+    #[default]
+    DropLowValue,
+    /// Keep only the newest message of snapshot opcodes while the queue is
+    /// full; everything else waits for room.
+    Coalesce,
+}
+
+impl OverflowPolicy {
+    /// Parses the `overflowPolicy` value of `packetCapture.json`.
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value {
+            "block" => Some(Self::Block),
+            "dropLowValue" => Some(Self::DropLowValue),
+            "coalesce" => Some(Self::Coalesce),
+            _ => None,
+        }
+    }
+}
+
+/// Opcodes that never carry damage, healing, casts or entity state the meter needs.
+fn is_low_value(pkt: Pkt) -> bool {
+    !matches!(
+        pkt,
+        Pkt::ServerChangeInfo
+            | Pkt::DataLoss
+            | Pkt::CaptureTime
+            | Pkt::EnterScene
+            | Pkt::SyncContainerData
+            | Pkt::SyncContainerDirtyData
+            | Pkt::SyncDungeonData
+            | Pkt::SyncDungeonDirtyData
+            | Pkt::SyncToMeDeltaInfo
+            | Pkt::SyncNearDeltaInfo
+            | Pkt::NotifyReviveUser
+            | Pkt::SyncClientUseSkill
+            | Pkt::BuffInfoSync
+            | Pkt::GetSocialDataReply
+    )
+}
+
+/// Opcodes whose messages are full snapshots, so a newer one supersedes an older one.
+fn is_coalescable(pkt: Pkt) -> bool {
+    matches!(
+        pkt,
+        Pkt::SyncServerTime | Pkt::SyncContainerData | Pkt::SyncDungeonData
+    )
+}
+
+/// Creates the capture queue.
+///
+/// # Arguments
+///
+/// * `policy` - How the sender behaves once the queue is full.
+/// * `capacity` - Maximum number of queued messages.
+///
+/// # Returns
+///
+/// * `(PacketSender, PacketReceiver, Arc<AtomicUsize>)` - Both ends and the
+///   shared queue depth, which the receiver decrements for every message it takes.
+pub fn channel(
+    policy: OverflowPolicy,
+    capacity: usize,
+) -> (PacketSender, PacketReceiver, Arc<AtomicUsize>) {
+    let (tx, rx) = mpsc::channel(capacity);
+    let queue_depth = Arc::new(AtomicUsize::new(0));
+    let sender = PacketSender {
+        tx,
+        policy,
+        queue_depth: Arc::clone(&queue_depth),
+        low_value_limit: capacity - capacity / 4,
+        pending: RefCell::new(Vec::new()),
+        shed_since_log: Cell::new(0),
+        last_overflow_log: Cell::new(None),
+    };
+    (sender, rx, queue_depth)
+}
+
+/// Producer end of the capture queue, owned by the capture thread.
+pub struct PacketSender {
+    tx: mpsc::Sender<(Pkt, Bytes)>,
+    policy: OverflowPolicy,
+    queue_depth: Arc<AtomicUsize>,
+    low_value_limit: usize,
+    /// Coalesced messages waiting for room, at most one per opcode, oldest first.
+    pending: RefCell<Vec<(Pkt, Bytes)>>,
+    shed_since_log: Cell<u64>,
+    last_overflow_log: Cell<Option<Instant>>,
+}
+
+impl PacketSender {
+    /// Queues a message according to the overflow policy.
+    ///
+    /// Blocks the calling thread while waiting for room, so it must not be
+    /// called from inside the async runtime. Messages shed by the policy count
+    /// as sent.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error once the receiver has been dropped.
+    pub fn send(&self, pkt: Pkt, data: Bytes) -> Result<(), String> {
+        match self.policy {
+            OverflowPolicy::Block => self.send_blocking(pkt, data),
+            OverflowPolicy::DropLowValue => {
+                if is_low_value(pkt) && self.depth() >= self.low_value_limit {
+                    stats::record_channel_drop(pkt);
+                    self.note_shed();
+                    return Ok(());
+                }
+                self.send_blocking(pkt, data)
+            }
+            OverflowPolicy::Coalesce => {
+                if !is_coalescable(pkt) {
+                    self.flush_pending()?;
+                    return self.send_blocking(pkt, data);
+                }
+                if self.try_flush_pending()? {
+                    match self.tx.try_send((pkt, data)) {
+                        Ok(()) => {
+                            self.queue_depth.fetch_add(1, Ordering::Relaxed);
+                            return Ok(());
+                        }
+                        Err(TrySendError::Closed(_)) => return Err(closed()),
+                        Err(TrySendError::Full((pkt, data))) => self.hold(pkt, data),
+                    }
+                } else {
+                    self.hold(pkt, data);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Queues a [`Pkt::CaptureTime`] marker that stamps the messages after it.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp_ms` - When the following messages were captured, in ms since the Unix epoch.
+    pub fn send_capture_time(&self, timestamp_ms: u64) -> Result<(), String> {
+        self.send(
+            Pkt::CaptureTime,
+            Bytes::copy_from_slice(&timestamp_ms.to_le_bytes()),
+        )
+    }
+
+    /// Number of messages the receiver has not taken yet.
+    fn depth(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
+
+    fn send_blocking(&self, pkt: Pkt, data: Bytes) -> Result<(), String> {
+        self.tx.blocking_send((pkt, data)).map_err(|_| closed())?;
+        self.queue_depth.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Keeps `data` until there is room, replacing an older message of the same opcode.
+    fn hold(&self, pkt: Pkt, data: Bytes) {
+        let mut pending = self.pending.borrow_mut();
+        if let Some(slot) = pending.iter_mut().find(|(held, _)| *held == pkt) {
+            slot.1 = data;
+            stats::record_channel_coalesce(pkt);
+            self.note_shed();
+        } else {
+            pending.push((pkt, data));
+        }
+    }
+
+    /// Sends held messages without waiting; returns whether all of them went out.
+    fn try_flush_pending(&self) -> Result<bool, String> {
+        let mut pending = self.pending.borrow_mut();
+        while !pending.is_empty() {
+            let (pkt, data) = pending.remove(0);
+            match self.tx.try_send((pkt, data)) {
+                Ok(()) => {
+                    self.queue_depth.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(TrySendError::Full(message)) => {
+                    pending.insert(0, message);
+                    return Ok(false);
+                }
+                Err(TrySendError::Closed(_)) => return Err(closed()),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Sends held messages, waiting for room, so they stay ahead of later messages.
+    fn flush_pending(&self) -> Result<(), String> {
+        let pending = std::mem::take(&mut *self.pending.borrow_mut());
+        for (pkt, data) in pending {
+            self.send_blocking(pkt, data)?;
+        }
+        Ok(())
+    }
+
+    fn note_shed(&self) {
+        let shed = self.shed_since_log.get() + 1;
+        self.shed_since_log.set(shed);
+        let due = self
+            .last_overflow_log
+            .get()
+            .is_none_or(|at| at.elapsed() >= OVERFLOW_LOG_INTERVAL);
+        if due {
+            warn!(
+                target: "app::capture",
+                "capture_overflow policy={:?} shed={} depth={}",
+                self.policy,
+                shed,
+                self.depth()
+            );
+            self.shed_since_log.set(0);
+            self.last_overflow_log.set(Some(Instant::now()));
+        }
+    }
+}
+
+impl Drop for PacketSender {
+    fn drop(&mut self) {
+        // Don't lose the newest snapshots when a replay reaches the end of its file.
+        let held = self.pending.borrow().len();
+        if held > 0 && self.flush_pending().is_err() {
+            info!(target: "app::capture", "capture_channel_closed held={}", held);
+        }
+    }
+}
+
+/// Reads the capture time carried by a [`Pkt::CaptureTime`] marker.
+pub fn decode_capture_time(data: &[u8]) -> Option<u64> {
+    data.try_into().ok().map(u64::from_le_bytes)
+}
+
+fn closed() -> String {
+    "capture channel closed".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OverflowPolicy, channel, decode_capture_time};
+    use crate::packets::opcodes::Pkt;
+    use bytes::Bytes;
+
+    fn drain(rx: &mut super::PacketReceiver) -> Vec<(Pkt, Bytes)> {
+        let mut out = Vec::new();
+        while let Ok(message) = rx.try_recv() {
+            out.push(message);
+        }
+        out
+    }
+
+    #[test]
+    fn drops_low_value_opcodes_when_congested() {
+        let (tx, mut rx, depth) = channel(OverflowPolicy::DropLowValue, 4);
+        for _ in 0..3 {
+            tx.send(Pkt::SyncToMeDeltaInfo, Bytes::new()).unwrap();
+        }
+        tx.send(Pkt::SyncNearEntities, Bytes::new()).unwrap();
+        tx.send(Pkt::SyncNearDeltaInfo, Bytes::new()).unwrap();
+
+        let received = drain(&mut rx);
+        assert_eq!(received.len(), 4);
+        assert!(
+            received
+                .iter()
+                .all(|(pkt, _)| *pkt != Pkt::SyncNearEntities)
+        );
+        assert_eq!(depth.load(std::sync::atomic::Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn keeps_buff_snapshots_when_congested() {
+        let (tx, mut rx, _) = channel(OverflowPolicy::DropLowValue, 4);
+        for _ in 0..3 {
+            tx.send(Pkt::SyncToMeDeltaInfo, Bytes::new()).unwrap();
+        }
+        tx.send(Pkt::BuffInfoSync, Bytes::new()).unwrap();
+
+        let (pkt, _) = drain(&mut rx).pop().unwrap();
+        assert_eq!(pkt, Pkt::BuffInfoSync);
+    }
+
+    #[test]
+    fn coalesces_snapshots_and_keeps_order() {
+        let (tx, mut rx, _) = channel(OverflowPolicy::Coalesce, 2);
+        tx.send(Pkt::SyncToMeDeltaInfo, Bytes::new()).unwrap();
+        tx.send(Pkt::SyncToMeDeltaInfo, Bytes::new()).unwrap();
+        tx.send(Pkt::SyncServerTime, Bytes::from_static(b"old"))
+            .unwrap();
+        tx.send(Pkt::SyncServerTime, Bytes::from_static(b"new"))
+            .unwrap();
+        assert_eq!(drain(&mut rx).len(), 2);
+
+        tx.send(Pkt::SyncNearDeltaInfo, Bytes::new()).unwrap();
+        let received = drain(&mut rx);
+        assert_eq!(
+            received,
+            vec![
+                (Pkt::SyncServerTime, Bytes::from_static(b"new")),
+                (Pkt::SyncNearDeltaInfo, Bytes::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn capture_time_markers_survive_congestion() {
+        let (tx, mut rx, _) = channel(OverflowPolicy::DropLowValue, 4);
+        for _ in 0..3 {
+            tx.send(Pkt::SyncToMeDeltaInfo, Bytes::new()).unwrap();
+        }
+        tx.send_capture_time(1_700_000_000_123).unwrap();
+
+        let (pkt, data) = drain(&mut rx).pop().unwrap();
+        assert_eq!(pkt, Pkt::CaptureTime);
+        assert_eq!(decode_capture_time(&data), Some(1_700_000_000_123));
+        assert_eq!(decode_capture_time(b"short"), None);
+    }
+
+    #[test]
+    fn parses_config_values() {
+        assert_eq!(
+            OverflowPolicy::from_config("coalesce"),
+            Some(OverflowPolicy::Coalesce)
+        );
+        assert_eq!(OverflowPolicy::from_config("lossy"), None);
+    }
+}
@@ -0,0 +1,434 @@
+//! Rules that decide which TCP connections are game scene servers.
+//!
+//! The byte signatures used to spot a scene server change with game patches,
+//! so they are read from `meter-data/ServerDetection.json` instead of being
+//! compiled in. A `ServerDetection.json` in the app data dir overrides the
+//! bundled file and can be reloaded while the meter runs. When no rule
+//! matches any more, a server endpoint can be pinned by hand.
+
+use crate::packets::utils::Server;
+use log::{error, info, warn};
+use serde::{Deserialize, Deserializer};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BUNDLED_RULES: &str = include_str!("../../meter-data/ServerDetection.json");
+/// File name of the rules override in the app data dir.
+pub const RULES_FILE_NAME: &str = "ServerDetection.json";
+/// Rule name reported for flows accepted because their server is pinned.
+pub const PINNED_RULE: &str = "pinned";
+
+static DETECTION: LazyLock<RwLock<Detection>> = LazyLock::new(|| {
+    let config = DetectionConfig::parse(BUNDLED_RULES).expect("invalid ServerDetection.json");
+    RwLock::new(Detection {
+        config: Arc::new(config),
+        source: "bundled".to_string(),
+        pinned: None,
+        last_match: None,
+    })
+});
+
+struct Detection {
+    config: Arc<DetectionConfig>,
+    source: String,
+    pinned: Option<SocketAddr>,
+    last_match: Option<DetectionMatch>,
+}
+
+/// Contents of `ServerDetection.json`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DetectionConfig {
+    /// Server ports considered for detection; empty allows every port.
+    #[serde(default)]
+    pub allow_ports: Vec<u16>,
+    /// Server addresses considered for detection; empty allows every address.
+    #[serde(default)]
+    pub allow_ips: Vec<IpAddr>,
+    /// Tried in order; the first rule that matches names the detection.
+    pub rules: Vec<DetectionRule>,
+}
+
+/// A signature for the first server payload of a game connection.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DetectionRule {
+    pub name: String,
+    /// Exact payload length required, if any.
+    #[serde(default)]
+    pub payload_len: Option<usize>,
+    #[serde(default)]
+    pub min_payload_len: usize,
+    /// Bytes that must appear at fixed payload offsets.
+    #[serde(default)]
+    pub matches: Vec<ByteMatch>,
+    /// Bytes that must appear in one of the length-prefixed fragments of the payload.
+    #[serde(default)]
+    pub fragment: Option<FragmentMatch>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ByteMatch {
+    pub offset: usize,
+    /// Hex string; spaces are ignored.
+    #[serde(deserialize_with = "hex_bytes")]
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FragmentMatch {
+    /// Payload offset of the first u32 fragment length.
+    pub start: usize,
+    /// Offset of `bytes` within a fragment, after its length.
+    pub offset: usize,
+    #[serde(deserialize_with = "hex_bytes")]
+    pub bytes: Vec<u8>,
+}
+
+fn hex_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    hex::decode(compact).map_err(serde::de::Error::custom)
+}
+
+impl DetectionConfig {
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let config: Self = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if config.rules.is_empty() {
+            return Err("no detection rules".to_string());
+        }
+        Ok(config)
+    }
+
+    /// Returns the name of the first rule matching a payload sent by `server`.
+    pub fn matching_rule(&self, server: &Server, tcp_payload: &[u8]) -> Option<&str> {
+        let source = server.source();
+        if !self.allow_ports.is_empty() && !self.allow_ports.contains(&source.port()) {
+            return None;
+        }
+        if !self.allow_ips.is_empty() && !self.allow_ips.contains(&source.ip()) {
+            return None;
+        }
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(tcp_payload))
+            .map(|rule| rule.name.as_str())
+    }
+}
+
+impl DetectionRule {
+    fn matches(&self, tcp_payload: &[u8]) -> bool {
+        if self.payload_len.is_some_and(|len| tcp_payload.len() != len)
+            || tcp_payload.len() < self.min_payload_len
+        {
+            return false;
+        }
+        if !self
+            .matches
+            .iter()
+            .all(|m| bytes_at(tcp_payload, m.offset, &m.bytes))
+        {
+            return false;
+        }
+        self.fragment
+            .as_ref()
+            .is_none_or(|fragment| fragment.matches(tcp_payload))
+    }
+}
+
+impl FragmentMatch {
+    fn matches(&self, tcp_payload: &[u8]) -> bool {
+        const FRAG_LENGTH_SIZE: usize = 4;
+        const MAX_FRAG_ITERATIONS: usize = 2000; // Circuit breaker
+
+        let mut i = 0usize;
+        let mut offset = self.start;
+        while tcp_payload.len().saturating_sub(offset) >= FRAG_LENGTH_SIZE {
+            i += 1;
+            if i >= MAX_FRAG_ITERATIONS {
+                error!(
+                    "TCP fragment processing stuck after {i} iterations - forcing recovery. \
+                    remaining={}, line={}",
+                    tcp_payload.len().saturating_sub(offset),
+                    line!()
+                );
+                break;
+            }
+            if i.is_multiple_of(1000) {
+                warn!(
+                    "High iteration count in fragment processing: iteration={i}, remaining={}, line={}",
+                    tcp_payload.len().saturating_sub(offset),
+                    line!()
+                );
+            }
+
+            let len_bytes = &tcp_payload[offset..offset + FRAG_LENGTH_SIZE];
+            let tcp_frag_payload_len =
+                u32::from_be_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]])
+                    .saturating_sub(FRAG_LENGTH_SIZE as u32) as usize;
+            offset += FRAG_LENGTH_SIZE;
+
+            if tcp_payload.len().saturating_sub(offset) < tcp_frag_payload_len {
+                break;
+            }
+
+            let tcp_frag = &tcp_payload[offset..offset + tcp_frag_payload_len];
+            offset += tcp_frag_payload_len;
+
+            if bytes_at(tcp_frag, self.offset, &self.bytes) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+fn bytes_at(data: &[u8], offset: usize, bytes: &[u8]) -> bool {
+    offset
+        .checked_add(bytes.len())
+        .and_then(|end| data.get(offset..end))
+        .is_some_and(|window| window == bytes)
+}
+
+/// The most recent connection identified as a game server.
+#[derive(specta::Type, serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DetectionMatch {
+    /// `server_ip:port -> client_ip:port`.
+    pub flow: String,
+    /// Name of the rule that matched, or `pinned`.
+    pub rule: String,
+    /// When the match happened, in ms since the Unix epoch.
+    pub matched_at_ms: i64,
+}
+
+/// Current detection rules, pin and last match.
+#[derive(specta::Type, serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerDetectionStatus {
+    /// `bundled`, or the path of the override file the rules were loaded from.
+    pub rules_source: String,
+    /// Rule names in the order they are tried.
+    pub rules: Vec<String>,
+    pub allow_ports: Vec<u16>,
+    pub allow_ips: Vec<String>,
+    /// Server endpoint (`ip:port`) accepted without matching any rule.
+    pub pinned_server: Option<String>,
+    pub last_match: Option<DetectionMatch>,
+}
+
+/// Returns the detection rules in use and which rule matched last.
+#[tauri::command]
+#[specta::specta]
+pub fn get_server_detection_status() -> ServerDetectionStatus {
+    status()
+}
+
+/// Reloads the detection rules, preferring `ServerDetection.json` in the app
+/// data dir over the bundled rules.
+///
+/// # Arguments
+///
+/// * `app_handle` - A handle to the Tauri application instance.
+///
+/// # Returns
+///
+/// * `Result<ServerDetectionStatus, String>` - The rules now in use.
+#[tauri::command]
+#[specta::specta]
+pub fn reload_server_detection_rules(
+    app_handle: tauri::AppHandle,
+) -> Result<ServerDetectionStatus, String> {
+    reload(override_path(&app_handle).as_deref())?;
+    Ok(status())
+}
+
+/// Pins a game server so its connections are decoded without matching a rule.
+///
+/// # Arguments
+///
+/// * `endpoint` - The server `ip:port` to pin, or `None` to remove the pin.
+///
+/// # Returns
+///
+/// * `Result<ServerDetectionStatus, String>` - The detection status after the change.
+#[tauri::command]
+#[specta::specta]
+pub fn pin_game_server(endpoint: Option<String>) -> Result<ServerDetectionStatus, String> {
+    let pinned = endpoint
+        .map(|endpoint| {
+            endpoint
+                .trim()
+                .parse::<SocketAddr>()
+                .map_err(|e| format!("Invalid server endpoint '{}': {}", endpoint, e))
+        })
+        .transpose()?;
+    pin(pinned);
+    Ok(status())
+}
+
+/// Path of the rules override file, if the app data dir is known.
+pub fn override_path(app: &tauri::AppHandle) -> Option<PathBuf> {
+    use tauri::Manager;
+
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(RULES_FILE_NAME))
+}
+
+/// Loads the rules from `path` if it exists, or the bundled rules otherwise.
+///
+/// The rules in use are kept when the file cannot be read or parsed.
+pub fn reload(path: Option<&Path>) -> Result<(), String> {
+    let (config, source) = match path.filter(|path| path.exists()) {
+        Some(path) => {
+            let json = std::fs::read_to_string(path)
+                .map_err(|e| format!("read {}: {}", path.display(), e))?;
+            let config = DetectionConfig::parse(&json)
+                .map_err(|e| format!("parse {}: {}", path.display(), e))?;
+            (config, path.display().to_string())
+        }
+        None => (
+            DetectionConfig::parse(BUNDLED_RULES)?,
+            "bundled".to_string(),
+        ),
+    };
+    info!(
+        target: "app::capture",
+        "server_detection_rules_loaded source={} rules={} allow_ports={:?} allow_ips={:?}",
+        source,
+        config.rules.len(),
+        config.allow_ports,
+        config.allow_ips
+    );
+    let mut detection = DETECTION.write().map_err(|e| e.to_string())?;
+    detection.config = Arc::new(config);
+    detection.source = source;
+    Ok(())
+}
+
+/// Pins `server` (or clears the pin with `None`).
+pub fn pin(server: Option<SocketAddr>) {
+    info!(target: "app::capture", "server_pinned endpoint={:?}", server);
+    if let Ok(mut detection) = DETECTION.write() {
+        detection.pinned = server;
+    }
+}
+
+/// Checks whether a TCP payload from an unknown flow comes from a game scene server.
+///
+/// Returns the name of the matching rule, which is also kept as the last match.
+pub fn detect(server: &Server, tcp_payload: &[u8]) -> Option<String> {
+    let (config, pinned) = {
+        let detection = DETECTION.read().ok()?;
+        (Arc::clone(&detection.config), detection.pinned)
+    };
+    let rule = if pinned == Some(server.source()) {
+        PINNED_RULE
+    } else {
+        config.matching_rule(server, tcp_payload)?
+    };
+    if let Ok(mut detection) = DETECTION.write() {
+        detection.last_match = Some(DetectionMatch {
+            flow: server.to_string(),
+            rule: rule.to_string(),
+            matched_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or_default(),
+        });
+    }
+    Some(rule.to_string())
+}
+
+pub fn status() -> ServerDetectionStatus {
+    let detection = DETECTION.read().unwrap_or_else(|e| e.into_inner());
+    ServerDetectionStatus {
+        rules_source: detection.source.clone(),
+        rules: detection
+            .config
+            .rules
+            .iter()
+            .map(|rule| rule.name.clone())
+            .collect(),
+        allow_ports: detection.config.allow_ports.clone(),
+        allow_ips: detection
+            .config
+            .allow_ips
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        pinned_server: detection.pinned.map(|server| server.to_string()),
+        last_match: detection.last_match.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BUNDLED_RULES, DetectionConfig};
+    use crate::packets::utils::Server;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn server(port: u16) -> Server {
+        Server::new(
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            port,
+            IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)),
+            50000,
+        )
+    }
+
+    fn login_return() -> Vec<u8> {
+        let mut payload = vec![0u8; 98];
+        payload[..10]
+            .copy_from_slice(&[0x00, 0x00, 0x00, 0x62, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+        payload[14..20].copy_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x0a, 0x4e]);
+        payload
+    }
+
+    #[test]
+    fn bundled_rules_match_known_signatures() {
+        let config = DetectionConfig::parse(BUNDLED_RULES).unwrap();
+
+        // 10 byte prefix, then one fragment carrying the service signature
+        let mut change = vec![0u8; 10];
+        let fragment = [0, 0, 0, 0, 0, 0x00, 0x63, 0x33, 0x53, 0x42, 0x00];
+        change.extend_from_slice(&((fragment.len() + 4) as u32).to_be_bytes());
+        change.extend_from_slice(&fragment);
+        assert_eq!(
+            config.matching_rule(&server(5003), &change),
+            Some("by change")
+        );
+
+        assert_eq!(
+            config.matching_rule(&server(5003), &login_return()),
+            Some("by Login Return Packet")
+        );
+        assert_eq!(config.matching_rule(&server(5003), &[0u8; 98]), None);
+    }
+
+    #[test]
+    fn allow_list_limits_candidates() {
+        let config = DetectionConfig::parse(
+            r#"{
+                "allowPorts": [5003],
+                "allowIps": ["10.0.0.1"],
+                "rules": [{ "name": "any", "matches": [{ "offset": 0, "bytes": "ab cd" }] }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.matching_rule(&server(5003), &[0xab, 0xcd]),
+            Some("any")
+        );
+        assert_eq!(config.matching_rule(&server(5004), &[0xab, 0xcd]), None);
+        assert_eq!(
+            config.matching_rule(&server(5003).reversed(), &[0xab, 0xcd]),
+            None
+        );
+        assert!(DetectionConfig::parse(r#"{ "rules": [] }"#).is_err());
+    }
+}
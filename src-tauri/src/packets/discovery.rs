@@ -0,0 +1,275 @@
+//! Discovery mode for notify method ids that are missing from [`Pkt`].
+//!
+//! While enabled, every notify whose method id does not map to a [`Pkt`] is
+//! counted in a histogram, and sample payloads can be written to a dump
+//! directory. After a game patch this shows which new messages appeared and
+//! gives their protobuf bodies to work from when extending `opcodes.rs`.
+//!
+//! [`Pkt`]: crate::packets::opcodes::Pkt
+
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Sample files kept per method id; later samples overwrite the oldest slot.
+const SAMPLES_PER_METHOD: u64 = 4;
+/// Method ids that get sample files; further ids are only counted.
+const MAX_DUMPED_METHODS: usize = 256;
+
+/// Checked before taking the lock, so unknown notifies cost nothing while disabled.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DISCOVERY: LazyLock<Mutex<Discovery>> = LazyLock::new(|| Mutex::new(Discovery::default()));
+
+#[derive(Default)]
+struct Discovery {
+    dump_dir: Option<PathBuf>,
+    methods: HashMap<u32, UnknownMethod>,
+    /// Method ids that have sample files.
+    dumped: HashSet<u32>,
+}
+
+/// Histogram entry for one unknown method id.
+#[derive(specta::Type, serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UnknownMethod {
+    pub method_id: u32,
+    /// Messages seen with this method id.
+    pub count: u64,
+    /// Sum of the payload sizes, after zstd decompression.
+    pub total_bytes: u64,
+    pub min_bytes: u64,
+    pub max_bytes: u64,
+    /// Messages that arrived zstd-compressed.
+    pub compressed: u64,
+    /// When the method id was first seen, in ms since the Unix epoch.
+    pub first_seen_ms: i64,
+    /// When the method id was last seen, in ms since the Unix epoch.
+    pub last_seen_ms: i64,
+}
+
+/// Enables or disables discovery of unknown method ids.
+///
+/// Samples are written to `<app log dir>/unknown-methods`, keeping a few
+/// rotating files per method id.
+///
+/// # Arguments
+///
+/// * `enabled` - Whether unknown method ids should be recorded.
+/// * `dump_payloads` - Whether sample payloads should be written to disk.
+/// * `app_handle` - A handle to the Tauri application instance.
+///
+/// # Returns
+///
+/// * `Result<Option<String>, String>` - The dump directory, when payloads are dumped.
+#[tauri::command]
+#[specta::specta]
+pub fn set_method_discovery(
+    enabled: bool,
+    dump_payloads: bool,
+    app_handle: tauri::AppHandle,
+) -> Result<Option<String>, String> {
+    use tauri::Manager;
+
+    if !enabled {
+        disable();
+        return Ok(None);
+    }
+    let dump_dir = if dump_payloads {
+        let dir = app_handle
+            .path()
+            .app_log_dir()
+            .map_err(|e| format!("Failed to get log dir: {}", e))?
+            .join("unknown-methods");
+        Some(dir)
+    } else {
+        None
+    };
+    enable(dump_dir.clone())?;
+    Ok(dump_dir.map(|dir| dir.to_string_lossy().into_owned()))
+}
+
+/// Returns the unknown method ids seen since discovery was enabled, most frequent first.
+#[tauri::command]
+#[specta::specta]
+pub fn get_unknown_methods() -> Vec<UnknownMethod> {
+    unknown_methods()
+}
+
+/// Clears the unknown method histogram.
+#[tauri::command]
+#[specta::specta]
+pub fn clear_unknown_methods() {
+    if let Ok(mut discovery) = DISCOVERY.lock() {
+        discovery.methods.clear();
+        discovery.dumped.clear();
+    }
+}
+
+/// Starts recording unknown method ids, optionally dumping sample payloads to `dump_dir`.
+pub fn enable(dump_dir: Option<PathBuf>) -> Result<(), String> {
+    if let Some(dir) = &dump_dir {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("create_dir_all {}: {}", dir.display(), e))?;
+    }
+    let mut discovery = DISCOVERY.lock().map_err(|e| e.to_string())?;
+    info!(
+        target: "app::capture",
+        "method_discovery_enabled dump_dir={}",
+        dump_dir
+            .as_deref()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_else(|| "-".to_string())
+    );
+    discovery.dump_dir = dump_dir;
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Stops recording; the histogram is kept until [`clear_unknown_methods`].
+pub fn disable() {
+    if let Ok(mut discovery) = DISCOVERY.lock() {
+        if ENABLED.swap(false, Ordering::Relaxed) {
+            info!(
+                target: "app::capture",
+                "method_discovery_disabled unknown_methods={}",
+                discovery.methods.len()
+            );
+        }
+        discovery.dump_dir = None;
+    }
+}
+
+/// Returns the histogram, most frequent method id first.
+pub fn unknown_methods() -> Vec<UnknownMethod> {
+    let mut methods: Vec<UnknownMethod> = DISCOVERY
+        .lock()
+        .map(|discovery| discovery.methods.values().cloned().collect())
+        .unwrap_or_default();
+    methods.sort_by(|a, b| b.count.cmp(&a.count).then(a.method_id.cmp(&b.method_id)));
+    methods
+}
+
+/// Records a notify whose method id is not a known opcode.
+///
+/// `body` is the payload after the 16-byte notify header, still compressed
+/// when `compressed` is set.
+pub fn record_unknown(method_id: u32, body: &[u8], compressed: bool) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Ok(mut guard) = DISCOVERY.lock() else {
+        return;
+    };
+    let discovery = &mut *guard;
+
+    let decoded = if compressed {
+        zstd::decode_all(body).ok()
+    } else {
+        None
+    };
+    let body = decoded.as_deref().unwrap_or(body);
+    let size = body.len() as u64;
+    let now_ms = unix_now_ms();
+
+    let is_new = !discovery.methods.contains_key(&method_id);
+    let entry = discovery
+        .methods
+        .entry(method_id)
+        .or_insert_with(|| UnknownMethod {
+            method_id,
+            count: 0,
+            total_bytes: 0,
+            min_bytes: size,
+            max_bytes: size,
+            compressed: 0,
+            first_seen_ms: now_ms,
+            last_seen_ms: now_ms,
+        });
+    entry.count += 1;
+    entry.total_bytes += size;
+    entry.min_bytes = entry.min_bytes.min(size);
+    entry.max_bytes = entry.max_bytes.max(size);
+    entry.last_seen_ms = now_ms;
+    if compressed {
+        entry.compressed += 1;
+    }
+    let count = entry.count;
+
+    if is_new {
+        info!(
+            target: "app::capture",
+            "unknown_method_discovered method_id={:#x} bytes={}",
+            method_id,
+            size
+        );
+    }
+
+    // Dump the first few messages, then progressively rarer ones, so a busy
+    // method id does not turn into a disk write per message.
+    let wants_sample = count <= SAMPLES_PER_METHOD || count.is_power_of_two();
+    let has_room =
+        discovery.dumped.contains(&method_id) || discovery.dumped.len() < MAX_DUMPED_METHODS;
+    if let Some(dir) = discovery.dump_dir.as_deref()
+        && wants_sample
+        && has_room
+    {
+        discovery.dumped.insert(method_id);
+        write_sample(dir, method_id, count, body);
+    }
+}
+
+fn write_sample(dir: &Path, method_id: u32, count: u64, body: &[u8]) {
+    let slot = (count - 1) % SAMPLES_PER_METHOD;
+    let path = dir.join(format!("method-{method_id:08x}-{slot}.bin"));
+    if let Err(e) = std::fs::write(&path, body) {
+        warn!(
+            target: "app::capture",
+            "unknown_method_dump_failed path={} err={}",
+            path.display(),
+            e
+        );
+    }
+}
+
+fn unix_now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SAMPLES_PER_METHOD, disable, enable, record_unknown, unknown_methods};
+
+    #[test]
+    fn records_histogram_and_rotates_samples() {
+        let dir = std::env::temp_dir().join(format!("rl-discovery-{}", std::process::id()));
+        enable(Some(dir.clone())).unwrap();
+        for size in [3usize, 10, 5, 7, 1, 2] {
+            record_unknown(0xdead_0001, &vec![0u8; size], false);
+        }
+        let compressed = zstd::encode_all(&[1u8; 32][..], 0).unwrap();
+        record_unknown(0xdead_0002, &compressed, true);
+        disable();
+        record_unknown(0xdead_0003, b"ignored", false);
+
+        let methods = unknown_methods();
+        let first = methods.iter().find(|m| m.method_id == 0xdead_0001).unwrap();
+        assert_eq!((first.count, first.min_bytes, first.max_bytes), (6, 1, 10));
+        let second = methods.iter().find(|m| m.method_id == 0xdead_0002).unwrap();
+        assert_eq!((second.total_bytes, second.compressed), (32, 1));
+        assert!(methods.iter().all(|m| m.method_id != 0xdead_0003));
+
+        let samples = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().contains("dead0001"))
+            .count();
+        assert_eq!(samples as u64, SAMPLES_PER_METHOD);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
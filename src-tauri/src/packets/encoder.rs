@@ -0,0 +1,378 @@
+//! Builds server and client frames: the inverse of `process_packet` and the
+//! fragment parsers in [`crate::packets::parser`].
+//!
+//! Every fragment is `[len: u32][type: u16][body]`, big-endian, where `len`
+//! counts the whole fragment and bit 15 of `type` marks a zstd-compressed
+//! body. Tests use this to drive reassembly and decoding with realistic
+//! traffic, and `resonance-analyzer load` uses it to drive the decoder and
+//! live state at raid-scale message rates.
+//! [`TcpFlow`] and [`pcap`] wrap the frames into a capture file that the
+//! replay sources read like a recorded one.
+
+pub use crate::packets::opcodes::Pkt;
+
+use crate::packets::opcodes::FragmentType;
+use crate::packets::parser::SCENE_SERVICE_UUID;
+use blueprotobuf_lib::blueprotobuf;
+use prost::Message;
+use std::borrow::Cow;
+use std::net::SocketAddrV4;
+
+const ZSTD_FLAG: u16 = 0x8000;
+const ZSTD_LEVEL: i32 = 1;
+
+const IPV4_HEADER_LEN: usize = 20;
+const TCP_HEADER_LEN: usize = 20;
+const IPPROTO_TCP: u8 = 6;
+const TCP_FLAGS_PSH_ACK: u8 = 0x18;
+const LINKTYPE_RAW: u32 = 101;
+/// Capture time of the first packet written by [`pcap`], in Unix seconds.
+const PCAP_EPOCH_SECS: u32 = 1_700_000_000;
+
+/// Encodes a notify fragment carrying `message` as `method`.
+pub fn notify(method: Pkt, message: &impl Message, compressed: bool) -> Vec<u8> {
+    notify_raw(method as u32, 0, &message.encode_to_vec(), compressed)
+}
+
+/// Encodes a notify fragment with an arbitrary method id and body.
+///
+/// # Arguments
+///
+/// * `method_id` - The method id, which need not be a known [`Pkt`].
+/// * `stub_id` - The stub id; the parser ignores it for notifies.
+/// * `body` - The protobuf payload.
+/// * `compressed` - Whether the body is zstd-compressed.
+pub fn notify_raw(method_id: u32, stub_id: u32, body: &[u8], compressed: bool) -> Vec<u8> {
+    fragment(
+        FragmentType::Notify,
+        &rpc_header(stub_id, method_id),
+        body,
+        compressed,
+    )
+}
+
+/// Encodes a client Call fragment; only its header is read by the parser.
+pub fn call(stub_id: u32, method_id: u32, body: &[u8]) -> Vec<u8> {
+    fragment(
+        FragmentType::Call,
+        &rpc_header(stub_id, method_id),
+        body,
+        false,
+    )
+}
+
+/// Encodes the Return fragment answering the Call with `stub_id`.
+pub fn reply(stub_id: u32, message: &impl Message, compressed: bool) -> Vec<u8> {
+    fragment(
+        FragmentType::Return,
+        &stub_id.to_be_bytes(),
+        &message.encode_to_vec(),
+        compressed,
+    )
+}
+
+/// Wraps already encoded `frames` in a server -> client FrameDown fragment.
+pub fn frame_down(frames: &[u8], compressed: bool) -> Vec<u8> {
+    // The nested frames follow a 4-byte sequence number the parser skips.
+    fragment(FragmentType::FrameDown, &[0; 4], frames, compressed)
+}
+
+/// Wraps already encoded `frames` in a client -> server FrameUp fragment.
+pub fn frame_up(frames: &[u8], compressed: bool) -> Vec<u8> {
+    fragment(FragmentType::FrameUp, &[0; 4], frames, compressed)
+}
+
+/// Splits an encoded stream into TCP segments of at most `mss` bytes.
+///
+/// # Returns
+///
+/// * `impl Iterator<Item = (u32, &[u8])>` - Each segment with its sequence
+///   number, counted from `initial_seq` and wrapping like TCP does.
+pub fn tcp_segments(
+    stream: &[u8],
+    initial_seq: u32,
+    mss: usize,
+) -> impl Iterator<Item = (u32, &[u8])> {
+    stream.chunks(mss.max(1)).scan(initial_seq, |seq, segment| {
+        let segment_seq = *seq;
+        *seq = seq.wrapping_add(segment.len() as u32);
+        Some((segment_seq, segment))
+    })
+}
+
+/// One direction of a synthetic IPv4 TCP connection.
+pub struct TcpFlow {
+    source: SocketAddrV4,
+    destination: SocketAddrV4,
+    next_seq: u32,
+}
+
+impl TcpFlow {
+    /// Creates a flow whose first payload byte has sequence number `initial_seq`.
+    pub fn new(source: SocketAddrV4, destination: SocketAddrV4, initial_seq: u32) -> Self {
+        Self {
+            source,
+            destination,
+            next_seq: initial_seq,
+        }
+    }
+
+    /// Sends `stream` as IPv4 packets carrying at most `mss` payload bytes each.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Vec<u8>>` - The packets in order, starting with their IPv4 header.
+    pub fn send(&mut self, stream: &[u8], mss: usize) -> Vec<Vec<u8>> {
+        let packets: Vec<_> = tcp_segments(stream, self.next_seq, mss)
+            .map(|(seq, payload)| ipv4_tcp_packet(self.source, self.destination, seq, payload))
+            .collect();
+        self.next_seq = self.next_seq.wrapping_add(stream.len() as u32);
+        packets
+    }
+}
+
+/// Writes `packets` as a classic pcap file of raw IP frames, one millisecond apart.
+pub fn pcap(packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut file = Vec::new();
+    file.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+    file.extend_from_slice(&2u16.to_le_bytes());
+    file.extend_from_slice(&4u16.to_le_bytes());
+    file.extend_from_slice(&0i32.to_le_bytes());
+    file.extend_from_slice(&0u32.to_le_bytes());
+    file.extend_from_slice(&u32::from(u16::MAX).to_le_bytes());
+    file.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+    for (i, packet) in packets.iter().enumerate() {
+        let millis = i as u64;
+        let secs = PCAP_EPOCH_SECS + (millis / 1000) as u32;
+        let micros = (millis % 1000) as u32 * 1000;
+        file.extend_from_slice(&secs.to_le_bytes());
+        file.extend_from_slice(&micros.to_le_bytes());
+        file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        file.extend_from_slice(packet);
+    }
+    file
+}
+
+/// Builds a `SyncNearDeltaInfo` in which `target_uuid` takes `damages`.
+pub fn near_delta_damage(
+    target_uuid: i64,
+    damages: Vec<blueprotobuf::SyncDamageInfo>,
+) -> blueprotobuf::SyncNearDeltaInfo {
+    blueprotobuf::SyncNearDeltaInfo {
+        delta_infos: vec![blueprotobuf::AoiSyncDelta {
+            uuid: Some(target_uuid),
+            skill_effects: Some(blueprotobuf::SkillEffect {
+                uuid: Some(target_uuid),
+                damages,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }],
+    }
+}
+
+/// The header shared by Notify and Call fragments: service uuid, stub id, method id.
+fn rpc_header(stub_id: u32, method_id: u32) -> [u8; 16] {
+    let mut header = [0u8; 16];
+    header[0..8].copy_from_slice(&SCENE_SERVICE_UUID.to_be_bytes());
+    header[8..12].copy_from_slice(&stub_id.to_be_bytes());
+    header[12..16].copy_from_slice(&method_id.to_be_bytes());
+    header
+}
+
+fn ipv4_tcp_packet(
+    source: SocketAddrV4,
+    destination: SocketAddrV4,
+    seq: u32,
+    payload: &[u8],
+) -> Vec<u8> {
+    let tcp_len = TCP_HEADER_LEN + payload.len();
+    let total_len = IPV4_HEADER_LEN + tcp_len;
+    let mut packet = Vec::with_capacity(total_len);
+
+    packet.push(0x45); // IPv4, 5-word header
+    packet.push(0);
+    packet.extend_from_slice(&(total_len as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0x40, 0]); // id 0, don't fragment
+    packet.push(64);
+    packet.push(IPPROTO_TCP);
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend_from_slice(&source.ip().octets());
+    packet.extend_from_slice(&destination.ip().octets());
+    let ip_checksum = checksum(0, &packet);
+    packet[10..12].copy_from_slice(&ip_checksum.to_be_bytes());
+
+    packet.extend_from_slice(&source.port().to_be_bytes());
+    packet.extend_from_slice(&destination.port().to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(&0u32.to_be_bytes());
+    packet.push((TCP_HEADER_LEN as u8 / 4) << 4);
+    packet.push(TCP_FLAGS_PSH_ACK);
+    packet.extend_from_slice(&u16::MAX.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 0]); // checksum, urgent pointer
+    packet.extend_from_slice(payload);
+
+    let mut pseudo_header = [0u8; 12];
+    pseudo_header[0..4].copy_from_slice(&source.ip().octets());
+    pseudo_header[4..8].copy_from_slice(&destination.ip().octets());
+    pseudo_header[9] = IPPROTO_TCP;
+    pseudo_header[10..12].copy_from_slice(&(tcp_len as u16).to_be_bytes());
+    let tcp_checksum = checksum(sum_words(0, &pseudo_header), &packet[IPV4_HEADER_LEN..]);
+    packet[IPV4_HEADER_LEN + 16..IPV4_HEADER_LEN + 18].copy_from_slice(&tcp_checksum.to_be_bytes());
+    packet
+}
+
+/// Adds `data` to a running ones' complement sum of big-endian 16-bit words.
+fn sum_words(mut sum: u32, data: &[u8]) -> u32 {
+    let mut words = data.chunks_exact(2);
+    for word in &mut words {
+        sum += u32::from(u16::from_be_bytes([word[0], word[1]]));
+    }
+    if let [last] = words.remainder() {
+        sum += u32::from(*last) << 8;
+    }
+    sum
+}
+
+/// The internet checksum of `data`, continuing from a partial `sum`.
+fn checksum(sum: u32, data: &[u8]) -> u16 {
+    let mut sum = sum_words(sum, data);
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn fragment(kind: FragmentType, header: &[u8], body: &[u8], compressed: bool) -> Vec<u8> {
+    let body = if compressed {
+        Cow::Owned(
+            zstd::encode_all(body, ZSTD_LEVEL).expect("zstd compression of an in-memory buffer"),
+        )
+    } else {
+        Cow::Borrowed(body)
+    };
+    let mut fragment_type = kind as u16;
+    if compressed {
+        fragment_type |= ZSTD_FLAG;
+    }
+
+    let len = 6 + header.len() + body.len();
+    let mut frame = Vec::with_capacity(len);
+    frame.extend_from_slice(&(len as u32).to_be_bytes());
+    frame.extend_from_slice(&fragment_type.to_be_bytes());
+    frame.extend_from_slice(header);
+    frame.extend_from_slice(&body);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::channel::{OverflowPolicy, channel};
+    use crate::packets::packet_process::{process_packet, process_upstream_packet};
+    use crate::packets::pcap_file::PcapReader;
+    use crate::packets::reassembler::Reassembler;
+    use crate::packets::rpc::RpcTracker;
+    use crate::packets::utils::TCPReassembler;
+    use bytes::Bytes;
+    use std::collections::HashMap;
+    use std::net::Ipv4Addr;
+    use std::time::Instant;
+
+    fn hit(attacker_uuid: i64, value: i64) -> blueprotobuf::SyncDamageInfo {
+        blueprotobuf::SyncDamageInfo {
+            attacker_uuid: Some(attacker_uuid),
+            value: Some(value),
+            owner_id: Some(1),
+            ..Default::default()
+        }
+    }
+
+    /// Runs `stream` through TCP and frame reassembly and the frame parser.
+    fn decode_stream(stream: &[u8], mss: usize, rpc: &mut RpcTracker) -> Vec<(Pkt, Bytes)> {
+        let (tx, mut rx, _) = channel(OverflowPolicy::Block, 1024);
+        let mut tcp = TCPReassembler::new();
+        let mut frames = Reassembler::new();
+        for (seq, segment) in tcp_segments(stream, u32::MAX - 40, mss) {
+            if let Some(data) = tcp.insert_segment_at(seq, segment, Instant::now()) {
+                frames.feed(data);
+            }
+            while let Some(frame) = frames.try_next() {
+                process_packet(&frame, rpc, &tx);
+            }
+        }
+        let mut out = Vec::new();
+        while let Ok(message) = rx.try_recv() {
+            out.push(message);
+        }
+        out
+    }
+
+    #[test]
+    fn notifies_round_trip_through_reassembly() {
+        let first = near_delta_damage(42, vec![hit(7, 1_000), hit(7, 250)]);
+        let second = near_delta_damage(43, vec![hit(8, 99)]);
+        let mut nested = notify(Pkt::SyncNearDeltaInfo, &first, true);
+        nested.extend(notify(Pkt::SyncNearDeltaInfo, &second, false));
+        let mut stream = frame_down(&nested, true);
+        stream.extend(notify(Pkt::SyncNearDeltaInfo, &first, false));
+        stream.extend(notify_raw(0x7fff_0000, 0, b"unknown", false));
+
+        let received = decode_stream(&stream, 7, &mut RpcTracker::new());
+        let decoded: Vec<_> = received
+            .into_iter()
+            .map(|(pkt, data)| {
+                assert_eq!(pkt, Pkt::SyncNearDeltaInfo);
+                blueprotobuf::SyncNearDeltaInfo::decode(data).unwrap()
+            })
+            .collect();
+        assert_eq!(decoded, vec![first.clone(), second, first]);
+    }
+
+    #[test]
+    fn replies_are_routed_to_their_call() {
+        let mut rpc = RpcTracker::with_routes(HashMap::from([(0x1234, Pkt::GetSocialDataReply)]));
+        let upstream = frame_up(&call(9, 0x1234, b"request"), false);
+        let mut frames = Reassembler::new();
+        frames.push(&upstream);
+        process_upstream_packet(&frames.try_next().unwrap(), &mut rpc);
+
+        let body = blueprotobuf::GetSocialDataReply::default();
+        let mut stream = reply(8, &body, false);
+        stream.extend(reply(9, &body, true));
+        let received = decode_stream(&stream, 1460, &mut rpc);
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].0, Pkt::GetSocialDataReply);
+    }
+
+    #[test]
+    fn captures_read_back_as_checksummed_tcp_segments() {
+        let server = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 5003);
+        let client = SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 2), 50000);
+        let stream: Vec<u8> = (0..=250).collect();
+        let mut flow = TcpFlow::new(server, client, u32::MAX - 100);
+        let mut packets = flow.send(&stream, 100);
+        packets.extend(flow.send(b"tail", 100));
+        let file = pcap(&packets);
+
+        let mut reader = PcapReader::new(file.as_slice()).unwrap();
+        let mut payload = Vec::new();
+        let mut seqs = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            assert_eq!(record.link_type, LINKTYPE_RAW);
+            let (ip, tcp) = record.data.split_at(IPV4_HEADER_LEN);
+            assert_eq!(checksum(0, ip), 0);
+            let mut pseudo_header = [0u8; 12];
+            pseudo_header[0..8].copy_from_slice(&ip[12..20]);
+            pseudo_header[9] = IPPROTO_TCP;
+            pseudo_header[10..12].copy_from_slice(&(tcp.len() as u16).to_be_bytes());
+            assert_eq!(checksum(sum_words(0, &pseudo_header), tcp), 0);
+            seqs.push(u32::from_be_bytes(tcp[4..8].try_into().unwrap()));
+            payload.extend_from_slice(&tcp[TCP_HEADER_LEN..]);
+        }
+        assert_eq!(seqs, vec![u32::MAX - 100, u32::MAX, 99, 150]);
+        assert_eq!(payload[..stream.len()], stream[..]);
+        assert_eq!(&payload[stream.len()..], b"tail");
+    }
+}
@@ -0,0 +1,333 @@
+//! Reassembly state for every identified game connection.
+//!
+//! Each flow is keyed by its server -> client 4-tuple and owns its own TCP and
+//! frame reassemblers, so a second scene connection (or a quick line switch)
+//! is decoded alongside the old one instead of replacing it. The client ->
+//! server half of a flow is only reassembled to see RPC calls.
+
+use crate::packets::reassembler::Reassembler;
+use crate::packets::rpc::RpcTracker;
+use crate::packets::utils::{DEFAULT_GAP_TIMEOUT, Server, TCPReassembler};
+use log::info;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A flow that carries no payload for this long is forgotten.
+const FLOW_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+/// How often idle flows are swept and the published list refreshed.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Latest view of the capture thread's flows, read by [`get_active_flows`].
+static ACTIVE_FLOWS: LazyLock<Mutex<Vec<ActiveFlow>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+/// A game connection currently being decoded.
+#[derive(specta::Type, serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveFlow {
+    /// `server_ip:port -> client_ip:port`.
+    pub flow: String,
+    /// When the flow was identified as a game connection, in ms since the Unix epoch.
+    pub identified_at_ms: i64,
+    /// When the flow last carried payload, in ms since the Unix epoch.
+    pub last_seen_ms: i64,
+    /// TCP payload bytes received since the flow was identified.
+    pub payload_bytes: u64,
+    /// Name of the detection rule that identified the flow, or `pinned`.
+    pub detected_by: String,
+}
+
+/// Returns the game connections the capture thread is currently decoding.
+#[tauri::command]
+#[specta::specta]
+pub fn get_active_flows() -> Vec<ActiveFlow> {
+    ACTIVE_FLOWS
+        .lock()
+        .map(|flows| flows.clone())
+        .unwrap_or_default()
+}
+
+pub struct Flow {
+    pub tcp_reassembler: TCPReassembler,
+    pub reassembler: Reassembler,
+    /// Client -> server reassembly, started on the first client payload.
+    pub upstream: Option<Upstream>,
+    pub rpc: RpcTracker,
+    gap_timeout: Duration,
+    detected_by: String,
+    identified_at_ms: i64,
+    last_seen: Instant,
+    last_seen_ms: i64,
+    payload_bytes: u64,
+}
+
+impl Flow {
+    fn new(next_seq: u32, detected_by: &str, gap_timeout: Duration, now: Instant) -> Self {
+        let mut tcp_reassembler = TCPReassembler::with_gap_timeout(gap_timeout);
+        tcp_reassembler.reset(Some(next_seq));
+        let now_ms = unix_now_ms();
+        Self {
+            tcp_reassembler,
+            reassembler: Reassembler::new(),
+            upstream: None,
+            rpc: RpcTracker::new(),
+            gap_timeout,
+            detected_by: detected_by.to_string(),
+            identified_at_ms: now_ms,
+            last_seen: now,
+            last_seen_ms: now_ms,
+            payload_bytes: 0,
+        }
+    }
+
+    /// Drops everything buffered in both reassemblers.
+    pub fn reset(&mut self, next_seq: Option<u32>) {
+        self.reassembler.take_remaining();
+        self.tcp_reassembler.reset(next_seq);
+    }
+
+    /// Returns the client -> server reassemblers, starting them at `seq` if
+    /// needed, along with the RPC tracker they feed.
+    pub fn upstream(&mut self, seq: u32) -> (&mut Upstream, &mut RpcTracker) {
+        let gap_timeout = self.gap_timeout;
+        let upstream = self
+            .upstream
+            .get_or_insert_with(|| Upstream::new(seq, gap_timeout));
+        (upstream, &mut self.rpc)
+    }
+
+    /// Marks the flow as active after receiving `payload_len` bytes.
+    pub fn touch(&mut self, payload_len: usize, now: Instant) {
+        self.last_seen = now;
+        self.last_seen_ms = unix_now_ms();
+        self.payload_bytes += payload_len as u64;
+    }
+}
+
+/// Reassembly state for the client -> server half of a flow.
+pub struct Upstream {
+    pub tcp_reassembler: TCPReassembler,
+    pub reassembler: Reassembler,
+}
+
+impl Upstream {
+    fn new(next_seq: u32, gap_timeout: Duration) -> Self {
+        let mut tcp_reassembler = TCPReassembler::with_gap_timeout(gap_timeout);
+        tcp_reassembler.reset(Some(next_seq));
+        Self {
+            tcp_reassembler,
+            reassembler: Reassembler::new(),
+        }
+    }
+
+    /// Drops everything buffered in both reassemblers.
+    pub fn reset(&mut self, next_seq: Option<u32>) {
+        self.reassembler.take_remaining();
+        self.tcp_reassembler.reset(next_seq);
+    }
+}
+
+/// Identified game flows keyed by their server -> client 4-tuple.
+pub struct FlowTable {
+    flows: HashMap<Server, Flow>,
+    last_sweep: Instant,
+    gap_timeout: Duration,
+}
+
+impl FlowTable {
+    pub fn new() -> Self {
+        Self::with_gap_timeout(DEFAULT_GAP_TIMEOUT)
+    }
+
+    /// Creates a table whose flows give up on a missing segment after `gap_timeout`.
+    pub fn with_gap_timeout(gap_timeout: Duration) -> Self {
+        Self {
+            flows: HashMap::new(),
+            last_sweep: Instant::now(),
+            gap_timeout,
+        }
+    }
+
+    pub fn get_mut(&mut self, server: &Server) -> Option<&mut Flow> {
+        self.flows.get_mut(server)
+    }
+
+    /// Looks up the flow that `client` (a client -> server 4-tuple) is the upstream half of.
+    pub fn get_upstream_mut(&mut self, client: &Server) -> Option<&mut Flow> {
+        self.flows.get_mut(&client.reversed())
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Starts decoding `server` from sequence number `next_seq`, replacing any
+    /// previous state for the same 4-tuple. `detected_by` names the detection
+    /// rule that identified it.
+    pub fn insert(&mut self, server: Server, next_seq: u32, detected_by: &str, now: Instant) {
+        self.flows.insert(
+            server,
+            Flow::new(next_seq, detected_by, self.gap_timeout, now),
+        );
+        info!(
+            target: "app::capture",
+            "flow_added flow={} detected_by={} active_flows={}",
+            server,
+            detected_by,
+            self.flows.len()
+        );
+        self.publish();
+    }
+
+    pub fn remove(&mut self, server: &Server, reason: &str) {
+        if self.flows.remove(server).is_some() {
+            info!(
+                target: "app::capture",
+                "flow_removed flow={} reason={} active_flows={}",
+                server,
+                reason,
+                self.flows.len()
+            );
+            self.publish();
+        }
+    }
+
+    /// Forgets idle flows and refreshes the published list at most once per
+    /// [`SWEEP_INTERVAL`]; cheap enough to call for every packet.
+    pub fn maintain(&mut self, now: Instant) {
+        if now.saturating_duration_since(self.last_sweep) < SWEEP_INTERVAL {
+            return;
+        }
+        self.last_sweep = now;
+
+        let before = self.flows.len();
+        self.flows.retain(|server, flow| {
+            let idle = now.saturating_duration_since(flow.last_seen);
+            if idle < FLOW_IDLE_TIMEOUT {
+                return true;
+            }
+            info!(
+                target: "app::capture",
+                "flow_removed flow={} reason=idle idle_secs={}",
+                server,
+                idle.as_secs()
+            );
+            false
+        });
+        if self.flows.len() != before {
+            info!(target: "app::capture", "active_flows={}", self.flows.len());
+        }
+        self.publish();
+    }
+
+    fn publish(&self) {
+        let mut active: Vec<ActiveFlow> = self
+            .flows
+            .iter()
+            .map(|(server, flow)| ActiveFlow {
+                flow: server.to_string(),
+                identified_at_ms: flow.identified_at_ms,
+                last_seen_ms: flow.last_seen_ms,
+                payload_bytes: flow.payload_bytes,
+                detected_by: flow.detected_by.clone(),
+            })
+            .collect();
+        active.sort_by_key(|flow| flow.identified_at_ms);
+        if let Ok(mut published) = ACTIVE_FLOWS.lock() {
+            *published = active;
+        }
+    }
+}
+
+impl Default for FlowTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for FlowTable {
+    fn drop(&mut self) {
+        // The capture loop has exited; nothing is being decoded any more.
+        if let Ok(mut published) = ACTIVE_FLOWS.lock() {
+            published.clear();
+        }
+    }
+}
+
+fn unix_now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FLOW_IDLE_TIMEOUT, FlowTable, SWEEP_INTERVAL};
+    use crate::packets::utils::Server;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::{Duration, Instant};
+
+    fn server(client_port: u16) -> Server {
+        Server::new(
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            5003,
+            IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)),
+            client_port,
+        )
+    }
+
+    #[test]
+    fn keeps_independent_state_per_flow() {
+        let now = Instant::now();
+        let mut flows = FlowTable::new();
+        assert!(flows.is_empty());
+        flows.insert(server(50000), 100, "test", now);
+        flows.insert(server(50001), 9000, "test", now);
+        assert_eq!(flows.len(), 2);
+
+        let first = flows.get_mut(&server(50000)).unwrap();
+        assert_eq!(
+            first.tcp_reassembler.insert_segment(100, b"abc"),
+            Some(b"abc".to_vec())
+        );
+        let second = flows.get_mut(&server(50001)).unwrap();
+        assert_eq!(second.tcp_reassembler.next_sequence(), Some(9000));
+    }
+
+    #[test]
+    fn finds_flow_from_client_direction() {
+        let now = Instant::now();
+        let mut flows = FlowTable::new();
+        flows.insert(server(50000), 100, "test", now);
+
+        assert!(flows.get_mut(&server(50000).reversed()).is_none());
+        let flow = flows.get_upstream_mut(&server(50000).reversed()).unwrap();
+        assert_eq!(flow.upstream(7).0.tcp_reassembler.next_sequence(), Some(7));
+        assert!(flows.get_upstream_mut(&server(50001).reversed()).is_none());
+    }
+
+    #[test]
+    fn expires_idle_flows_only() {
+        let start = Instant::now();
+        let mut flows = FlowTable::new();
+        flows.insert(server(50000), 0, "test", start);
+        flows.insert(server(50001), 0, "test", start);
+
+        let later = start + FLOW_IDLE_TIMEOUT + SWEEP_INTERVAL;
+        flows
+            .get_mut(&server(50001))
+            .unwrap()
+            .touch(10, later - Duration::from_secs(1));
+        flows.maintain(later);
+
+        assert!(flows.get_mut(&server(50000)).is_none());
+        assert!(flows.get_mut(&server(50001)).is_some());
+    }
+}
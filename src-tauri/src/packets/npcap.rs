@@ -171,7 +171,11 @@ impl NpcapCapture {
         self.data_link
     }
 
-    pub fn next_packet(&self) -> Result<Option<Vec<u8>>, String> {
+    /// Returns the next captured frame, including its link-layer header.
+    ///
+    /// The frame points into the driver's buffer, which is only valid until the
+    /// next call, hence the `&mut self` borrow.
+    pub fn next_packet(&mut self) -> Result<Option<&[u8]>, String> {
         unsafe {
             let next_ex: Symbol<PcapNextEx> =
                 self.lib.get(b"pcap_next_ex").map_err(|e| e.to_string())?;
@@ -185,10 +189,7 @@ impl NpcapCapture {
                 1 => {
                     // Success
                     let len = (*header).caplen as usize;
-                    let mut packet_data = Vec::with_capacity(len);
-                    ptr::copy_nonoverlapping(data, packet_data.as_mut_ptr(), len);
-                    packet_data.set_len(len);
-                    Ok(Some(packet_data))
+                    Ok(Some(std::slice::from_raw_parts(data, len)))
                 }
                 0 => Ok(None), // Timeout
                 -1 => Err("Error reading packet".to_string()),
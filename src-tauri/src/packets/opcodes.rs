@@ -2,7 +2,7 @@
 pub struct ParseError;
 
 #[non_exhaustive]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Pkt {
     ServerChangeInfo,
     // TODO: change all these names
@@ -54,6 +54,18 @@ pub enum Pkt {
     // 0x45-0x47 skipped
     NotifyTimerList = 0x00000048,
     NotifyTimerUpdate = 0x00000049,
+    // RPC replies routed by packets::rpc; ids are outside the notify method id range
+    GetSocialDataReply = 0x00010001,
+    // Raised by the capture thread when stream bytes were lost; carries no payload
+    DataLoss = 0x00020000,
+    // Raised by replay sources before messages captured at a new millisecond;
+    // carries the capture time in ms since the Unix epoch (u64, little-endian)
+    CaptureTime = 0x00020001,
+}
+
+impl Pkt {
+    /// Opcodes that stand for RPC replies rather than notify method ids.
+    pub const RPC_REPLIES: [Pkt; 1] = [Pkt::GetSocialDataReply];
 }
 
 impl TryFrom<u32> for Pkt {
@@ -1,20 +1,38 @@
-use crate::packets;
+use crate::packets::af_packet::AfPacketCapture;
+use crate::packets::channel::{
+    self, CAPTURE_CHANNEL_CAPACITY, OverflowPolicy, PacketReceiver, PacketSender,
+};
+use crate::packets::detection;
+use crate::packets::flows::{Flow, FlowTable};
 use crate::packets::npcap::NpcapCapture;
 use crate::packets::opcodes::Pkt;
-use crate::packets::packet_process::process_packet;
-use crate::packets::reassembler::Reassembler;
-use crate::packets::utils::{Server, TCPReassembler, tcp_sequence_before};
+use crate::packets::packet_process::{process_packet, process_upstream_packet};
+use crate::packets::pcap_file::{PcapReader, PcapRecord};
+use crate::packets::session;
+use crate::packets::stats;
+use crate::packets::utils::{DEFAULT_GAP_TIMEOUT, Server, tcp_sequence_before};
 use bytes::Bytes;
-use etherparse::NetSlice::Ipv4;
-use etherparse::SlicedPacket;
+use etherparse::NetSlice::{Ipv4, Ipv6};
+use etherparse::{SlicedPacket, TcpSlice};
 use etherparse::TransportSlice::Tcp;
 use log::{debug, error, info, warn};
 use once_cell::sync::OnceCell;
-use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(windows)]
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use tokio::sync::watch;
+#[cfg(windows)]
 use windivert::WinDivert;
+#[cfg(windows)]
 use windivert::prelude::NetworkLayer;
+#[cfg(windows)]
 use windivert::prelude::WinDivertFlags;
 
 // Global sender for restart signal
@@ -27,26 +45,110 @@ const DLT_NULL: i32 = 0;
 const DLT_EN10MB: i32 = 1;
 const DLT_RAW: i32 = 12;
 const DLT_LOOP: i32 = 108;
+// LINKTYPE_* values that only show up in capture files.
+const LINKTYPE_RAW: i32 = 101;
+const LINKTYPE_LINUX_SLL: i32 = 113;
+const LINKTYPE_IPV4: i32 = 228;
+const LINKTYPE_IPV6: i32 = 229;
+const LINKTYPE_LINUX_SLL2: i32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+/// 802.1Q VLAN tag.
+const ETHERTYPE_VLAN: u16 = 0x8100;
+/// 802.1ad service tag (outer tag of QinQ).
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+/// Pre-standard QinQ outer tag still emitted by some switches.
+const ETHERTYPE_QINQ_LEGACY: u16 = 0x9100;
 
 #[derive(Clone, Debug)]
 pub enum CaptureMethod {
     WinDivert,
     Npcap(String),
+    /// Linux AF_PACKET socket on the named interface (all interfaces when empty).
+    AfPacket {
+        device: String,
+        /// Attach a kernel BPF filter so only TCP over IPv4/IPv6 reaches userspace.
+        tcp_filter: bool,
+    },
+    /// Replay a pcap/pcapng file recorded with Wireshark/tcpdump.
+    PcapFile(PathBuf, ReplayTiming),
+    /// Replay a decoded session recorded by `packets::session`.
+    SessionFile(PathBuf, ReplayTiming),
 }
 
+impl CaptureMethod {
+    /// Whether this replays a recording instead of capturing live traffic.
+    pub fn is_replay(&self) -> bool {
+        matches!(self, Self::PcapFile(..) | Self::SessionFile(..))
+    }
+}
+
+/// Tuning of the capture pipeline, read from the capture config.
+#[derive(Clone, Copy, Debug)]
+pub struct CaptureOptions {
+    /// What the capture queue does when the live loop falls behind.
+    pub overflow_policy: OverflowPolicy,
+    /// How long a missing TCP segment is waited for before its bytes are given up.
+    pub gap_timeout: Duration,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            overflow_policy: OverflowPolicy::default(),
+            gap_timeout: DEFAULT_GAP_TIMEOUT,
+        }
+    }
+}
+
+/// Pacing used when replaying a capture or session file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayTiming {
+    /// Feed packets as fast as the pipeline accepts them.
+    Fast,
+    /// Sleep between packets to reproduce the original capture timing.
+    Original,
+}
+
+/// A packet and when it was captured, in ms since the Unix epoch, if recorded.
+type TimedPacket<'a> = (&'a [u8], Option<u64>);
+
+/// A capture backend.
+///
+/// Packets are returned as views into the source's own receive buffer, which is
+/// reused for the next packet, so nothing is allocated or copied for the many
+/// packets that turn out not to be game traffic. Bytes of a game flow are
+/// copied once, into its `Reassembler`.
 trait PacketSource: Send {
-    fn next_packet(&mut self) -> Result<Option<Vec<u8>>, String>;
+    /// Returns the next IPv4/IPv6 packet, valid until the next call.
+    fn next_packet(&mut self) -> Result<Option<&[u8]>, String>;
+
+    /// Like `next_packet`, also returning when a recorded packet was captured,
+    /// in ms since the Unix epoch. Live packets are stamped by the live state.
+    fn next_timed_packet(&mut self) -> Result<Option<TimedPacket<'_>>, String> {
+        Ok(self.next_packet()?.map(|packet| (packet, None)))
+    }
+
+    /// Finite sources (capture files) return true once they have no more packets.
+    fn is_exhausted(&self) -> bool {
+        false
+    }
 }
 
+#[cfg(windows)]
 struct WinDivertSource {
     handle: WinDivert<NetworkLayer>,
     buffer: Vec<u8>,
+    /// Holds a packet that WinDivert returned outside of `buffer`.
+    owned: Vec<u8>,
 }
 
+#[cfg(windows)]
 impl WinDivertSource {
     fn new() -> Result<Self, String> {
         let handle = WinDivert::network(
-            "!loopback && ip && tcp",
+            "!loopback && (ip || ipv6) && tcp",
             0,
             WinDivertFlags::new().set_sniff(),
         )
@@ -57,16 +159,25 @@ impl WinDivertSource {
         Ok(Self {
             handle,
             buffer: vec![0u8; 10 * 1024 * 1024],
+            owned: Vec::new(),
         })
     }
 }
 
+#[cfg(windows)]
 impl PacketSource for WinDivertSource {
-    fn next_packet(&mut self) -> Result<Option<Vec<u8>>, String> {
-        self.handle
+    fn next_packet(&mut self) -> Result<Option<&[u8]>, String> {
+        let packet = self
+            .handle
             .recv(Some(&mut self.buffer))
-            .map(|packet| Some(packet.data.to_vec()))
-            .map_err(|e| e.to_string())
+            .map_err(|e| e.to_string())?;
+        match packet.data {
+            Cow::Borrowed(data) => Ok(Some(data)),
+            Cow::Owned(data) => {
+                self.owned = data;
+                Ok(Some(&self.owned))
+            }
+        }
     }
 }
 
@@ -80,68 +191,201 @@ impl NpcapSource {
         info!(target: "app::capture", "Npcap handle opened device={}", device);
         Ok(Self { capture })
     }
+}
 
-    fn strip_l2(&self, data: &[u8]) -> Option<Vec<u8>> {
-        match self.capture.datalink() {
-            DLT_EN10MB => {
-                if data.len() > 14 && data[12] == 0x08 && data[13] == 0x00 {
-                    Some(data[14..].to_vec())
-                } else {
-                    None
-                }
+impl PacketSource for NpcapSource {
+    fn next_packet(&mut self) -> Result<Option<&[u8]>, String> {
+        let datalink = self.capture.datalink();
+        match self.capture.next_packet()? {
+            Some(data) => Ok(strip_link_layer(datalink, data)),
+            None => Ok(None),
+        }
+    }
+}
+
+struct AfPacketSource {
+    capture: AfPacketCapture,
+}
+
+impl AfPacketSource {
+    fn new(device: &str, tcp_filter: bool) -> Result<Self, String> {
+        let capture = AfPacketCapture::new(device, tcp_filter)?;
+        info!(
+            target: "app::capture",
+            "AF_PACKET socket opened device={} tcp_filter={}",
+            if device.is_empty() { "any" } else { device },
+            tcp_filter
+        );
+        Ok(Self { capture })
+    }
+}
+
+impl PacketSource for AfPacketSource {
+    fn next_packet(&mut self) -> Result<Option<&[u8]>, String> {
+        // Cooked-mode socket: packets already start at the IP header.
+        self.capture.next_packet()
+    }
+}
+
+struct PcapFileSource {
+    reader: PcapReader<BufReader<File>>,
+    timing: ReplayTiming,
+    /// First capture timestamp and the wall-clock instant it was replayed at.
+    clock_origin: Option<(u64, Instant)>,
+    /// The last record read; its frame buffer is reused for the next one.
+    record: PcapRecord,
+    exhausted: bool,
+}
+
+impl PcapFileSource {
+    fn new(path: &Path, timing: ReplayTiming) -> Result<Self, String> {
+        let reader = PcapReader::open(path)?;
+        info!(
+            target: "app::capture",
+            "Capture file opened path={} timing={:?}",
+            path.display(),
+            timing
+        );
+        Ok(Self {
+            reader,
+            timing,
+            clock_origin: None,
+            record: PcapRecord::default(),
+            exhausted: false,
+        })
+    }
+
+    fn pace(&mut self, timestamp_ns: u64) {
+        if self.timing != ReplayTiming::Original {
+            return;
+        }
+        let (first_ns, started_at) = *self
+            .clock_origin
+            .get_or_insert((timestamp_ns, Instant::now()));
+        let target = Duration::from_nanos(timestamp_ns.saturating_sub(first_ns));
+        let elapsed = started_at.elapsed();
+        if target > elapsed {
+            std::thread::sleep(target - elapsed);
+        }
+    }
+}
+
+impl PacketSource for PcapFileSource {
+    fn next_packet(&mut self) -> Result<Option<&[u8]>, String> {
+        Ok(self.next_timed_packet()?.map(|(packet, _)| packet))
+    }
+
+    fn next_timed_packet(&mut self) -> Result<Option<TimedPacket<'_>>, String> {
+        if !self.reader.next_record_into(&mut self.record)? {
+            self.exhausted = true;
+            return Ok(None);
+        }
+        self.pace(self.record.timestamp_ns);
+        let captured_at_ms = self.record.timestamp_ns / 1_000_000;
+        let link_type = i32::try_from(self.record.link_type).unwrap_or(-1);
+        Ok(strip_link_layer(link_type, &self.record.data)
+            .map(|packet| (packet, Some(captured_at_ms))))
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+}
+
+/// Strips the link-layer header for the given DLT/LINKTYPE value and returns
+/// the IPv4/IPv6 packet, or None for frames we cannot (or do not want to) handle.
+fn strip_link_layer(link_type: i32, data: &[u8]) -> Option<&[u8]> {
+    match link_type {
+        DLT_EN10MB => {
+            if data.len() < 14 {
+                return None;
             }
-            DLT_RAW => Some(data.to_vec()),
-            DLT_NULL | DLT_LOOP => {
-                if data.len() <= 4 {
-                    return None;
-                }
-                let family = u32::from_ne_bytes([data[0], data[1], data[2], data[3]]);
-                match family {
-                    2 => Some(data[4..].to_vec()), // AF_INET on Windows
-                    23 | 24 => None,               // IPv6 families, ignored for now
-                    other => {
-                        static LOGGED_FAMILY: OnceLock<u32> = OnceLock::new();
-                        if LOGGED_FAMILY.set(other).is_ok() {
-                            warn!(
-                                "Unsupported DLT_NULL/LOOP family {} (datalink {}), dropping packets",
-                                other,
-                                self.capture.datalink()
-                            );
-                        }
-                        None
+            ip_after_ethertype(u16::from_be_bytes([data[12], data[13]]), &data[14..])
+        }
+        DLT_RAW | LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => Some(data),
+        LINKTYPE_LINUX_SLL => {
+            // 16-byte cooked header, protocol type in the last two bytes.
+            if data.len() < 16 {
+                return None;
+            }
+            ip_after_ethertype(u16::from_be_bytes([data[14], data[15]]), &data[16..])
+        }
+        LINKTYPE_LINUX_SLL2 => {
+            // 20-byte cooked v2 header, protocol type in the first two bytes.
+            if data.len() < 20 {
+                return None;
+            }
+            ip_after_ethertype(u16::from_be_bytes([data[0], data[1]]), &data[20..])
+        }
+        DLT_NULL | DLT_LOOP => {
+            if data.len() <= 4 {
+                return None;
+            }
+            // DLT_NULL is host byte order, DLT_LOOP network byte order; AF_INET is 2 everywhere.
+            let family = if link_type == DLT_LOOP {
+                u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+            } else {
+                u32::from_ne_bytes([data[0], data[1], data[2], data[3]])
+            };
+            match family {
+                2 => Some(&data[4..]), // AF_INET
+                // AF_INET6 differs per OS: Windows 23, BSDs 24, FreeBSD 28, macOS 30.
+                23 | 24 | 28 | 30 => Some(&data[4..]),
+                other => {
+                    static LOGGED_FAMILY: OnceLock<u32> = OnceLock::new();
+                    if LOGGED_FAMILY.set(other).is_ok() {
+                        warn!(
+                            "Unsupported DLT_NULL/LOOP family {} (datalink {}), dropping packets",
+                            other,
+                            link_type
+                        );
                     }
+                    None
                 }
             }
-            other => {
-                static LOGGED_DLT: OnceLock<i32> = OnceLock::new();
-                if LOGGED_DLT.set(other).is_ok() {
-                    warn!("Unsupported Npcap datalink type {}, dropping packets", other);
-                }
-                None
+        }
+        other => {
+            static LOGGED_DLT: OnceLock<i32> = OnceLock::new();
+            if LOGGED_DLT.set(other).is_ok() {
+                warn!("Unsupported datalink type {}, dropping packets", other);
             }
+            None
         }
     }
 }
 
-impl PacketSource for NpcapSource {
-    fn next_packet(&mut self) -> Result<Option<Vec<u8>>, String> {
-        match self.capture.next_packet()? {
-            Some(data) => Ok(self.strip_l2(&data)),
-            None => Ok(None),
+/// Returns the IP packet following `ethertype`, peeling any 802.1Q/QinQ tags first.
+fn ip_after_ethertype(mut ethertype: u16, mut payload: &[u8]) -> Option<&[u8]> {
+    while matches!(
+        ethertype,
+        ETHERTYPE_VLAN | ETHERTYPE_QINQ | ETHERTYPE_QINQ_LEGACY
+    ) {
+        // Each tag is a 2-byte TCI followed by the inner EtherType.
+        if payload.len() < 4 {
+            return None;
         }
+        ethertype = u16::from_be_bytes([payload[2], payload[3]]);
+        payload = &payload[4..];
+    }
+    match ethertype {
+        ETHERTYPE_IPV4 | ETHERTYPE_IPV6 if !payload.is_empty() => Some(payload),
+        _ => None,
     }
 }
 
 pub fn start_capture(
     method: CaptureMethod,
-) -> (
-    tokio::sync::mpsc::UnboundedReceiver<(packets::opcodes::Pkt, Bytes)>,
-    Arc<AtomicUsize>,
-) {
-    let (packet_sender, packet_receiver) =
-        tokio::sync::mpsc::unbounded_channel::<(packets::opcodes::Pkt, Bytes)>();
-    let queue_depth = Arc::new(AtomicUsize::new(0));
-    let capture_queue_depth = Arc::clone(&queue_depth);
+    options: CaptureOptions,
+) -> (PacketReceiver, Arc<AtomicUsize>) {
+    let (packet_sender, packet_receiver, queue_depth) =
+        channel::channel(options.overflow_policy, CAPTURE_CHANNEL_CAPACITY);
+    info!(
+        target: "app::capture",
+        "capture_channel capacity={} overflow_policy={:?} gap_timeout_ms={}",
+        CAPTURE_CHANNEL_CAPACITY,
+        options.overflow_policy,
+        options.gap_timeout.as_millis()
+    );
     let (restart_sender, mut restart_receiver) = watch::channel(false);
     RESTART_SENDER.set(restart_sender.clone()).ok();
 
@@ -152,6 +396,30 @@ pub fn start_capture(
         CaptureMethod::Npcap(dev) => {
             info!(target: "app::capture", "capture_start method=Npcap device={}", dev)
         }
+        CaptureMethod::AfPacket { device, tcp_filter } => {
+            info!(
+                target: "app::capture",
+                "capture_start method=AfPacket device={} tcp_filter={}",
+                device,
+                tcp_filter
+            )
+        }
+        CaptureMethod::PcapFile(path, timing) => {
+            info!(
+                target: "app::capture",
+                "capture_start method=PcapFile path={} timing={:?}",
+                path.display(),
+                timing
+            )
+        }
+        CaptureMethod::SessionFile(path, timing) => {
+            info!(
+                target: "app::capture",
+                "capture_start method=SessionFile path={} timing={:?}",
+                path.display(),
+                timing
+            )
+        }
     }
 
     // Use std::thread::spawn to avoid blocking the async runtime with WinDivert recv
@@ -159,13 +427,30 @@ pub fn start_capture(
         let capture_span = tracing::info_span!(target: "app::capture", "capture_thread", method = ?method);
         let _capture_guard = capture_span.enter();
         loop {
-            read_packets(
-                &packet_sender,
-                &capture_queue_depth,
-                &mut restart_receiver,
-                method.clone(),
-            );
-            
+            // Failures are logged where they happen; the loop below restarts the capture.
+            let _ = match &method {
+                CaptureMethod::SessionFile(path, timing) => {
+                    session::replay_session(&packet_sender, &restart_receiver, path, *timing)
+                }
+                _ => read_packets(
+                    &packet_sender,
+                    &mut restart_receiver,
+                    method.clone(),
+                    options.gap_timeout,
+                ),
+            };
+
+            // A replay that reached the end of its file stays idle until a restart
+            // is requested instead of looping over the same file forever.
+            if method.is_replay() && !*restart_receiver.borrow() {
+                info!(target: "app::capture", "Capture file replay finished; waiting for restart");
+                while !*restart_receiver.borrow() {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+                let _ = restart_sender.send(false);
+                continue;
+            }
+
             // Check if this was a requested restart or a crash/exit
             if !*restart_receiver.borrow() {
                 warn!("Packet capture exited unexpectedly. Restarting in 1s...");
@@ -185,24 +470,64 @@ pub fn start_capture(
     (packet_receiver, queue_depth)
 }
 
+/// Replays a pcap or session file once and closes the channel at the end of the file.
+///
+/// Unlike [`start_capture`] this never restarts, so consumers see a clean end of stream.
+/// The queue always blocks when full, so every message in the file is delivered.
+///
+/// The returned handle yields the replay's outcome once the channel has closed: an
+/// error means the file could not be opened or was cut short by a read error.
+pub fn replay_file(method: CaptureMethod) -> (PacketReceiver, JoinHandle<Result<(), String>>) {
+    let (packet_sender, packet_receiver, _queue_depth) =
+        channel::channel(OverflowPolicy::Block, CAPTURE_CHANNEL_CAPACITY);
+
+    let handle = std::thread::spawn(move || {
+        let (_restart_sender, mut restart_receiver) = watch::channel(false);
+        // Dropping the sender on return closes the channel.
+        match &method {
+            CaptureMethod::SessionFile(path, timing) => {
+                session::replay_session(&packet_sender, &restart_receiver, path, *timing)
+            }
+            CaptureMethod::PcapFile(..) => read_packets(
+                &packet_sender,
+                &mut restart_receiver,
+                method.clone(),
+                DEFAULT_GAP_TIMEOUT,
+            ),
+            _ => Err(format!(
+                "replay_file called with a live capture method {:?}",
+                method
+            )),
+        }
+    });
+    (packet_receiver, handle)
+}
+
 #[allow(clippy::too_many_lines)]
 fn read_packets(
-    packet_sender: &tokio::sync::mpsc::UnboundedSender<(packets::opcodes::Pkt, Bytes)>,
-    queue_depth: &AtomicUsize,
+    packet_sender: &PacketSender,
     restart_receiver: &mut watch::Receiver<bool>,
     method: CaptureMethod,
-) {
+    gap_timeout: Duration,
+) -> Result<(), String> {
     let read_span = tracing::info_span!(target: "app::capture", "capture_read_loop", method = ?method);
     let _read_guard = read_span.enter();
 
     let mut source: Box<dyn PacketSource> = match method {
+        #[cfg(windows)]
         CaptureMethod::WinDivert => match WinDivertSource::new() {
             Ok(s) => Box::new(s),
             Err(e) => {
                 error!(target: "app::capture", "capture_source_init_failed method=WinDivert err={}", e);
-                return;
+                return Err(e);
             }
         },
+        #[cfg(not(windows))]
+        CaptureMethod::WinDivert => {
+            let e = "WinDivert capture is only available on Windows".to_string();
+            error!(target: "app::capture", "capture_source_init_failed method=WinDivert err={}", e);
+            return Err(e);
+        }
         CaptureMethod::Npcap(device) => match NpcapSource::new(&device) {
             Ok(s) => Box::new(s),
             Err(e) => {
@@ -212,42 +537,102 @@ fn read_packets(
                     device,
                     e
                 );
-                return;
+                return Err(e);
             }
         },
+        CaptureMethod::AfPacket { device, tcp_filter } => {
+            match AfPacketSource::new(&device, tcp_filter) {
+                Ok(s) => Box::new(s),
+                Err(e) => {
+                    error!(
+                        target: "app::capture",
+                        "capture_source_init_failed method=AfPacket device={} err={}",
+                        device,
+                        e
+                    );
+                    return Err(e);
+                }
+            }
+        }
+        CaptureMethod::PcapFile(path, timing) => match PcapFileSource::new(&path, timing) {
+            Ok(s) => Box::new(s),
+            Err(e) => {
+                error!(
+                    target: "app::capture",
+                    "capture_source_init_failed method=PcapFile path={} err={}",
+                    path.display(),
+                    e
+                );
+                return Err(e);
+            }
+        },
+        CaptureMethod::SessionFile(..) => {
+            error!(target: "app::capture", "SessionFile is replayed above the packet layer");
+            return Err("SessionFile is replayed above the packet layer".to_string());
+        }
     };
 
-    let mut known_server: Option<Server> = None; // nothing at start
-    let mut tcp_reassembler: TCPReassembler = TCPReassembler::new();
-    let mut reassembler = Reassembler::new();
+    let mut flows = FlowTable::with_gap_timeout(gap_timeout);
+    let mut last_capture_time_ms = None;
 
     loop {
-        let packet_data = match source.next_packet() {
-            Ok(Some(data)) => data,
-            Ok(None) => continue, // Timeout or ignored packet
+        flows.maintain(Instant::now());
+        // Checked up front: the packet below borrows `source` for the rest of the iteration.
+        if source.is_exhausted() {
+            info!(target: "app::capture", "capture_source_exhausted");
+            break;
+        }
+        let packet_data = match source.next_timed_packet() {
+            Ok(Some((data, captured_at_ms))) => {
+                if let Some(ms) = captured_at_ms
+                    && last_capture_time_ms != Some(ms)
+                {
+                    last_capture_time_ms = Some(ms);
+                    if let Err(err) = packet_sender.send_capture_time(ms) {
+                        debug!("Failed to send capture time: {err}");
+                    }
+                }
+                data
+            }
+            Ok(None) => continue, // Timeout, ignored packet or end of file
             Err(e) => {
                 error!(target: "app::capture", "capture_error err={}", e);
-                break; // Exit loop on error? Or retry?
+                return Err(e);
             }
         };
 
+        stats::record_packet(packet_data.len());
+
         // info!("{}", line!());
-        let Ok(network_slices) = SlicedPacket::from_ip(&packet_data) else {
+        let Ok(network_slices) = SlicedPacket::from_ip(packet_data) else {
+            stats::record_non_game_packet();
             continue; // if it's not ip, go next packet
         };
         // info!("{}", line!());
-        let Some(Ipv4(ip_packet)) = network_slices.net else {
-            continue;
+        let (src_addr, dst_addr) = match &network_slices.net {
+            Some(Ipv4(ip_packet)) => (
+                IpAddr::V4(ip_packet.header().source_addr()),
+                IpAddr::V4(ip_packet.header().destination_addr()),
+            ),
+            Some(Ipv6(ip_packet)) => (
+                IpAddr::V6(ip_packet.header().source_addr()),
+                IpAddr::V6(ip_packet.header().destination_addr()),
+            ),
+            _ => {
+                stats::record_non_game_packet();
+                continue;
+            }
         };
         // info!("{}", line!());
         let Some(Tcp(tcp_packet)) = network_slices.transport else {
+            stats::record_non_game_packet();
             continue;
         };
         // info!("{}", line!());
         let curr_server = Server::new(
-            ip_packet.header().source(),
+            src_addr,
             tcp_packet.to_header().source_port,
-            ip_packet.header().destination(),
+            dst_addr,
             tcp_packet.to_header().destination_port,
         );
         // trace!(
@@ -257,98 +642,36 @@ fn read_packets(
         //     tcp_packet.payload(),
         // );
 
-        // 1. Try to identify game server via small packets
-        if known_server != Some(curr_server) {
-            let tcp_payload = tcp_packet.payload();
-            if tcp_payload.len() >= 10 && tcp_payload[4] == 0 {
-                const FRAG_LENGTH_SIZE: usize = 4;
-                const SIGNATURE: [u8; 6] = [0x00, 0x63, 0x33, 0x53, 0x42, 0x00];
-                const MAX_FRAG_ITERATIONS: usize = 2000; // Circuit breaker
-
-                let mut i = 0usize;
-                let mut offset = 10usize;
-                while tcp_payload.len().saturating_sub(offset) >= FRAG_LENGTH_SIZE {
-                    i += 1;
-                    if i >= MAX_FRAG_ITERATIONS {
-                        error!(
-                            "TCP fragment processing stuck after {i} iterations - forcing recovery. \
-                            remaining={}, line={}",
-                            tcp_payload.len().saturating_sub(offset),
-                            line!()
-                        );
-                        break;
-                    }
-                    if i % 1000 == 0 {
-                        warn!(
-                            "High iteration count in fragment processing: iteration={i}, remaining={}, line={}",
-                            tcp_payload.len().saturating_sub(offset),
-                            line!()
-                        );
-                    }
-
-                    let len_bytes = &tcp_payload[offset..offset + FRAG_LENGTH_SIZE];
-                    let tcp_frag_payload_len = u32::from_be_bytes([
-                        len_bytes[0],
-                        len_bytes[1],
-                        len_bytes[2],
-                        len_bytes[3],
-                    ])
-                    .saturating_sub(FRAG_LENGTH_SIZE as u32) as usize;
-                    offset += FRAG_LENGTH_SIZE;
-
-                    if tcp_payload.len().saturating_sub(offset) < tcp_frag_payload_len {
-                        break;
-                    }
-
-                    let tcp_frag = &tcp_payload[offset..offset + tcp_frag_payload_len];
-                    offset += tcp_frag_payload_len;
-
-                    if tcp_frag.len() >= 5 + SIGNATURE.len()
-                        && tcp_frag[5..5 + SIGNATURE.len()] == SIGNATURE
-                    {
-                        info!(
-                            target: "app::capture",
-                            "Got Scene Server Address (by change): {curr_server}"
-                        );
-                        known_server = Some(curr_server);
-                        let payload_len = u32::try_from(tcp_payload.len()).unwrap_or(u32::MAX);
-                        let seq_end = tcp_packet.sequence_number().wrapping_add(payload_len);
-                        reset_stream(&mut tcp_reassembler, &mut reassembler, Some(seq_end));
-                        if let Err(err) = packet_sender.send((Pkt::ServerChangeInfo, Bytes::new()))
-                        {
-                            debug!("Failed to send packet: {err}");
-                        } else {
-                            queue_depth.fetch_add(1, Ordering::Relaxed);
-                        }
-                    }
-                }
+        let Some(flow) = flows.get_mut(&curr_server) else {
+            // Client -> server half of a known flow: only RPC calls are of interest.
+            if let Some(flow) = flows.get_upstream_mut(&curr_server) {
+                process_upstream_segment(flow, &tcp_packet);
+                continue;
             }
-            // 2. Payload length is 98 = Login packets?
-            if tcp_payload.len() == 98 {
-                const SIGNATURE_1: [u8; 10] =
-                    [0x00, 0x00, 0x00, 0x62, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01];
-                const SIGNATURE_2: [u8; 6] = [0x00, 0x00, 0x00, 0x00, 0x0a, 0x4e];
-                if tcp_payload.len() >= 20
-                    && tcp_payload[0..10] == SIGNATURE_1
-                    && tcp_payload[14..20] == SIGNATURE_2
+            // Unknown flow: check whether it is a game server connection.
+            let tcp_payload = tcp_packet.payload();
+            if let Some(detected_by) = detection::detect(&curr_server, tcp_payload) {
+                info!(
+                    target: "app::capture",
+                    "Got Scene Server Address ({detected_by}): {curr_server}"
+                );
+                let payload_len = u32::try_from(tcp_payload.len()).unwrap_or(u32::MAX);
+                let seq_end = tcp_packet.sequence_number().wrapping_add(payload_len);
+                flows.insert(curr_server, seq_end, &detected_by, Instant::now());
+                if detected_by == detection::PINNED_RULE
+                    && let Some(flow) = flows.get_mut(&curr_server)
                 {
-                    info!(
-                        target: "app::capture",
-                        "Got Scene Server Address by Login Return Packet: {curr_server}"
-                    );
-                    known_server = Some(curr_server);
-                    let payload_len = u32::try_from(tcp_payload.len()).unwrap_or(u32::MAX);
-                    let seq_end = tcp_packet.sequence_number().wrapping_add(payload_len);
-                    reset_stream(&mut tcp_reassembler, &mut reassembler, Some(seq_end));
-                    if let Err(err) = packet_sender.send((Pkt::ServerChangeInfo, Bytes::new())) {
-                        debug!("Failed to send packet: {err}");
-                    } else {
-                        queue_depth.fetch_add(1, Ordering::Relaxed);
-                    }
+                    // A pinned connection is picked up mid-stream, not on a frame boundary.
+                    flow.reassembler.resync();
                 }
+                if let Err(err) = packet_sender.send(Pkt::ServerChangeInfo, Bytes::new()) {
+                    debug!("Failed to send packet: {err}");
+                }
+            } else {
+                stats::record_non_game_packet();
             }
             continue;
-        }
+        };
 
         let sequence_number = tcp_packet.sequence_number();
         let payload = tcp_packet.payload();
@@ -359,29 +682,23 @@ fn read_packets(
                 target: "app::capture",
                 "SYN observed for {curr_server}; resetting TCP reassembler state"
             );
-            reset_stream(
-                &mut tcp_reassembler,
-                &mut reassembler,
-                Some(sequence_number.wrapping_add(1)),
-            );
+            flow.reset(Some(sequence_number.wrapping_add(1)));
             if payload_len == 0 {
                 continue;
             }
         }
 
-        let mut defer_reset = false;
-        if tcp_packet.fin() || tcp_packet.rst() {
-            defer_reset = true;
-        }
+        let closing = tcp_packet.fin() || tcp_packet.rst();
 
         if payload_len == 0 {
-            if defer_reset {
-                reset_stream(&mut tcp_reassembler, &mut reassembler, None);
+            if closing {
+                flows.remove(&curr_server, "closed");
             }
             continue;
         }
+        flow.touch(payload_len, Instant::now());
 
-        if let Some(expected) = tcp_reassembler.next_sequence() {
+        if let Some(expected) = flow.tcp_reassembler.next_sequence() {
             if tcp_sequence_before(sequence_number, expected) {
                 let backwards = expected.wrapping_sub(sequence_number);
                 if backwards > MAX_BACKTRACK_BYTES {
@@ -390,31 +707,86 @@ fn read_packets(
                         "Sequence regression detected for {curr_server}: expected {expected}, \
                         got {sequence_number} (backwards {backwards} bytes). Resetting stream"
                     );
-                    reset_stream(
-                        &mut tcp_reassembler,
-                        &mut reassembler,
-                        Some(sequence_number),
-                    );
+                    stats::record_sequence_regression();
+                    flow.reset(Some(sequence_number));
+                    flow.reassembler.resync();
+                    send_data_loss(packet_sender);
                 }
             }
         }
 
-        if let Some(buffer) = tcp_reassembler.insert_segment(sequence_number, payload) {
-            reassembler.feed_owned(buffer);
+        if let Some(buffer) = flow
+            .tcp_reassembler
+            .insert_segment_at(sequence_number, payload, Instant::now())
+        {
+            if flow.tcp_reassembler.take_lost_bytes() > 0 {
+                // The buffered partial frame is cut; find the next frame boundary.
+                flow.reassembler.resync();
+                send_data_loss(packet_sender);
+            }
+            flow.reassembler.feed(buffer);
         }
 
-        while let Some(packet) = reassembler.try_next() {
-            process_packet(&packet, packet_sender, queue_depth);
+        while let Some(packet) = flow.reassembler.try_next() {
+            process_packet(&packet, &mut flow.rpc, packet_sender);
+        }
+        if flow.reassembler.take_lost_bytes() > 0 {
+            // Frames were dropped while resyncing on a frame boundary.
+            send_data_loss(packet_sender);
         }
 
-        if defer_reset {
-            reset_stream(&mut tcp_reassembler, &mut reassembler, None);
+        if closing {
+            flows.remove(&curr_server, "closed");
         }
         if *restart_receiver.borrow() {
             break;
         }
     } // todo: if it errors, it breaks out of the loop but will it ever error?
     // info!("{}", line!());
+    Ok(())
+}
+
+/// Feeds a client -> server segment of `flow` and records the RPC calls it completes.
+fn process_upstream_segment(flow: &mut Flow, tcp_packet: &TcpSlice) {
+    let sequence_number = tcp_packet.sequence_number();
+    let payload = tcp_packet.payload();
+    let (upstream, rpc) = flow.upstream(sequence_number);
+    if tcp_packet.syn() {
+        upstream.reset(Some(sequence_number.wrapping_add(1)));
+    }
+    if payload.is_empty() {
+        return;
+    }
+    if let Some(expected) = upstream.tcp_reassembler.next_sequence()
+        && tcp_sequence_before(sequence_number, expected)
+        && expected.wrapping_sub(sequence_number) > MAX_BACKTRACK_BYTES
+    {
+        upstream.reset(Some(sequence_number));
+        upstream.reassembler.resync();
+    }
+
+    if let Some(buffer) = upstream
+        .tcp_reassembler
+        .insert_segment_at(sequence_number, payload, Instant::now())
+    {
+        if upstream.tcp_reassembler.take_lost_bytes() > 0 {
+            upstream.reassembler.resync();
+        }
+        upstream.reassembler.feed(buffer);
+    }
+    while let Some(packet) = upstream.reassembler.try_next() {
+        process_upstream_packet(&packet, rpc);
+    }
+    // Lost client frames only cost the replies of their calls, like lost segments above.
+    upstream.reassembler.take_lost_bytes();
+}
+
+/// Tells the live loop that part of the game stream was lost, so the current
+/// encounter cannot be trusted to be complete.
+fn send_data_loss(packet_sender: &PacketSender) {
+    if let Err(err) = packet_sender.send(Pkt::DataLoss, Bytes::new()) {
+        debug!("Failed to send packet: {err}");
+    }
 }
 
 // Function to send restart signal from another thread/task
@@ -425,11 +797,64 @@ pub fn request_restart() {
     }
 }
 
-fn reset_stream(
-    tcp_reassembler: &mut TCPReassembler,
-    reassembler: &mut Reassembler,
-    next_seq: Option<u32>,
-) {
-    reassembler.take_remaining();
-    tcp_reassembler.reset(next_seq);
+#[cfg(test)]
+mod tests {
+    use super::{
+        CaptureMethod, DLT_EN10MB, DLT_NULL, LINKTYPE_LINUX_SLL2, ReplayTiming, replay_file,
+        strip_link_layer,
+    };
+    use crate::packets::encoder;
+
+    const IPV6_STUB: [u8; 4] = [0x60, 0x00, 0x00, 0x00];
+
+    /// Ethernet frame with zeroed MACs followed by `rest` (tags, EtherType, payload).
+    fn ethernet(rest: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(rest);
+        frame
+    }
+
+    #[test]
+    fn strips_plain_and_vlan_tagged_ethernet() {
+        let plain = ethernet(&[0x86, 0xdd, 0x60, 0x00, 0x00, 0x00]);
+        assert_eq!(strip_link_layer(DLT_EN10MB, &plain), Some(&IPV6_STUB[..]));
+
+        // 802.1ad outer tag, 802.1Q inner tag, then IPv6.
+        let qinq = ethernet(&[
+            0x88, 0xa8, 0x00, 0x0a, 0x81, 0x00, 0x00, 0x64, 0x86, 0xdd, 0x60, 0x00, 0x00, 0x00,
+        ]);
+        assert_eq!(strip_link_layer(DLT_EN10MB, &qinq), Some(&IPV6_STUB[..]));
+
+        let arp = ethernet(&[0x08, 0x06, 0x00, 0x01]);
+        assert_eq!(strip_link_layer(DLT_EN10MB, &arp), None);
+    }
+
+    #[test]
+    fn strips_sll2_and_null_ipv6() {
+        let mut sll2 = vec![0x86, 0xdd];
+        sll2.extend_from_slice(&[0u8; 18]);
+        sll2.extend_from_slice(&IPV6_STUB);
+        assert_eq!(strip_link_layer(LINKTYPE_LINUX_SLL2, &sll2), Some(&IPV6_STUB[..]));
+
+        let mut null = 24u32.to_ne_bytes().to_vec();
+        null.extend_from_slice(&IPV6_STUB);
+        assert_eq!(strip_link_layer(DLT_NULL, &null), Some(&IPV6_STUB[..]));
+    }
+
+    #[test]
+    fn replay_file_reports_a_truncated_capture() {
+        let mut file = encoder::pcap(&[vec![0x45; 40]]);
+        file.truncate(file.len() - 10);
+        let path =
+            std::env::temp_dir().join(format!("resonance-truncated-{}.pcap", std::process::id()));
+        std::fs::write(&path, file).unwrap();
+
+        let (mut rx, replay) =
+            replay_file(CaptureMethod::PcapFile(path.clone(), ReplayTiming::Fast));
+        while rx.blocking_recv().is_some() {}
+        let result = replay.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert!(result.is_err_and(|e| e.contains("Truncated pcap record")));
+    }
 }
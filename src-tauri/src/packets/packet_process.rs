@@ -1,15 +1,78 @@
-use crate::packets;
+use crate::packets::channel::PacketSender;
 use crate::packets::opcodes::FragmentType;
 use crate::packets::parser;
+use crate::packets::rpc::RpcTracker;
+use crate::packets::stats;
 use bytes::Bytes;
 use log::debug;
-use std::sync::atomic::{AtomicUsize, Ordering};
 
-pub fn process_packet(
-    frame: &Bytes,
-    packet_sender: &tokio::sync::mpsc::UnboundedSender<(packets::opcodes::Pkt, Bytes)>,
-    queue_depth: &AtomicUsize,
-) {
+/// Processes a server -> client frame: notifies and routed RPC returns are sent to the live loop.
+pub fn process_packet(frame: &Bytes, rpc: &mut RpcTracker, packet_sender: &PacketSender) {
+    for_each_fragment(
+        frame,
+        |fragment_type, payload_start, payload_end, is_zstd_compressed| match fragment_type {
+            FragmentType::Notify => {
+                if let Some((method_id, payload)) = parser::parse_notify_fragment(
+                    frame,
+                    payload_start,
+                    payload_end,
+                    is_zstd_compressed,
+                ) {
+                    stats::record_message(method_id);
+                    if let Err(err) = packet_sender.send(method_id, payload) {
+                        debug!("Failed to send packet: {err}");
+                    }
+                }
+            }
+            FragmentType::Return => {
+                if let Some((reply, payload)) = parser::parse_return_fragment(
+                    frame,
+                    payload_start,
+                    payload_end,
+                    is_zstd_compressed,
+                    rpc,
+                ) {
+                    stats::record_message(reply);
+                    if let Err(err) = packet_sender.send(reply, payload) {
+                        debug!("Failed to send packet: {err}");
+                    }
+                }
+            }
+            FragmentType::FrameDown => {
+                if let Some(nested_bytes) =
+                    nested_frame(frame, payload_start, payload_end, is_zstd_compressed)
+                {
+                    process_packet(&nested_bytes, rpc, packet_sender);
+                }
+            }
+            _ => {}
+        },
+    );
+}
+
+/// Processes a client -> server frame; only Calls are kept, to correlate later Returns.
+pub fn process_upstream_packet(frame: &Bytes, rpc: &mut RpcTracker) {
+    for_each_fragment(
+        frame,
+        |fragment_type, payload_start, payload_end, is_zstd_compressed| match fragment_type {
+            FragmentType::Call => {
+                parser::parse_call_fragment(frame, payload_start, payload_end, rpc);
+            }
+            FragmentType::FrameUp => {
+                if let Some(nested_bytes) =
+                    nested_frame(frame, payload_start, payload_end, is_zstd_compressed)
+                {
+                    process_upstream_packet(&nested_bytes, rpc);
+                }
+            }
+            _ => {}
+        },
+    );
+}
+
+/// Calls `f(fragment_type, payload_start, payload_end, is_zstd_compressed)` for every
+/// fragment in `frame`.
+fn for_each_fragment(frame: &Bytes, mut f: impl FnMut(FragmentType, usize, usize, bool)) {
     let mut offset = 0usize;
     let buf = frame.as_ref();
 
@@ -25,6 +88,7 @@ pub fn process_packet(
 
         if packet_size < 6 {
             debug!("Malformed packet: packet_size < 6");
+            stats::record_malformed_frame();
             break;
         }
         let end = match offset.checked_add(packet_size) {
@@ -41,51 +105,41 @@ pub fn process_packet(
         });
         let is_zstd_compressed = (packet_type & 0x8000) != 0;
         let msg_type_id = packet_type & 0x7fff;
-        let payload_start = offset + 6;
-        let payload_end = end;
 
-        match FragmentType::from(msg_type_id) {
-            FragmentType::Notify => {
-                if let Some((method_id, payload)) = parser::parse_notify_fragment(
-                    frame,
-                    payload_start,
-                    payload_end,
-                    is_zstd_compressed,
-                ) {
-                    if let Err(err) = packet_sender.send((method_id, payload)) {
-                        debug!("Failed to send packet: {err}");
-                    } else {
-                        queue_depth.fetch_add(1, Ordering::Relaxed);
-                    }
-                }
-            }
-            FragmentType::FrameDown => {
-                if payload_end.saturating_sub(payload_start) < 4 {
-                    debug!("FrameDown: payload too short");
-                    offset = end;
-                    continue;
-                }
+        f(
+            FragmentType::from(msg_type_id),
+            offset + 6,
+            end,
+            is_zstd_compressed,
+        );
 
-                let nested_start = payload_start + 4;
-                let nested_packet = &buf[nested_start..payload_end];
-                if is_zstd_compressed {
-                    match zstd::decode_all(nested_packet) {
-                        Ok(tcp_fragment_decompressed) => {
-                            let nested_bytes = Bytes::from(tcp_fragment_decompressed);
-                            process_packet(&nested_bytes, packet_sender, queue_depth);
-                        }
-                        Err(_e) => {
-                            debug!("FrameDown: zstd decompression failed");
-                        }
-                    }
-                } else {
-                    let nested_bytes = frame.slice(nested_start..payload_end);
-                    process_packet(&nested_bytes, packet_sender, queue_depth);
-                }
+        offset = end;
+    }
+}
+
+/// Unwraps the frame nested in a FrameDown/FrameUp fragment.
+fn nested_frame(
+    frame: &Bytes,
+    payload_start: usize,
+    payload_end: usize,
+    is_zstd_compressed: bool,
+) -> Option<Bytes> {
+    if payload_end.saturating_sub(payload_start) < 4 {
+        debug!("Frame: payload too short");
+        return None;
+    }
+
+    let nested_start = payload_start + 4;
+    if is_zstd_compressed {
+        match zstd::decode_all(&frame[nested_start..payload_end]) {
+            Ok(tcp_fragment_decompressed) => Some(Bytes::from(tcp_fragment_decompressed)),
+            Err(_e) => {
+                debug!("Frame: zstd decompression failed");
+                stats::record_zstd_failure();
+                None
             }
-            _ => {}
         }
-
-        offset = end;
+    } else {
+        Some(frame.slice(nested_start..payload_end))
     }
 }
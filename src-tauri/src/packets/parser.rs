@@ -1,7 +1,13 @@
 use crate::packets;
+use crate::packets::discovery;
 use crate::packets::opcodes::Pkt;
+use crate::packets::rpc::RpcTracker;
+use crate::packets::stats;
 use bytes::Bytes;
 use log::debug;
+use std::time::Instant;
+
+pub const SCENE_SERVICE_UUID: u64 = 0x0000000063335342;
 
 /// Parse a single notify fragment from a frame slice and return opcode + payload.
 pub fn parse_notify_fragment(
@@ -17,27 +23,93 @@ pub fn parse_notify_fragment(
     }
 
     let service_uuid = u64::from_be_bytes(payload[0..8].try_into().ok()?);
-    // read and ignore stub id (4 bytes)
+    // read and ignore stub id (4 bytes); notifies are not answered
     let _stub_id = u32::from_be_bytes(payload[8..12].try_into().ok()?);
     let method_id_raw = u32::from_be_bytes(payload[12..16].try_into().ok()?);
 
-    if service_uuid != 0x0000000063335342 {
+    if service_uuid != SCENE_SERVICE_UUID {
         debug!("Notify: service_uuid mismatch: {service_uuid:x}");
         return None;
     }
 
+    let Ok(method_id) = Pkt::try_from(method_id_raw) else {
+        stats::record_unknown_method();
+        discovery::record_unknown(method_id_raw, &payload[16..], compressed);
+        return None;
+    };
+
     if compressed {
         match zstd::decode_all(&payload[16..]) {
-            Ok(decoded) => Some((Pkt::try_from(method_id_raw).ok()?, Bytes::from(decoded))),
+            Ok(decoded) => Some((method_id, Bytes::from(decoded))),
             Err(e) => {
                 debug!("Notify: zstd decompression failed: {e}");
+                stats::record_zstd_failure();
+                None
+            }
+        }
+    } else {
+        Some((method_id, frame.slice(payload_start + 16..payload_end)))
+    }
+}
+
+/// Parse the header of a client Call fragment and record it for reply routing.
+///
+/// Calls use the notify header (service uuid, stub id, method id); the request
+/// body itself is not needed.
+pub fn parse_call_fragment(
+    frame: &Bytes,
+    payload_start: usize,
+    payload_end: usize,
+    rpc: &mut RpcTracker,
+) -> Option<()> {
+    let payload = frame.get(payload_start..payload_end)?;
+    if payload.len() < 16 {
+        debug!("Call: payload too short: {}", payload.len());
+        return None;
+    }
+
+    let service_uuid = u64::from_be_bytes(payload[0..8].try_into().ok()?);
+    let stub_id = u32::from_be_bytes(payload[8..12].try_into().ok()?);
+    let method_id = u32::from_be_bytes(payload[12..16].try_into().ok()?);
+    if service_uuid != SCENE_SERVICE_UUID {
+        debug!("Call: service_uuid mismatch: {service_uuid:x}");
+        return None;
+    }
+
+    rpc.record_call(stub_id, method_id, Instant::now());
+    Some(())
+}
+
+/// Parse a Return fragment answering a recorded Call and return the routed opcode + reply body.
+///
+/// A Return carries only the stub id of its Call (4 bytes) ahead of the body.
+/// Returns for calls that were not recorded are skipped without decompressing.
+pub fn parse_return_fragment(
+    frame: &Bytes,
+    payload_start: usize,
+    payload_end: usize,
+    compressed: bool,
+    rpc: &mut RpcTracker,
+) -> Option<(packets::opcodes::Pkt, Bytes)> {
+    let payload = frame.get(payload_start..payload_end)?;
+    if payload.len() < 4 {
+        debug!("Return: payload too short: {}", payload.len());
+        return None;
+    }
+
+    let stub_id = u32::from_be_bytes(payload[0..4].try_into().ok()?);
+    let reply = rpc.take_reply(stub_id, Instant::now())?;
+
+    if compressed {
+        match zstd::decode_all(&payload[4..]) {
+            Ok(decoded) => Some((reply, Bytes::from(decoded))),
+            Err(e) => {
+                debug!("Return: zstd decompression failed: {e}");
+                stats::record_zstd_failure();
                 None
             }
         }
     } else {
-        Some((
-            Pkt::try_from(method_id_raw).ok()?,
-            frame.slice(payload_start + 16..payload_end),
-        ))
+        Some((reply, frame.slice(payload_start + 4..payload_end)))
     }
 }
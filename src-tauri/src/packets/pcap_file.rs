@@ -0,0 +1,532 @@
+//! Reader for offline capture files, used by the `PcapFile` capture method.
+//!
+//! Both classic pcap (microsecond or nanosecond timestamps, either byte order)
+//! and pcapng are read. For pcapng, every section may switch byte order,
+//! `if_tsresol` is honoured, and enhanced, simple and obsolete packet blocks
+//! are returned while other blocks are skipped. Timestamps are normalised to
+//! nanoseconds since the Unix epoch.
+//!
+//! This module only frames records; the link layer is stripped by
+//! [`packet_capture`](crate::packets::packet_capture), which accepts Ethernet
+//! (including VLAN tags), raw IP (`LINKTYPE_RAW`, `LINKTYPE_IPV4`,
+//! `LINKTYPE_IPV6`), Linux cooked capture v1 and v2, and BSD loopback
+//! (`NULL`/`LOOP`). Records with any other link type are dropped.
+
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::Path;
+
+// Classic pcap magic numbers (as read in native little-endian order).
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+
+// pcapng block types.
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const PCAPNG_OBSOLETE_PACKET: u32 = 0x0000_0002;
+const PCAPNG_SIMPLE_PACKET: u32 = 0x0000_0003;
+const PCAPNG_ENHANCED_PACKET: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_OPT_END: u16 = 0;
+const PCAPNG_OPT_IF_TSRESOL: u16 = 9;
+
+/// Upper bound for a single record/block so a corrupt length cannot make us
+/// allocate gigabytes.
+const MAX_RECORD_SIZE: usize = 64 * 1024 * 1024;
+
+/// One captured frame read from a pcap/pcapng file.
+#[derive(Debug, Clone, Default)]
+pub struct PcapRecord {
+    /// LINKTYPE_* value of the interface the frame was captured on.
+    pub link_type: u32,
+    /// Capture timestamp in nanoseconds since the Unix epoch.
+    pub timestamp_ns: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn u16(self, b: &[u8]) -> u16 {
+        let b = [b[0], b[1]];
+        match self {
+            Endian::Little => u16::from_le_bytes(b),
+            Endian::Big => u16::from_be_bytes(b),
+        }
+    }
+
+    fn u32(self, b: &[u8]) -> u32 {
+        let b = [b[0], b[1], b[2], b[3]];
+        match self {
+            Endian::Little => u32::from_le_bytes(b),
+            Endian::Big => u32::from_be_bytes(b),
+        }
+    }
+}
+
+struct PcapngInterface {
+    link_type: u32,
+    snap_len: u32,
+    /// Number of timestamp units per second (from `if_tsresol`).
+    ticks_per_second: u64,
+}
+
+enum Format {
+    Pcap {
+        endian: Endian,
+        link_type: u32,
+        nanos: bool,
+    },
+    Pcapng {
+        endian: Endian,
+        interfaces: Vec<PcapngInterface>,
+    },
+}
+
+/// Streaming reader for classic pcap and pcapng capture files.
+///
+/// The format is detected from the first four bytes. Only the blocks needed to
+/// recover packet data and timestamps are interpreted; everything else is skipped.
+pub struct PcapReader<R: Read> {
+    reader: R,
+    format: Format,
+    /// pcapng block body, reused across blocks.
+    block: Vec<u8>,
+}
+
+impl PcapReader<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Self::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, String> {
+        let mut magic = [0u8; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|e| format!("Failed to read capture file header: {}", e))?;
+
+        let format = match u32::from_le_bytes(magic) {
+            PCAP_MAGIC_MICROS => Self::read_pcap_header(&mut reader, Endian::Little, false)?,
+            PCAP_MAGIC_NANOS => Self::read_pcap_header(&mut reader, Endian::Little, true)?,
+            _ => match u32::from_be_bytes(magic) {
+                PCAP_MAGIC_MICROS => Self::read_pcap_header(&mut reader, Endian::Big, false)?,
+                PCAP_MAGIC_NANOS => Self::read_pcap_header(&mut reader, Endian::Big, true)?,
+                PCAPNG_SECTION_HEADER => {
+                    let mut len_bytes = [0u8; 4];
+                    reader
+                        .read_exact(&mut len_bytes)
+                        .map_err(|e| format!("Truncated pcapng section header: {}", e))?;
+                    let endian = read_section_header(&mut reader, len_bytes)?;
+                    Format::Pcapng {
+                        endian,
+                        interfaces: Vec::new(),
+                    }
+                }
+                other => return Err(format!("Unrecognized capture file magic {:#010x}", other)),
+            },
+        };
+
+        Ok(Self {
+            reader,
+            format,
+            block: Vec::new(),
+        })
+    }
+
+    fn read_pcap_header(reader: &mut R, endian: Endian, nanos: bool) -> Result<Format, String> {
+        // version(4) + thiszone(4) + sigfigs(4) + snaplen(4) + network(4)
+        let mut header = [0u8; 20];
+        reader
+            .read_exact(&mut header)
+            .map_err(|e| format!("Truncated pcap header: {}", e))?;
+        // Only the low 16 bits are the link type; the rest carries FCS info.
+        let link_type = endian.u32(&header[16..20]) & 0xffff;
+        Ok(Format::Pcap {
+            endian,
+            link_type,
+            nanos,
+        })
+    }
+
+    /// Returns the next captured frame, or `Ok(None)` once the end of the file
+    /// has been reached.
+    #[cfg(test)]
+    pub fn next_record(&mut self) -> Result<Option<PcapRecord>, String> {
+        let mut record = PcapRecord::default();
+        Ok(self.next_record_into(&mut record)?.then_some(record))
+    }
+
+    /// Reads the next captured frame into `record`, reusing its data buffer.
+    ///
+    /// Returns false once the end of the file has been reached.
+    pub fn next_record_into(&mut self, record: &mut PcapRecord) -> Result<bool, String> {
+        match self.format {
+            Format::Pcap {
+                endian,
+                link_type,
+                nanos,
+            } => self.next_pcap_record(endian, link_type, nanos, record),
+            Format::Pcapng { .. } => self.next_pcapng_record(record),
+        }
+    }
+
+    fn next_pcap_record(
+        &mut self,
+        endian: Endian,
+        link_type: u32,
+        nanos: bool,
+        record: &mut PcapRecord,
+    ) -> Result<bool, String> {
+        let mut header = [0u8; 16];
+        if !read_exact_or_eof(&mut self.reader, &mut header)? {
+            return Ok(false);
+        }
+        let ts_sec = u64::from(endian.u32(&header[0..4]));
+        let ts_frac = u64::from(endian.u32(&header[4..8]));
+        let incl_len = endian.u32(&header[8..12]) as usize;
+        if incl_len > MAX_RECORD_SIZE {
+            return Err(format!("pcap record length {} exceeds limit", incl_len));
+        }
+        record.data.resize(incl_len, 0);
+        self.reader
+            .read_exact(&mut record.data)
+            .map_err(|e| format!("Truncated pcap record: {}", e))?;
+
+        let frac_ns = if nanos { ts_frac } else { ts_frac * 1_000 };
+        record.link_type = link_type;
+        record.timestamp_ns = ts_sec * 1_000_000_000 + frac_ns;
+        Ok(true)
+    }
+
+    fn next_pcapng_record(&mut self, record: &mut PcapRecord) -> Result<bool, String> {
+        loop {
+            let Format::Pcapng { endian, .. } = self.format else {
+                unreachable!("pcapng reader used on classic pcap");
+            };
+
+            let mut head = [0u8; 8];
+            if !read_exact_or_eof(&mut self.reader, &mut head)? {
+                return Ok(false);
+            }
+            let block_type = endian.u32(&head[0..4]);
+
+            if block_type == PCAPNG_SECTION_HEADER {
+                // A new section may switch byte order and resets the interface list.
+                let endian =
+                    read_section_header(&mut self.reader, [head[4], head[5], head[6], head[7]])?;
+                self.format = Format::Pcapng {
+                    endian,
+                    interfaces: Vec::new(),
+                };
+                continue;
+            }
+
+            let total_len = endian.u32(&head[4..8]) as usize;
+            if !(12..=MAX_RECORD_SIZE).contains(&total_len) || !total_len.is_multiple_of(4) {
+                return Err(format!("Invalid pcapng block length {}", total_len));
+            }
+            let mut body = std::mem::take(&mut self.block);
+            body.resize(total_len - 8, 0);
+            let read = self
+                .reader
+                .read_exact(&mut body)
+                .map_err(|e| format!("Truncated pcapng block: {}", e));
+            // Drop the trailing copy of the block length.
+            body.truncate(total_len - 12);
+
+            let handled =
+                read.and_then(|()| self.handle_pcapng_block(endian, block_type, &body, record));
+            self.block = body;
+            if handled? {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn handle_pcapng_block(
+        &mut self,
+        endian: Endian,
+        block_type: u32,
+        body: &[u8],
+        record: &mut PcapRecord,
+    ) -> Result<bool, String> {
+        let Format::Pcapng { interfaces, .. } = &mut self.format else {
+            return Ok(false);
+        };
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                if body.len() < 8 {
+                    return Err("Truncated pcapng interface description".to_string());
+                }
+                interfaces.push(PcapngInterface {
+                    link_type: u32::from(endian.u16(&body[0..2])),
+                    snap_len: endian.u32(&body[4..8]),
+                    ticks_per_second: parse_tsresol(endian, &body[8..]),
+                });
+                Ok(false)
+            }
+            PCAPNG_ENHANCED_PACKET | PCAPNG_OBSOLETE_PACKET => {
+                if body.len() < 20 {
+                    return Err("Truncated pcapng packet block".to_string());
+                }
+                let interface_id = if block_type == PCAPNG_ENHANCED_PACKET {
+                    endian.u32(&body[0..4]) as usize
+                } else {
+                    endian.u16(&body[0..2]) as usize
+                };
+                let Some(interface) = interfaces.get(interface_id) else {
+                    return Err(format!(
+                        "pcapng packet references unknown interface {}",
+                        interface_id
+                    ));
+                };
+                let ts = (u64::from(endian.u32(&body[4..8])) << 32)
+                    | u64::from(endian.u32(&body[8..12]));
+                let cap_len = endian.u32(&body[12..16]) as usize;
+                let data = body
+                    .get(20..20 + cap_len)
+                    .ok_or_else(|| "pcapng packet data exceeds block".to_string())?;
+                record.link_type = interface.link_type;
+                record.timestamp_ns = ticks_to_nanos(ts, interface.ticks_per_second);
+                record.data.clear();
+                record.data.extend_from_slice(data);
+                Ok(true)
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                if body.len() < 4 {
+                    return Err("Truncated pcapng simple packet block".to_string());
+                }
+                let Some(interface) = interfaces.first() else {
+                    return Err("pcapng simple packet before any interface".to_string());
+                };
+                let orig_len = endian.u32(&body[0..4]) as usize;
+                let mut cap_len = orig_len.min(body.len() - 4);
+                if interface.snap_len > 0 {
+                    cap_len = cap_len.min(interface.snap_len as usize);
+                }
+                // Simple packet blocks carry no timestamp.
+                record.link_type = interface.link_type;
+                record.timestamp_ns = 0;
+                record.data.clear();
+                record.data.extend_from_slice(&body[4..4 + cap_len]);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+/// Reads the remainder of a section header block, given its (still undecoded)
+/// length field, and returns the byte order declared by it.
+fn read_section_header<R: Read>(reader: &mut R, len_bytes: [u8; 4]) -> Result<Endian, String> {
+    let mut magic = [0u8; 4];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|e| format!("Truncated pcapng section header: {}", e))?;
+    let endian = if u32::from_le_bytes(magic) == PCAPNG_BYTE_ORDER_MAGIC {
+        Endian::Little
+    } else if u32::from_be_bytes(magic) == PCAPNG_BYTE_ORDER_MAGIC {
+        Endian::Big
+    } else {
+        return Err("Invalid pcapng byte-order magic".to_string());
+    };
+    let total_len = endian.u32(&len_bytes) as usize;
+    // block type(4) + length(4) + byte-order magic(4) already consumed
+    if !(16..=MAX_RECORD_SIZE).contains(&total_len) {
+        return Err(format!(
+            "Invalid pcapng section header length {}",
+            total_len
+        ));
+    }
+    skip(reader, total_len - 12)?;
+    Ok(endian)
+}
+
+/// Walks the interface description options looking for `if_tsresol`.
+/// Defaults to microsecond resolution as mandated by the pcapng spec.
+fn parse_tsresol(endian: Endian, mut options: &[u8]) -> u64 {
+    while options.len() >= 4 {
+        let code = endian.u16(&options[0..2]);
+        let len = endian.u16(&options[2..4]) as usize;
+        if code == PCAPNG_OPT_END {
+            break;
+        }
+        let padded = (len + 3) & !3;
+        if options.len() < 4 + padded {
+            break;
+        }
+        if code == PCAPNG_OPT_IF_TSRESOL && len >= 1 {
+            let value = options[4];
+            let exponent = u32::from(value & 0x7f);
+            let ticks = if value & 0x80 == 0 {
+                10u64.checked_pow(exponent)
+            } else {
+                2u64.checked_pow(exponent)
+            };
+            return ticks.filter(|t| *t > 0).unwrap_or(1_000_000);
+        }
+        options = &options[4 + padded..];
+    }
+    1_000_000
+}
+
+fn ticks_to_nanos(ticks: u64, ticks_per_second: u64) -> u64 {
+    let nanos = u128::from(ticks) * 1_000_000_000 / u128::from(ticks_per_second);
+    u64::try_from(nanos).unwrap_or(u64::MAX)
+}
+
+/// Like `read_exact`, but reports a clean end of file (no bytes read) as `false`.
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, String> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err("Unexpected end of capture file".to_string()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(true)
+}
+
+fn skip<R: Read>(reader: &mut R, count: usize) -> Result<(), String> {
+    let copied = std::io::copy(&mut reader.take(count as u64), &mut std::io::sink())
+        .map_err(|e| e.to_string())?;
+    if copied as usize != count {
+        return Err("Unexpected end of capture file".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PcapReader;
+
+    fn classic_pcap(link_type: u32, packets: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let mut v = Vec::new();
+        v.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+        v.extend_from_slice(&2u16.to_le_bytes());
+        v.extend_from_slice(&4u16.to_le_bytes());
+        v.extend_from_slice(&0i32.to_le_bytes());
+        v.extend_from_slice(&0u32.to_le_bytes());
+        v.extend_from_slice(&65535u32.to_le_bytes());
+        v.extend_from_slice(&link_type.to_le_bytes());
+        for (sec, usec, data) in packets {
+            v.extend_from_slice(&sec.to_le_bytes());
+            v.extend_from_slice(&usec.to_le_bytes());
+            v.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            v.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            v.extend_from_slice(data);
+        }
+        v
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let mut padded = body.to_vec();
+        while !padded.len().is_multiple_of(4) {
+            padded.push(0);
+        }
+        let total = (padded.len() + 12) as u32;
+        let mut v = Vec::new();
+        v.extend_from_slice(&block_type.to_be_bytes());
+        v.extend_from_slice(&total.to_be_bytes());
+        v.extend_from_slice(&padded);
+        v.extend_from_slice(&total.to_be_bytes());
+        v
+    }
+
+    fn pcapng_file(packet: &[u8], tsresol: Option<u8>) -> Vec<u8> {
+        let mut shb = Vec::new();
+        shb.extend_from_slice(&0x1a2b_3c4du32.to_be_bytes());
+        shb.extend_from_slice(&1u16.to_be_bytes());
+        shb.extend_from_slice(&0u16.to_be_bytes());
+        shb.extend_from_slice(&(-1i64).to_be_bytes());
+
+        let mut idb = Vec::new();
+        idb.extend_from_slice(&113u16.to_be_bytes());
+        idb.extend_from_slice(&0u16.to_be_bytes());
+        idb.extend_from_slice(&0u32.to_be_bytes());
+        if let Some(res) = tsresol {
+            idb.extend_from_slice(&9u16.to_be_bytes());
+            idb.extend_from_slice(&1u16.to_be_bytes());
+            idb.extend_from_slice(&[res, 0, 0, 0]);
+            idb.extend_from_slice(&[0, 0, 0, 0]);
+        }
+
+        let mut epb = Vec::new();
+        epb.extend_from_slice(&0u32.to_be_bytes());
+        epb.extend_from_slice(&0u32.to_be_bytes());
+        epb.extend_from_slice(&1_500u32.to_be_bytes());
+        epb.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+        epb.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+        epb.extend_from_slice(packet);
+
+        let mut v = pcapng_block(0x0a0d_0d0a, &shb);
+        v.extend(pcapng_block(1, &idb));
+        v.extend(pcapng_block(0x0bad_cafe, &[1, 2, 3, 4]));
+        v.extend(pcapng_block(6, &epb));
+        v
+    }
+
+    #[test]
+    fn reads_classic_pcap_records() {
+        let file = classic_pcap(1, &[(10, 500, b"abc"), (11, 0, b"defg")]);
+        let mut reader = PcapReader::new(file.as_slice()).unwrap();
+
+        let first = reader.next_record().unwrap().unwrap();
+        assert_eq!(first.link_type, 1);
+        assert_eq!(first.timestamp_ns, 10_000_500_000);
+        assert_eq!(first.data, b"abc");
+
+        let second = reader.next_record().unwrap().unwrap();
+        assert_eq!(second.timestamp_ns, 11_000_000_000);
+        assert_eq!(second.data, b"defg");
+
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_classic_record_is_an_error() {
+        let mut file = classic_pcap(101, &[(1, 0, b"abcdef")]);
+        file.truncate(file.len() - 2);
+        let mut reader = PcapReader::new(file.as_slice()).unwrap();
+        assert!(reader.next_record().is_err());
+    }
+
+    #[test]
+    fn reads_big_endian_pcapng_and_skips_unknown_blocks() {
+        let file = pcapng_file(b"hello", None);
+        let mut reader = PcapReader::new(file.as_slice()).unwrap();
+
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.link_type, 113);
+        assert_eq!(record.data, b"hello");
+        // (0 << 32 | 1500) microseconds
+        assert_eq!(record.timestamp_ns, 1_500_000);
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn pcapng_honours_if_tsresol() {
+        let file = pcapng_file(b"x", Some(9));
+        let mut reader = PcapReader::new(file.as_slice()).unwrap();
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.timestamp_ns, 1_500);
+    }
+
+    #[test]
+    fn rejects_unknown_magic() {
+        let data = [0u8; 32];
+        assert!(PcapReader::new(&data[..]).is_err());
+    }
+}
@@ -1,4 +1,7 @@
-use bytes::{Bytes, BytesMut};
+use crate::packets::opcodes::FragmentType;
+use crate::packets::stats;
+use bytes::{Buf, Bytes, BytesMut};
+use std::borrow::Cow;
 use std::convert::TryInto;
 
 /// A simple TCP reassembler for length-prefixed frames where each frame
@@ -6,26 +9,51 @@ use std::convert::TryInto;
 ///
 /// The reassembler keeps a single BytesMut buffer. When a complete frame is
 /// available, it returns a Bytes view.
+///
+/// After bytes were lost upstream the buffer no longer starts on a frame
+/// boundary; [`Reassembler::resync`] makes it search for the next plausible
+/// frame header instead of trusting whatever length comes first.
 pub struct Reassembler {
     buffer: BytesMut,
     /// Safety cap to avoid pathological allocations (can be tuned)
     max_buffer_size: usize,
+    /// Searching for a frame boundary instead of reading lengths in place.
+    resyncing: bool,
+    /// Bytes discarded since the last `take_lost_bytes`.
+    lost_bytes: u64,
 }
 
+/// Largest frame accepted while searching for a frame boundary; real frames
+/// are far smaller, and a tight bound rejects most random lengths.
+const MAX_RESYNC_FRAME_SIZE: usize = 1024 * 1024;
+/// u32 length followed by the u16 fragment type.
+const FRAME_HEADER_LEN: usize = 6;
+
 impl Reassembler {
     pub fn new() -> Self {
         Self {
             buffer: BytesMut::with_capacity(4096),
             max_buffer_size: 10 * 1024 * 1024, // 10 MB
+            resyncing: false,
+            lost_bytes: 0,
         }
     }
 
+    /// Drops the buffered partial frame and looks for the next frame boundary
+    /// in the bytes that follow. Called when the TCP stream skipped over lost data.
+    pub fn resync(&mut self) {
+        self.buffer.clear();
+        self.resyncing = true;
+    }
+
     /// Push incoming bytes (e.g., TCP payload) into the reassembler.
-    #[allow(dead_code)]
     pub fn push(&mut self, data: &[u8]) {
         self.buffer.extend_from_slice(data);
         // If buffer grows beyond max, drop to recover from malformed input.
         if self.buffer.len() > self.max_buffer_size {
+            stats::record_malformed_frame();
+            stats::record_bytes_lost(self.buffer.len() as u64);
+            self.lost_bytes += self.buffer.len() as u64;
             self.buffer.clear();
         }
     }
@@ -33,6 +61,10 @@ impl Reassembler {
     /// Try to extract the next complete frame if available.
     /// Returns Some(frame_bytes) or None if not enough data yet.
     pub fn try_next(&mut self) -> Option<Bytes> {
+        if self.resyncing && !self.find_frame_boundary() {
+            return None;
+        }
+
         // Need at least 4 bytes to read length
         if self.buffer.len() < 4 {
             return None;
@@ -42,11 +74,15 @@ impl Reassembler {
         let len_bytes = &self.buffer[..4];
         let frame_len = u32::from_be_bytes(len_bytes.try_into().unwrap()) as usize;
 
-        // Sanity check: frame length must be >= 4 (header included) and not absurd
-        if frame_len == 0 || frame_len > self.max_buffer_size {
-            // Avoid trying to parse insane frame sizes; drop buffer to recover.
-            self.buffer.clear();
-            return None;
+        // Sanity check: frame length must cover the header and not be absurd
+        if frame_len < FRAME_HEADER_LEN || frame_len > self.max_buffer_size {
+            // Not on a frame boundary; search for the next one.
+            stats::record_malformed_frame();
+            stats::record_bytes_lost(1);
+            self.lost_bytes += 1;
+            self.buffer.advance(1);
+            self.resyncing = true;
+            return self.try_next();
         }
 
         if self.buffer.len() < frame_len {
@@ -57,6 +93,58 @@ impl Reassembler {
         Some(self.buffer.split_to(frame_len).freeze())
     }
 
+    /// Discards bytes up to the first offset that looks like the start of a
+    /// frame. Returns false if no boundary could be confirmed yet.
+    ///
+    /// A candidate is confirmed once its whole frame is buffered and it is
+    /// either followed by another plausible header or ends the buffer.
+    fn find_frame_boundary(&mut self) -> bool {
+        let buf = &self.buffer[..];
+        let mut offset = 0;
+        let mut confirmed = None;
+        while offset + FRAME_HEADER_LEN <= buf.len() {
+            let Some(frame_len) = plausible_frame_len(&buf[offset..]) else {
+                offset += 1;
+                continue;
+            };
+            let end = offset + frame_len;
+            if end > buf.len() {
+                // Wait for the rest of this candidate before judging it.
+                break;
+            }
+            if buf.len() - end < FRAME_HEADER_LEN || plausible_frame_len(&buf[end..]).is_some() {
+                confirmed = Some(offset);
+                break;
+            }
+            offset += 1;
+        }
+
+        let skip = confirmed.unwrap_or_else(|| {
+            // Keep a possible partial header at the end of the buffer.
+            offset.min(self.buffer.len().saturating_sub(FRAME_HEADER_LEN - 1))
+        });
+        if skip > 0 {
+            stats::record_bytes_lost(skip as u64);
+            self.lost_bytes += skip as u64;
+            self.buffer.advance(skip);
+        }
+        if confirmed.is_some() {
+            log::info!(target: "app::capture", "frame_resync skipped_bytes={}", skip);
+            self.resyncing = false;
+            return true;
+        }
+        false
+    }
+
+    /// Feed reassembled TCP bytes: borrowed bytes are copied into the buffer,
+    /// owned ones are handed to [`Self::feed_owned`].
+    pub fn feed(&mut self, bytes: Cow<'_, [u8]>) {
+        match bytes {
+            Cow::Borrowed(data) => self.push(data),
+            Cow::Owned(data) => self.feed_owned(data),
+        }
+    }
+
     /// Feed an owned Vec<u8> into the reassembler without copying when possible.
     /// If the internal buffer is empty we take ownership of the
     /// provided Vec to avoid an extra copy. Otherwise we extend the buffer.
@@ -69,13 +157,34 @@ impl Reassembler {
         self.buffer.extend_from_slice(&bytes);
     }
 
+    /// Returns the bytes discarded while looking for frame boundaries since
+    /// the last call, and resets the count.
+    ///
+    /// Frames were dropped with them, so the caller should treat the stream as
+    /// incomplete.
+    pub fn take_lost_bytes(&mut self) -> u64 {
+        std::mem::take(&mut self.lost_bytes)
+    }
+
     /// Take and return the remaining unconsumed bytes and
     /// reset the internal buffer.
     pub fn take_remaining(&mut self) -> Bytes {
+        self.resyncing = false;
         self.buffer.split().freeze()
     }
 }
 
+/// Returns the frame length if `buf` starts with a believable frame header.
+fn plausible_frame_len(buf: &[u8]) -> Option<usize> {
+    let header = buf.get(..FRAME_HEADER_LEN)?;
+    let frame_len = u32::from_be_bytes(header[..4].try_into().ok()?) as usize;
+    let fragment_type = u16::from_be_bytes(header[4..6].try_into().ok()?) & 0x7fff;
+    let known_type =
+        (FragmentType::Call as u16..=FragmentType::FrameDown as u16).contains(&fragment_type);
+    ((FRAME_HEADER_LEN..=MAX_RESYNC_FRAME_SIZE).contains(&frame_len) && known_type)
+        .then_some(frame_len)
+}
+
 #[cfg(test)]
 mod tests {
     use super::Reassembler;
@@ -87,6 +196,13 @@ mod tests {
         v
     }
 
+    /// A frame whose payload starts with a Notify fragment type.
+    fn make_notify_frame(body: &[u8]) -> Vec<u8> {
+        let mut payload = 2u16.to_be_bytes().to_vec();
+        payload.extend_from_slice(body);
+        make_frame(&payload)
+    }
+
     #[test]
     fn single_frame_in_one_push() {
         let mut r = Reassembler::new();
@@ -126,4 +242,49 @@ mod tests {
         let got = r.try_next().unwrap();
         assert_eq!(&got[4..], b"split-me");
     }
+
+    #[test]
+    fn resync_skips_to_next_frame_boundary() {
+        let mut r = Reassembler::new();
+        let first = make_notify_frame(b"first");
+        let second = make_notify_frame(b"second");
+        // The tail of a frame whose start was lost, then two intact frames
+        r.push(&[0xde, 0xad, 0x00, 0x00, 0x00, 0x01, 0xbe]);
+        r.resync();
+        r.push(&[0x00, 0xff, 0x12, 0x00, 0x00]);
+        r.push(&first);
+        r.push(&second);
+
+        assert_eq!(r.try_next().unwrap().as_ref(), first.as_slice());
+        assert_eq!(r.try_next().unwrap().as_ref(), second.as_slice());
+        assert!(r.try_next().is_none());
+    }
+
+    #[test]
+    fn insane_length_resyncs_instead_of_clearing() {
+        let mut r = Reassembler::new();
+        let frame = make_notify_frame(b"after-garbage");
+        let mut data = vec![0xff, 0xff, 0xff, 0xff, 0x00];
+        data.extend_from_slice(&frame);
+        r.push(&data);
+
+        assert_eq!(r.try_next().unwrap().as_ref(), frame.as_slice());
+        assert!(r.try_next().is_none());
+        assert_eq!(r.take_lost_bytes(), 5);
+        assert_eq!(r.take_lost_bytes(), 0);
+    }
+
+    #[test]
+    fn overflowing_buffer_counts_lost_bytes() {
+        let mut r = Reassembler::new();
+        // A believable header announcing a frame that never completes
+        let mut header = (9u32 * 1024 * 1024).to_be_bytes().to_vec();
+        header.extend_from_slice(&2u16.to_be_bytes());
+        r.push(&header);
+        assert!(r.try_next().is_none());
+        r.push(&vec![0u8; 10 * 1024 * 1024]);
+
+        assert!(r.try_next().is_none());
+        assert_eq!(r.take_lost_bytes(), 10 * 1024 * 1024 + 6);
+    }
 }
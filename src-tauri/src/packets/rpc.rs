@@ -0,0 +1,218 @@
+//! Correlation of RPC calls and returns on a game connection.
+//!
+//! Some data (player inspect, dungeon queries) is not pushed as a notify but
+//! returned for a request the client made. A Call fragment carries a stub id
+//! and a method id; the matching Return fragment only carries the stub id. The
+//! [`RpcTracker`] remembers the calls whose replies the meter wants and hands
+//! back the synthetic [`Pkt`] to route a Return as.
+//!
+//! Which method ids are routed is read from `meter-data/RpcMethods.json`, a map
+//! from reply name to the method ids that produce it. A `RpcMethods.json` in
+//! the app data dir overrides the bundled file, so ids found for a game patch
+//! can be added without rebuilding the meter. The routes are read when the
+//! live loop starts and apply to connections opened after that.
+
+use crate::packets::opcodes::Pkt;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::{Duration, Instant};
+
+/// Calls remembered per connection; the oldest is forgotten when full.
+const MAX_PENDING_CALLS: usize = 256;
+/// A call that has not been answered within this time is forgotten.
+const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+const BUNDLED_METHODS: &str = include_str!("../../meter-data/RpcMethods.json");
+/// File name of the method ids override in the app data dir.
+pub const METHODS_FILE_NAME: &str = "RpcMethods.json";
+
+static ROUTES: LazyLock<RwLock<Arc<HashMap<u32, Pkt>>>> = LazyLock::new(|| {
+    let routes = parse_routes(BUNDLED_METHODS).expect("invalid RpcMethods.json");
+    RwLock::new(Arc::new(routes))
+});
+
+/// Parses a `RpcMethods.json` map into routes by method id.
+///
+/// Entries naming a reply the meter does not know are skipped with a warning.
+fn parse_routes(json: &str) -> Result<HashMap<u32, Pkt>, String> {
+    let methods: HashMap<String, Vec<u32>> =
+        serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let mut routes = HashMap::new();
+    for (name, method_ids) in methods {
+        let Some(reply) = reply_pkt(&name) else {
+            warn!(target: "app::capture", "rpc_route_unknown name={}", name);
+            continue;
+        };
+        for method_id in method_ids {
+            routes.insert(method_id, reply);
+        }
+    }
+    Ok(routes)
+}
+
+/// Path of the method ids override file, if the app data dir is known.
+pub fn override_path(app: &tauri::AppHandle) -> Option<PathBuf> {
+    use tauri::Manager;
+
+    app.path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join(METHODS_FILE_NAME))
+}
+
+/// Loads the routed method ids from `path` if it exists, or the bundled file otherwise.
+///
+/// The routes in use are kept when the file cannot be read or parsed.
+pub fn reload(path: Option<&Path>) -> Result<(), String> {
+    let (routes, source) = match path.filter(|path| path.exists()) {
+        Some(path) => {
+            let json = std::fs::read_to_string(path)
+                .map_err(|e| format!("read {}: {}", path.display(), e))?;
+            let routes =
+                parse_routes(&json).map_err(|e| format!("parse {}: {}", path.display(), e))?;
+            (routes, path.display().to_string())
+        }
+        None => (parse_routes(BUNDLED_METHODS)?, "bundled".to_string()),
+    };
+    info!(
+        target: "app::capture",
+        "rpc_routes_loaded source={} methods={}",
+        source,
+        routes.len()
+    );
+    *ROUTES.write().map_err(|e| e.to_string())? = Arc::new(routes);
+    Ok(())
+}
+
+/// Maps a `RpcMethods.json` entry to the opcode its replies are routed as.
+fn reply_pkt(name: &str) -> Option<Pkt> {
+    match name {
+        "GetSocialData" => Some(Pkt::GetSocialDataReply),
+        _ => None,
+    }
+}
+
+struct PendingCall {
+    reply: Pkt,
+    sent_at: Instant,
+}
+
+/// Outstanding calls of one connection, keyed by stub id.
+pub struct RpcTracker {
+    routes: HashMap<u32, Pkt>,
+    pending: HashMap<u32, PendingCall>,
+}
+
+impl RpcTracker {
+    /// Creates a tracker routing the methods listed in `RpcMethods.json`.
+    pub fn new() -> Self {
+        let routes = ROUTES
+            .read()
+            .map(|routes| HashMap::clone(&routes))
+            .unwrap_or_default();
+        Self::with_routes(routes)
+    }
+
+    /// Creates a tracker routing replies of the given method ids.
+    pub fn with_routes(routes: HashMap<u32, Pkt>) -> Self {
+        Self {
+            routes,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Remembers a call if its reply is routed; other calls are ignored.
+    pub fn record_call(&mut self, stub_id: u32, method_id: u32, now: Instant) {
+        let Some(&reply) = self.routes.get(&method_id) else {
+            return;
+        };
+        if self.pending.len() >= MAX_PENDING_CALLS {
+            self.pending
+                .retain(|_, call| now.saturating_duration_since(call.sent_at) < CALL_TIMEOUT);
+        }
+        if self.pending.len() >= MAX_PENDING_CALLS
+            && let Some(oldest) = self
+                .pending
+                .iter()
+                .min_by_key(|(_, call)| call.sent_at)
+                .map(|(stub_id, _)| *stub_id)
+        {
+            self.pending.remove(&oldest);
+        }
+        self.pending.insert(
+            stub_id,
+            PendingCall {
+                reply,
+                sent_at: now,
+            },
+        );
+    }
+
+    /// Returns the opcode to route the reply for `stub_id` as, if its call was recorded.
+    pub fn take_reply(&mut self, stub_id: u32, now: Instant) -> Option<Pkt> {
+        let call = self.pending.remove(&stub_id)?;
+        (now.saturating_duration_since(call.sent_at) < CALL_TIMEOUT).then_some(call.reply)
+    }
+}
+
+impl Default for RpcTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CALL_TIMEOUT, MAX_PENDING_CALLS, RpcTracker, parse_routes, reply_pkt};
+    use crate::packets::opcodes::Pkt;
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    #[test]
+    fn routes_only_recorded_calls() {
+        let mut rpc = RpcTracker::with_routes(HashMap::from([(0x1001, Pkt::GetSocialDataReply)]));
+        let now = Instant::now();
+        rpc.record_call(7, 0x1001, now);
+        rpc.record_call(8, 0x2002, now);
+
+        assert_eq!(rpc.take_reply(8, now), None);
+        assert_eq!(rpc.take_reply(7, now), Some(Pkt::GetSocialDataReply));
+        // A stub id is answered once
+        assert_eq!(rpc.take_reply(7, now), None);
+
+        rpc.record_call(9, 0x1001, now);
+        assert_eq!(rpc.take_reply(9, now + CALL_TIMEOUT), None);
+    }
+
+    #[test]
+    fn forgets_oldest_call_when_full() {
+        let mut rpc = RpcTracker::with_routes(HashMap::from([(0x1001, Pkt::GetSocialDataReply)]));
+        let start = Instant::now();
+        for stub_id in 0..=MAX_PENDING_CALLS as u32 {
+            rpc.record_call(
+                stub_id,
+                0x1001,
+                start + std::time::Duration::from_millis(stub_id.into()),
+            );
+        }
+        let later = start + std::time::Duration::from_secs(1);
+        assert_eq!(rpc.take_reply(0, later), None);
+        assert_eq!(rpc.take_reply(1, later), Some(Pkt::GetSocialDataReply));
+        assert_eq!(reply_pkt("GetSocialData"), Some(Pkt::GetSocialDataReply));
+    }
+
+    #[test]
+    fn parses_method_ids_per_reply() {
+        let routes = parse_routes(r#"{"GetSocialData": [4097, 4098], "Unknown": [1]}"#).unwrap();
+        assert_eq!(
+            routes,
+            HashMap::from([
+                (4097, Pkt::GetSocialDataReply),
+                (4098, Pkt::GetSocialDataReply)
+            ])
+        );
+        assert!(parse_routes("[]").is_err());
+    }
+}
@@ -0,0 +1,378 @@
+//! Recording and replay of decoded game messages (`.rlsession` files).
+//!
+//! A session file is one zstd stream. Decompressed, it holds the 8-byte magic
+//! `RLSESS01` followed by records until the end of the stream. All integers
+//! are little-endian:
+//!
+//! | Offset | Size | Field          | Meaning                                          |
+//! |--------|------|----------------|--------------------------------------------------|
+//! | 0      | 8    | `timestamp_ms` | `u64`, time the message was captured, ms since the Unix epoch |
+//! | 8      | 4    | `method_id`    | `u32`, the [`Pkt`] value of the message           |
+//! | 12     | 4    | `payload_len`  | `u32`, length of the payload that follows        |
+//! | 16     | n    | payload        | message body as passed to the live loop          |
+//!
+//! `method_id` 0 is a server change and 0x00020000 marks lost stream bytes;
+//! both have an empty payload. Replay capture-time markers are never written;
+//! the records after one carry its time instead. Readers skip records with
+//! unknown method ids and stop at the last complete record of a truncated
+//! file. A change to this layout needs a new magic.
+
+use crate::packets::channel::{PacketSender, decode_capture_time};
+use crate::packets::opcodes::Pkt;
+use crate::packets::packet_capture::ReplayTiming;
+use crate::packets::stats;
+use bytes::Bytes;
+use log::{debug, info, warn};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+/// File extension used for recorded sessions.
+pub const SESSION_FILE_EXTENSION: &str = "rlsession";
+
+/// Written once at the start of the (decompressed) stream.
+const SESSION_MAGIC: &[u8; 8] = b"RLSESS01";
+/// Per-record header: timestamp_ms(u64) + method_id(u32) + payload_len(u32), little-endian.
+const RECORD_HEADER_LEN: usize = 16;
+/// Upper bound for a single payload so a corrupt file cannot make us allocate gigabytes.
+const MAX_PAYLOAD_LEN: usize = 64 * 1024 * 1024;
+const ZSTD_LEVEL: i32 = 3;
+/// How often buffered records are pushed to disk, so a crash loses at most this much.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// One decoded message from a session file.
+#[derive(Debug)]
+pub struct SessionRecord {
+    /// Wall-clock time the message was originally received, in ms since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub op: Pkt,
+    pub data: Bytes,
+}
+
+/// Writes the `(Pkt, Bytes)` stream into a zstd-compressed session file.
+pub struct SessionWriter<W: Write> {
+    encoder: zstd::Encoder<'static, W>,
+    records: u64,
+    last_flush: Instant,
+    /// Set while re-recording a replay, whose messages keep their capture time.
+    capture_time_ms: Option<u64>,
+}
+
+/// Session writer backed by a file on disk.
+pub type SessionRecorder = SessionWriter<BufWriter<File>>;
+
+impl SessionRecorder {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        Self::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> SessionWriter<W> {
+    pub fn new(writer: W) -> Result<Self, String> {
+        let mut encoder = zstd::Encoder::new(writer, ZSTD_LEVEL).map_err(|e| e.to_string())?;
+        encoder
+            .write_all(SESSION_MAGIC)
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            encoder,
+            records: 0,
+            last_flush: Instant::now(),
+            capture_time_ms: None,
+        })
+    }
+
+    /// Appends a message stamped with the current wall-clock time.
+    ///
+    /// [`Pkt::CaptureTime`] markers of a replay are not written; the messages
+    /// after one are stamped with its capture time instead.
+    pub fn record(&mut self, op: Pkt, data: &[u8]) -> Result<(), String> {
+        if op == Pkt::CaptureTime {
+            self.capture_time_ms = decode_capture_time(data);
+            return Ok(());
+        }
+        let timestamp_ms = self.capture_time_ms.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0)
+        });
+        self.record_at(timestamp_ms, op, data)
+    }
+
+    pub fn record_at(&mut self, timestamp_ms: u64, op: Pkt, data: &[u8]) -> Result<(), String> {
+        let payload_len = u32::try_from(data.len())
+            .map_err(|_| format!("Payload of {} bytes is too large to record", data.len()))?;
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[0..8].copy_from_slice(&timestamp_ms.to_le_bytes());
+        header[8..12].copy_from_slice(&(op as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&payload_len.to_le_bytes());
+        self.encoder
+            .write_all(&header)
+            .and_then(|_| self.encoder.write_all(data))
+            .map_err(|e| e.to_string())?;
+        self.records += 1;
+
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.last_flush = Instant::now();
+            self.encoder.flush().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Terminates the zstd frame and returns the underlying writer.
+    pub fn finish(self) -> Result<W, String> {
+        let mut writer = self.encoder.finish().map_err(|e| e.to_string())?;
+        writer.flush().map_err(|e| e.to_string())?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> std::fmt::Debug for SessionWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionWriter")
+            .field("records", &self.records)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reads messages back out of a session file.
+pub struct SessionReader<R: Read> {
+    decoder: zstd::Decoder<'static, BufReader<R>>,
+}
+
+impl SessionReader<File> {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Self::new(file)
+    }
+}
+
+impl<R: Read> SessionReader<R> {
+    pub fn new(reader: R) -> Result<Self, String> {
+        let mut decoder = zstd::Decoder::new(reader).map_err(|e| e.to_string())?;
+        let mut magic = [0u8; SESSION_MAGIC.len()];
+        decoder
+            .read_exact(&mut magic)
+            .map_err(|e| format!("Failed to read session header: {}", e))?;
+        if &magic != SESSION_MAGIC {
+            return Err("Not a session recording (bad magic)".to_string());
+        }
+        Ok(Self { decoder })
+    }
+
+    /// Returns the next message, or `Ok(None)` at the end of the recording.
+    ///
+    /// A recording cut short (e.g. the app crashed mid-write) ends at the last
+    /// complete message. Messages with method ids this build does not know
+    /// are skipped.
+    pub fn next_record(&mut self) -> Result<Option<SessionRecord>, String> {
+        loop {
+            let mut header = [0u8; RECORD_HEADER_LEN];
+            match self.decoder.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.to_string()),
+            }
+            let timestamp_ms = u64::from_le_bytes(header[0..8].try_into().unwrap());
+            let method_id = u32::from_le_bytes(header[8..12].try_into().unwrap());
+            let payload_len = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+            if payload_len > MAX_PAYLOAD_LEN {
+                return Err(format!(
+                    "Session record length {} exceeds limit",
+                    payload_len
+                ));
+            }
+
+            let mut data = vec![0u8; payload_len];
+            match self.decoder.read_exact(&mut data) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    warn!(target: "app::capture", "Session recording is truncated; stopping at last complete message");
+                    return Ok(None);
+                }
+                Err(e) => return Err(e.to_string()),
+            }
+
+            let Some(op) = pkt_from_method_id(method_id) else {
+                debug!(target: "app::capture", "Skipping recorded message with unknown method id {:#x}", method_id);
+                continue;
+            };
+            return Ok(Some(SessionRecord {
+                timestamp_ms,
+                op,
+                data: Bytes::from(data),
+            }));
+        }
+    }
+}
+
+fn pkt_from_method_id(method_id: u32) -> Option<Pkt> {
+    if method_id == Pkt::ServerChangeInfo as u32 {
+        return Some(Pkt::ServerChangeInfo);
+    }
+    if method_id == Pkt::DataLoss as u32 {
+        return Some(Pkt::DataLoss);
+    }
+    if let Some(reply) = Pkt::RPC_REPLIES
+        .into_iter()
+        .find(|pkt| *pkt as u32 == method_id)
+    {
+        return Some(reply);
+    }
+    Pkt::try_from(method_id).ok()
+}
+
+/// Feeds a recorded session into `packet_sender` in place of live capture.
+///
+/// Returns when the recording is exhausted, cannot be read, or a restart is
+/// requested; an unreadable recording is returned as an error.
+pub fn replay_session(
+    packet_sender: &PacketSender,
+    restart_receiver: &watch::Receiver<bool>,
+    path: &Path,
+    timing: ReplayTiming,
+) -> Result<(), String> {
+    let mut reader = match SessionReader::open(path) {
+        Ok(reader) => reader,
+        Err(e) => {
+            warn!(
+                target: "app::capture",
+                "capture_source_init_failed method=SessionFile path={} err={}",
+                path.display(),
+                e
+            );
+            return Err(e);
+        }
+    };
+    info!(
+        target: "app::capture",
+        "Session recording opened path={} timing={:?}",
+        path.display(),
+        timing
+    );
+
+    let mut clock_origin: Option<(u64, Instant)> = None;
+    let mut last_capture_time_ms = None;
+    let mut replayed = 0u64;
+    loop {
+        let record = match reader.next_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(e) => {
+                warn!(target: "app::capture", "session_replay_error err={}", e);
+                return Err(format!("Failed to read session record: {}", e));
+            }
+        };
+
+        if timing == ReplayTiming::Original {
+            let (first_ms, started_at) =
+                *clock_origin.get_or_insert((record.timestamp_ms, Instant::now()));
+            let target = Duration::from_millis(record.timestamp_ms.saturating_sub(first_ms));
+            let elapsed = started_at.elapsed();
+            if target > elapsed {
+                std::thread::sleep(target - elapsed);
+            }
+        }
+
+        if last_capture_time_ms != Some(record.timestamp_ms) {
+            last_capture_time_ms = Some(record.timestamp_ms);
+            if packet_sender
+                .send_capture_time(record.timestamp_ms)
+                .is_err()
+            {
+                debug!(target: "app::capture", "Session replay receiver dropped");
+                return Ok(());
+            }
+        }
+        stats::record_message(record.op);
+        if packet_sender.send(record.op, record.data).is_err() {
+            debug!(target: "app::capture", "Session replay receiver dropped");
+            return Ok(());
+        }
+        replayed += 1;
+
+        if *restart_receiver.borrow() {
+            break;
+        }
+    }
+    info!(target: "app::capture", "session_replay_done messages={}", replayed);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SessionReader, SessionWriter};
+    use crate::packets::opcodes::Pkt;
+
+    #[test]
+    fn round_trips_records() {
+        let mut writer = SessionWriter::new(Vec::new()).unwrap();
+        writer.record_at(1_000, Pkt::ServerChangeInfo, &[]).unwrap();
+        writer
+            .record_at(1_250, Pkt::SyncNearDeltaInfo, b"payload")
+            .unwrap();
+        assert_eq!(writer.records(), 2);
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = SessionReader::new(bytes.as_slice()).unwrap();
+        let first = reader.next_record().unwrap().unwrap();
+        assert_eq!(first.timestamp_ms, 1_000);
+        assert_eq!(first.op, Pkt::ServerChangeInfo);
+        assert!(first.data.is_empty());
+
+        let second = reader.next_record().unwrap().unwrap();
+        assert_eq!(second.timestamp_ms, 1_250);
+        assert_eq!(second.op, Pkt::SyncNearDeltaInfo);
+        assert_eq!(&second.data[..], b"payload");
+
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn re_recorded_replays_keep_their_capture_time() {
+        let mut writer = SessionWriter::new(Vec::new()).unwrap();
+        writer
+            .record(Pkt::CaptureTime, &1_500u64.to_le_bytes())
+            .unwrap();
+        writer.record(Pkt::SyncNearDeltaInfo, b"hit").unwrap();
+        assert_eq!(writer.records(), 1);
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = SessionReader::new(bytes.as_slice()).unwrap();
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.timestamp_ms, 1_500);
+        assert_eq!(record.op, Pkt::SyncNearDeltaInfo);
+    }
+
+    #[test]
+    fn truncated_recording_ends_at_last_complete_record() {
+        let mut writer = SessionWriter::new(Vec::new()).unwrap();
+        writer.record_at(1, Pkt::EnterScene, b"first").unwrap();
+        writer.record_at(2, Pkt::EnterScene, b"second").unwrap();
+        let mut bytes = writer.finish().unwrap();
+
+        // Re-encode the decompressed stream minus its last few bytes.
+        let mut raw = zstd::decode_all(bytes.as_slice()).unwrap();
+        raw.truncate(raw.len() - 3);
+        bytes = zstd::encode_all(raw.as_slice(), 3).unwrap();
+
+        let mut reader = SessionReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(&reader.next_record().unwrap().unwrap().data[..], b"first");
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_foreign_files() {
+        let bytes = zstd::encode_all(&b"definitely not a session"[..], 3).unwrap();
+        assert!(SessionReader::new(bytes.as_slice()).is_err());
+    }
+}
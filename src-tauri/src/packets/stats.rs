@@ -0,0 +1,282 @@
+//! Counters for every stage of the capture pipeline.
+//!
+//! The capture thread and the live loop bump these as packets move from the
+//! wire to decoded protobuf messages; [`get_capture_stats`] and the periodic
+//! `capture-stats` event expose a snapshot so drops can be located without
+//! reading logs.
+
+use crate::packets::opcodes::Pkt;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+static PACKETS_SEEN: AtomicU64 = AtomicU64::new(0);
+static BYTES_SEEN: AtomicU64 = AtomicU64::new(0);
+static NON_GAME_PACKETS: AtomicU64 = AtomicU64::new(0);
+static SEQUENCE_REGRESSIONS: AtomicU64 = AtomicU64::new(0);
+static REASSEMBLY_GAPS: AtomicU64 = AtomicU64::new(0);
+static BYTES_LOST: AtomicU64 = AtomicU64::new(0);
+static ZSTD_FAILURES: AtomicU64 = AtomicU64::new(0);
+static MALFORMED_FRAMES: AtomicU64 = AtomicU64::new(0);
+static UNKNOWN_METHODS: AtomicU64 = AtomicU64::new(0);
+static CHANNEL_DROPPED: AtomicU64 = AtomicU64::new(0);
+static CHANNEL_COALESCED: AtomicU64 = AtomicU64::new(0);
+
+static MESSAGES: LazyLock<Mutex<HashMap<Pkt, MessageCounts>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Default, Clone, Copy)]
+struct MessageCounts {
+    received: u64,
+    decode_failures: u64,
+    shed: u64,
+    handled: u64,
+    handler_time: Duration,
+    handler_max_time: Duration,
+}
+
+/// Snapshot of the capture pipeline counters since the app started.
+#[derive(specta::Type, serde::Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureStats {
+    /// Packets read from the capture source.
+    pub packets_seen: u64,
+    /// Bytes read from the capture source.
+    pub bytes_seen: u64,
+    /// Packets that were not TCP or did not belong to an identified game flow.
+    pub non_game_packets: u64,
+    /// Times a flow jumped backwards far enough to reset its reassembly state.
+    pub sequence_regressions: u64,
+    /// Times the TCP reassembler gave up waiting for missing data and skipped ahead.
+    pub reassembly_gaps: u64,
+    /// Stream bytes skipped over by gap skips and frame resynchronisation.
+    pub bytes_lost: u64,
+    /// Frames or notify payloads that failed zstd decompression.
+    pub zstd_failures: u64,
+    /// Frames dropped because of an invalid length header.
+    pub malformed_frames: u64,
+    /// Notify messages whose method id is not a known [`Pkt`].
+    pub unknown_methods: u64,
+    /// Messages dropped by the capture queue's overflow policy.
+    pub channel_dropped: u64,
+    /// Messages replaced by a newer snapshot while the capture queue was full.
+    pub channel_coalesced: u64,
+    /// Per-opcode message counts, ordered by opcode name.
+    pub messages: Vec<MessageStats>,
+}
+
+/// Message counts for a single opcode.
+#[derive(specta::Type, serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageStats {
+    pub pkt: String,
+    /// Messages parsed from the capture stream.
+    pub received: u64,
+    /// Messages whose protobuf payload failed to decode.
+    pub decode_failures: u64,
+    /// Messages dropped or coalesced away by the capture queue.
+    pub shed: u64,
+    /// Messages run through their live state handler.
+    pub handled: u64,
+    /// Time spent decoding and applying the handled messages, in microseconds.
+    pub handler_time_us: u64,
+    /// Slowest single handler run, in microseconds.
+    pub handler_max_time_us: u64,
+}
+
+/// Returns the capture pipeline counters.
+#[tauri::command]
+#[specta::specta]
+pub fn get_capture_stats() -> CaptureStats {
+    snapshot()
+}
+
+/// Returns the current value of every counter.
+pub fn snapshot() -> CaptureStats {
+    let mut messages: Vec<MessageStats> = MESSAGES
+        .lock()
+        .map(|messages| {
+            messages
+                .iter()
+                .map(|(pkt, counts)| MessageStats {
+                    pkt: format!("{pkt:?}"),
+                    received: counts.received,
+                    decode_failures: counts.decode_failures,
+                    shed: counts.shed,
+                    handled: counts.handled,
+                    handler_time_us: counts.handler_time.as_micros() as u64,
+                    handler_max_time_us: counts.handler_max_time.as_micros() as u64,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    messages.sort_by(|a, b| a.pkt.cmp(&b.pkt));
+
+    CaptureStats {
+        packets_seen: PACKETS_SEEN.load(Ordering::Relaxed),
+        bytes_seen: BYTES_SEEN.load(Ordering::Relaxed),
+        non_game_packets: NON_GAME_PACKETS.load(Ordering::Relaxed),
+        sequence_regressions: SEQUENCE_REGRESSIONS.load(Ordering::Relaxed),
+        reassembly_gaps: REASSEMBLY_GAPS.load(Ordering::Relaxed),
+        bytes_lost: BYTES_LOST.load(Ordering::Relaxed),
+        zstd_failures: ZSTD_FAILURES.load(Ordering::Relaxed),
+        malformed_frames: MALFORMED_FRAMES.load(Ordering::Relaxed),
+        unknown_methods: UNKNOWN_METHODS.load(Ordering::Relaxed),
+        channel_dropped: CHANNEL_DROPPED.load(Ordering::Relaxed),
+        channel_coalesced: CHANNEL_COALESCED.load(Ordering::Relaxed),
+        messages,
+    }
+}
+
+pub fn record_packet(len: usize) {
+    PACKETS_SEEN.fetch_add(1, Ordering::Relaxed);
+    BYTES_SEEN.fetch_add(len as u64, Ordering::Relaxed);
+}
+
+pub fn record_non_game_packet() {
+    NON_GAME_PACKETS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_sequence_regression() {
+    SEQUENCE_REGRESSIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_reassembly_gap() {
+    REASSEMBLY_GAPS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_bytes_lost(len: u64) {
+    BYTES_LOST.fetch_add(len, Ordering::Relaxed);
+}
+
+pub fn record_zstd_failure() {
+    ZSTD_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_malformed_frame() {
+    MALFORMED_FRAMES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_unknown_method() {
+    UNKNOWN_METHODS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_channel_drop(pkt: Pkt) {
+    CHANNEL_DROPPED.fetch_add(1, Ordering::Relaxed);
+    record_shed(pkt);
+}
+
+pub fn record_channel_coalesce(pkt: Pkt) {
+    CHANNEL_COALESCED.fetch_add(1, Ordering::Relaxed);
+    record_shed(pkt);
+}
+
+fn record_shed(pkt: Pkt) {
+    if let Ok(mut messages) = MESSAGES.lock() {
+        messages.entry(pkt).or_default().shed += 1;
+    }
+}
+
+pub fn record_message(pkt: Pkt) {
+    if let Ok(mut messages) = MESSAGES.lock() {
+        messages.entry(pkt).or_default().received += 1;
+    }
+}
+
+pub fn record_decode_failure(pkt: Pkt) {
+    if let Ok(mut messages) = MESSAGES.lock() {
+        messages.entry(pkt).or_default().decode_failures += 1;
+    }
+}
+
+/// Handler runs timed by the live loop and not yet added to the shared counters.
+///
+/// The live loop owns one of these so timing a message takes no lock; the runs
+/// are merged into the counters by [`HandlerTimes::publish`] on the stats tick.
+#[derive(Debug, Default)]
+pub struct HandlerTimes {
+    runs: HashMap<Pkt, HandlerRuns>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct HandlerRuns {
+    handled: u64,
+    time: Duration,
+    max_time: Duration,
+}
+
+impl HandlerTimes {
+    /// Records one run of the live state handler for `pkt`.
+    pub fn record(&mut self, pkt: Pkt, elapsed: Duration) {
+        let runs = self.runs.entry(pkt).or_default();
+        runs.handled += 1;
+        runs.time += elapsed;
+        runs.max_time = runs.max_time.max(elapsed);
+    }
+
+    /// Adds the recorded runs to the counters and starts over.
+    pub fn publish(&mut self) {
+        if self.runs.is_empty() {
+            return;
+        }
+        if let Ok(mut messages) = MESSAGES.lock() {
+            for (pkt, runs) in self.runs.drain() {
+                let counts = messages.entry(pkt).or_default();
+                counts.handled += runs.handled;
+                counts.handler_time += runs.time;
+                counts.handler_max_time = counts.handler_max_time.max(runs.max_time);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HandlerTimes, record_decode_failure, record_message, snapshot};
+    use crate::packets::opcodes::Pkt;
+    use std::time::Duration;
+
+    fn counts(pkt: &str) -> (u64, u64) {
+        snapshot()
+            .messages
+            .iter()
+            .find(|m| m.pkt == pkt)
+            .map(|m| (m.received, m.decode_failures))
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn counts_messages_per_opcode() {
+        let (received, failures) = counts("NotifyTimerUpdate");
+        record_message(Pkt::NotifyTimerUpdate);
+        record_message(Pkt::NotifyTimerUpdate);
+        record_decode_failure(Pkt::NotifyTimerUpdate);
+
+        let (new_received, new_failures) = counts("NotifyTimerUpdate");
+        assert!(new_received >= received + 2);
+        assert!(new_failures > failures);
+
+        let messages = snapshot().messages;
+        assert!(messages.windows(2).all(|w| w[0].pkt <= w[1].pkt));
+    }
+
+    #[test]
+    fn tracks_handler_time_per_opcode() {
+        let mut times = HandlerTimes::default();
+        times.record(Pkt::NotifyTimerList, Duration::from_micros(40));
+        times.record(Pkt::NotifyTimerList, Duration::from_micros(900));
+        times.publish();
+        // Published runs are not added twice
+        times.publish();
+
+        let stats = snapshot()
+            .messages
+            .into_iter()
+            .find(|m| m.pkt == "NotifyTimerList")
+            .unwrap();
+        assert!(stats.handled >= 2);
+        assert!(stats.handler_time_us >= 940);
+        assert!(stats.handler_max_time_us >= 900);
+    }
+}
@@ -1,16 +1,20 @@
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+/// Identifies one direction of a TCP connection (IPv4 or IPv6).
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Server {
-    src_addr: [u8; 4],
+    src_addr: IpAddr,
     src_port: u16,
-    dst_addr: [u8; 4],
+    dst_addr: IpAddr,
     dst_port: u16,
 }
 
 impl Server {
-    pub fn new(src_addr: [u8; 4], src_port: u16, dst_addr: [u8; 4], dst_port: u16) -> Self {
+    pub fn new(src_addr: IpAddr, src_port: u16, dst_addr: IpAddr, dst_port: u16) -> Self {
         Self {
             src_addr,
             src_port,
@@ -18,25 +22,30 @@ impl Server {
             dst_port,
         }
     }
+
+    /// The sending endpoint.
+    pub fn source(&self) -> SocketAddr {
+        SocketAddr::new(self.src_addr, self.src_port)
+    }
+
+    /// The same connection seen in the opposite direction.
+    pub fn reversed(&self) -> Self {
+        Self::new(self.dst_addr, self.dst_port, self.src_addr, self.src_port)
+    }
 }
 
 impl fmt::Display for Server {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // SocketAddr brackets IPv6 addresses: [2001:db8::1]:5003
         write!(
             f,
-            "{}:{} -> {}:{}",
-            ip_to_str(&self.src_addr),
-            self.src_port,
-            ip_to_str(&self.dst_addr),
-            self.dst_port
+            "{} -> {}",
+            SocketAddr::new(self.src_addr, self.src_port),
+            SocketAddr::new(self.dst_addr, self.dst_port)
         )
     }
 }
 
-fn ip_to_str(ip: &[u8; 4]) -> String {
-    format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3])
-}
-
 #[inline]
 pub fn tcp_sequence_before(a: u32, b: u32) -> bool {
     (a.wrapping_sub(b) as i32) < 0
@@ -52,16 +61,30 @@ pub struct TCPReassembler {
     cache: BTreeMap<u32, Vec<u8>>, // sequence -> payload
     next_seq: Option<u32>,         // next expected sequence
     buffered_bytes: usize,         // Total bytes currently in the cache
+    gap_timeout: Duration,         // How long to wait for a missing segment
+    gap_since: Option<Instant>,    // When the stream last stalled on a hole
+    lost_bytes: u64,               // Bytes skipped since the last `take_lost_bytes`
 }
 
 const MAX_TCP_CACHE_SIZE: usize = 5 * 1024 * 1024; // 5MB limit
+const MAX_TCP_CACHE_SEGMENTS: usize = 4096;
+
+/// How long a hole in the stream is waited for before the missing bytes are given up.
+pub const DEFAULT_GAP_TIMEOUT: Duration = Duration::from_secs(2);
 
 impl TCPReassembler {
     pub fn new() -> Self {
+        Self::with_gap_timeout(DEFAULT_GAP_TIMEOUT)
+    }
+
+    pub fn with_gap_timeout(gap_timeout: Duration) -> Self {
         Self {
             cache: BTreeMap::new(),
             next_seq: None,
             buffered_bytes: 0,
+            gap_timeout,
+            gap_since: None,
+            lost_bytes: 0,
         }
     }
 
@@ -69,6 +92,21 @@ impl TCPReassembler {
     /// Returns Some(Vec<u8>) when new contiguous bytes starting at the
     /// expected sequence become available.
     pub fn insert_segment(&mut self, sequence_number: u32, payload: &[u8]) -> Option<Vec<u8>> {
+        self.insert_segment_at(sequence_number, payload, Instant::now())
+            .map(Cow::into_owned)
+    }
+
+    /// Same as [`Self::insert_segment`] with an explicit arrival time, which
+    /// decides when a hole in the stream has been waited on for too long.
+    ///
+    /// A segment that arrives in order while nothing is buffered is returned
+    /// as a view into `payload` without being copied.
+    pub fn insert_segment_at<'a>(
+        &mut self,
+        sequence_number: u32,
+        payload: &'a [u8],
+        now: Instant,
+    ) -> Option<Cow<'a, [u8]>> {
         if payload.is_empty() {
             return None;
         }
@@ -93,43 +131,31 @@ impl TCPReassembler {
             data = &data[overlap..];
         }
 
-        // Avoid storing duplicates unless the new payload is longer.
-        match self.cache.get_mut(&start_seq) {
-            Some(existing) => {
-                if data.len() > existing.len() {
-                    self.buffered_bytes -= existing.len();
-                    existing.clear();
-                    existing.extend_from_slice(data);
-                    self.buffered_bytes += existing.len();
-                }
-            }
-            None => {
-                self.cache.insert(start_seq, data.to_vec());
-                self.buffered_bytes += data.len();
-            }
+        // Fast path: in order with no holes to fill.
+        if start_seq == expected && self.cache.is_empty() {
+            self.next_seq = Some(expected.wrapping_add(data.len() as u32));
+            self.gap_since = None;
+            return Some(Cow::Borrowed(data));
         }
 
+        // Avoid storing duplicates unless the new payload is longer.
+        self.store(start_seq, data);
+
         // Gap Skipping / Buffer Limit Enforcement
-        if self.buffered_bytes > MAX_TCP_CACHE_SIZE {
-            // If the buffer is too full, it implies we have a large gap we are waiting for.
-            // We should give up on the missing data and jump to the earliest available packet.
-            if let Some((&first_cached_seq, _)) = self.cache.iter().next() {
-                log::warn!(
-                    "TCPReassembler buffer exceeded limit ({} bytes). Skipping gap from {:?} to {}",
-                    self.buffered_bytes,
-                    self.next_seq,
-                    first_cached_seq
-                );
-                // Advance expectation to the first thing we actually have
-                self.next_seq = Some(first_cached_seq);
+        if !self.cache.contains_key(&expected) {
+            let gap_since = *self.gap_since.get_or_insert(now);
+            let over_limit = self.buffered_bytes > MAX_TCP_CACHE_SIZE
+                || self.cache.len() > MAX_TCP_CACHE_SEGMENTS;
+            let timed_out = now.saturating_duration_since(gap_since) >= self.gap_timeout;
+            if over_limit || timed_out {
+                self.skip_gap(expected, over_limit);
             }
         }
 
         let mut cursor = self.next_seq.unwrap();
         let mut output: Vec<u8> = Vec::new();
 
-        while let Some(mut segment) = self.cache.remove(&cursor) {
-            self.buffered_bytes -= segment.len();
+        while let Some(mut segment) = self.take_segment_at(cursor) {
             cursor = cursor.wrapping_add(segment.len() as u32);
             if output.is_empty() {
                 output = std::mem::take(&mut segment);
@@ -142,24 +168,131 @@ impl TCPReassembler {
             None
         } else {
             self.next_seq = Some(cursor);
-            Some(output)
+            // Still waiting on a later hole; its wait starts now.
+            self.gap_since = (!self.cache.is_empty()).then_some(now);
+            Some(Cow::Owned(output))
+        }
+    }
+
+    fn store(&mut self, start_seq: u32, data: &[u8]) {
+        match self.cache.get_mut(&start_seq) {
+            Some(existing) => {
+                if data.len() > existing.len() {
+                    self.buffered_bytes -= existing.len();
+                    existing.clear();
+                    existing.extend_from_slice(data);
+                    self.buffered_bytes += existing.len();
+                }
+            }
+            None => {
+                self.cache.insert(start_seq, data.to_vec());
+                self.buffered_bytes += data.len();
+            }
+        }
+    }
+
+    /// Gives up on the bytes missing at `expected` and jumps to the earliest
+    /// segment actually buffered.
+    fn skip_gap(&mut self, expected: u32, over_limit: bool) {
+        let Some(first_cached_seq) = self
+            .cache
+            .keys()
+            .copied()
+            .min_by_key(|seq| seq.wrapping_sub(expected))
+        else {
+            return;
+        };
+        let skipped = first_cached_seq.wrapping_sub(expected);
+        log::warn!(
+            target: "app::capture",
+            "tcp_gap_skipped from={} to={} skipped_bytes={} buffered_bytes={} buffered_segments={} reason={}",
+            expected,
+            first_cached_seq,
+            skipped,
+            self.buffered_bytes,
+            self.cache.len(),
+            if over_limit { "limit" } else { "timeout" }
+        );
+        crate::packets::stats::record_reassembly_gap();
+        crate::packets::stats::record_bytes_lost(u64::from(skipped));
+        self.lost_bytes += u64::from(skipped);
+        self.next_seq = Some(first_cached_seq);
+        self.gap_since = None;
+    }
+
+    /// Removes the segment covering `cursor`, trimming any bytes before it.
+    /// Segments that end before `cursor` are dropped along the way.
+    fn take_segment_at(&mut self, cursor: u32) -> Option<Vec<u8>> {
+        if let Some(segment) = self.cache.remove(&cursor) {
+            self.buffered_bytes -= segment.len();
+            return Some(segment);
+        }
+        let stale: Vec<u32> = self
+            .cache
+            .keys()
+            .copied()
+            .filter(|seq| tcp_sequence_before(*seq, cursor))
+            .collect();
+        let mut found: Option<Vec<u8>> = None;
+        for seq in stale {
+            let Some(segment) = self.cache.remove(&seq) else {
+                continue;
+            };
+            self.buffered_bytes -= segment.len();
+            let overlap = cursor.wrapping_sub(seq) as usize;
+            if overlap < segment.len()
+                && found
+                    .as_ref()
+                    .is_none_or(|f| f.len() < segment.len() - overlap)
+            {
+                found = Some(segment[overlap..].to_vec());
+            }
         }
+        found
     }
 
     pub fn reset(&mut self, next_seq: Option<u32>) {
         self.cache.clear();
         self.buffered_bytes = 0;
         self.next_seq = next_seq;
+        self.gap_since = None;
     }
 
     pub fn next_sequence(&self) -> Option<u32> {
         self.next_seq
     }
+
+    /// Returns the bytes skipped over since the last call and clears the count.
+    pub fn take_lost_bytes(&mut self) -> u64 {
+        std::mem::take(&mut self.lost_bytes)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::TCPReassembler;
+    use super::{MAX_TCP_CACHE_SEGMENTS, Server, TCPReassembler};
+    use std::borrow::Cow;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn formats_v4_and_v6_servers() {
+        let v4 = Server::new(
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            5003,
+            IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)),
+            50000,
+        );
+        assert_eq!(v4.to_string(), "10.0.0.1:5003 -> 192.168.1.2:50000");
+
+        let v6 = Server::new(
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            5003,
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+            50000,
+        );
+        assert_eq!(v6.to_string(), "[2001:db8::1]:5003 -> [::1]:50000");
+    }
 
     #[test]
     fn reassembles_in_order() {
@@ -212,4 +345,82 @@ mod tests {
             Some(b"xyz".to_vec())
         );
     }
+
+    #[test]
+    fn skips_gap_after_timeout() {
+        let mut reassembler = TCPReassembler::with_gap_timeout(Duration::from_secs(2));
+        let start = Instant::now();
+        assert!(reassembler.insert_segment_at(100, b"abc", start).is_some());
+        // 103..106 is lost
+        assert!(reassembler.insert_segment_at(106, b"ghi", start).is_none());
+        assert!(
+            reassembler
+                .insert_segment_at(109, b"jkl", start + Duration::from_secs(1))
+                .is_none()
+        );
+        assert_eq!(reassembler.take_lost_bytes(), 0);
+        assert_eq!(
+            reassembler
+                .insert_segment_at(112, b"mno", start + Duration::from_secs(2))
+                .as_deref(),
+            Some(&b"ghijklmno"[..])
+        );
+        assert_eq!(reassembler.take_lost_bytes(), 3);
+        assert_eq!(reassembler.take_lost_bytes(), 0);
+        assert_eq!(reassembler.next_sequence(), Some(115));
+    }
+
+    #[test]
+    fn skips_gap_when_segment_cap_is_reached() {
+        let mut reassembler = TCPReassembler::new();
+        let now = Instant::now();
+        assert!(reassembler.insert_segment_at(0, b"a", now).is_some());
+        // Every other byte is missing, so nothing becomes contiguous
+        for i in 0..MAX_TCP_CACHE_SEGMENTS as u32 {
+            assert!(
+                reassembler
+                    .insert_segment_at(2 + i * 2, b"x", now)
+                    .is_none()
+            );
+        }
+        assert_eq!(
+            reassembler
+                .insert_segment_at(2 + MAX_TCP_CACHE_SEGMENTS as u32 * 2, b"y", now)
+                .as_deref(),
+            Some(&b"x"[..])
+        );
+        assert_eq!(reassembler.take_lost_bytes(), 1);
+    }
+
+    #[test]
+    fn drains_segments_overlapping_the_cursor() {
+        let mut reassembler = TCPReassembler::new();
+        assert!(reassembler.insert_segment(0, b"ab").is_some());
+        assert!(reassembler.insert_segment(5, b"fghij").is_none());
+        assert!(reassembler.insert_segment(3, b"defg").is_none());
+        assert_eq!(
+            reassembler.insert_segment(2, b"c"),
+            Some(b"cdefghij".to_vec())
+        );
+    }
+
+    #[test]
+    fn in_order_segments_are_not_copied() {
+        let mut reassembler = TCPReassembler::new();
+        let now = Instant::now();
+        assert!(matches!(
+            reassembler.insert_segment_at(10, b"abc", now),
+            Some(Cow::Borrowed(b"abc"))
+        ));
+        assert!(reassembler.insert_segment_at(16, b"ghi", now).is_none());
+        // Filling a hole drains the buffered segments, which needs an owned buffer
+        assert!(matches!(
+            reassembler.insert_segment_at(13, b"def", now),
+            Some(Cow::Owned(_))
+        ));
+        assert!(matches!(
+            reassembler.insert_segment_at(19, b"jkl", now),
+            Some(Cow::Borrowed(b"jkl"))
+        ));
+    }
 }
@@ -0,0 +1,423 @@
+//! Schema-less protobuf inspector.
+//!
+//! [`inspect`] decodes any payload into a tree of wire fields without knowing
+//! its message type. Length-delimited fields are guessed to be nested
+//! messages, strings, packed varints or raw bytes, in that order of checks.
+//!
+//! [`SchemaDiff`] compares the wire tree of a message with the same message
+//! after a decode/encode round trip through its prost type. Prost drops fields
+//! it does not know, so anything that disappears is missing from
+//! `blueprotobuf_package.rs`. Every scalar in that file is `optional`, so
+//! fields sent with default values survive the round trip and are not
+//! reported.
+
+use crate::packets::opcodes::Pkt;
+use blueprotobuf_lib::blueprotobuf;
+use prost::Message;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Nested messages deeper than this are shown as raw bytes.
+const MAX_DEPTH: usize = 32;
+/// Bytes shown in the example of a missing field.
+const EXAMPLE_LEN: usize = 32;
+
+/// One field as it appears on the wire.
+#[derive(specta::Type, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WireField {
+    pub number: u32,
+    pub value: WireValue,
+}
+
+/// A field value, typed only as far as the wire format allows.
+#[derive(specta::Type, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum WireValue {
+    /// Wire type 0; `zigzag` is the value read as a `sint64`.
+    Varint { value: u64, zigzag: i64 },
+    /// Wire type 1.
+    Fixed64 { value: u64, double: f64 },
+    /// Wire type 5.
+    Fixed32 { value: u32, float: f32 },
+    /// Wire type 2 that parses as a message.
+    Message { fields: Vec<WireField> },
+    /// Wire type 2 that is printable UTF-8.
+    String { value: String },
+    /// Wire type 2 that is a run of varints.
+    PackedVarints { values: Vec<u64> },
+    /// Wire type 2 that matched nothing else, hex encoded.
+    Bytes { hex: String },
+}
+
+impl WireValue {
+    /// Protobuf name of the wire type.
+    pub fn wire_type(&self) -> &'static str {
+        match self {
+            WireValue::Varint { .. } => "varint",
+            WireValue::Fixed64 { .. } => "fixed64",
+            WireValue::Fixed32 { .. } => "fixed32",
+            _ => "len",
+        }
+    }
+
+    /// Short human-readable rendering, used as an example value.
+    fn summary(&self) -> String {
+        match self {
+            WireValue::Varint { value, .. } => value.to_string(),
+            WireValue::Fixed64 { value, double } => format!("{value} ({double})"),
+            WireValue::Fixed32 { value, float } => format!("{value} ({float})"),
+            WireValue::Message { fields } => format!("message with {} fields", fields.len()),
+            WireValue::String { value } => format!("{value:?}"),
+            WireValue::PackedVarints { values } => format!("{values:?}"),
+            WireValue::Bytes { hex } => hex.chars().take(EXAMPLE_LEN * 2).collect(),
+        }
+    }
+}
+
+/// Decodes `payload` as a protobuf message of unknown type.
+///
+/// # Returns
+///
+/// * `Result<Vec<WireField>, String>` - The top-level fields, or an error if
+///   the payload is not a valid protobuf message.
+pub fn inspect(payload: &[u8]) -> Result<Vec<WireField>, String> {
+    parse_message(payload, 0)
+}
+
+fn parse_message(mut buf: &[u8], depth: usize) -> Result<Vec<WireField>, String> {
+    let mut fields = Vec::new();
+    while !buf.is_empty() {
+        let key = read_varint(&mut buf).ok_or("truncated field key")?;
+        let number = u32::try_from(key >> 3)
+            .ok()
+            .filter(|n| (1..1 << 29).contains(n))
+            .ok_or_else(|| format!("invalid field number {}", key >> 3))?;
+        let value = match key & 7 {
+            0 => {
+                let value = read_varint(&mut buf).ok_or("truncated varint")?;
+                WireValue::Varint {
+                    value,
+                    zigzag: ((value >> 1) as i64) ^ -((value & 1) as i64),
+                }
+            }
+            1 => {
+                let bytes: [u8; 8] = take(&mut buf, 8)
+                    .ok_or("truncated fixed64")?
+                    .try_into()
+                    .map_err(|_| "truncated fixed64")?;
+                WireValue::Fixed64 {
+                    value: u64::from_le_bytes(bytes),
+                    double: f64::from_le_bytes(bytes),
+                }
+            }
+            2 => {
+                let len = read_varint(&mut buf).ok_or("truncated length")?;
+                let len = usize::try_from(len).map_err(|_| "length overflow")?;
+                let data = take(&mut buf, len).ok_or("length past end of payload")?;
+                guess_len_delimited(data, depth)
+            }
+            5 => {
+                let bytes: [u8; 4] = take(&mut buf, 4)
+                    .ok_or("truncated fixed32")?
+                    .try_into()
+                    .map_err(|_| "truncated fixed32")?;
+                WireValue::Fixed32 {
+                    value: u32::from_le_bytes(bytes),
+                    float: f32::from_le_bytes(bytes),
+                }
+            }
+            wire_type => return Err(format!("unsupported wire type {wire_type}")),
+        };
+        fields.push(WireField { number, value });
+    }
+    Ok(fields)
+}
+
+fn guess_len_delimited(data: &[u8], depth: usize) -> WireValue {
+    if let Ok(value) = std::str::from_utf8(data)
+        && !data.is_empty()
+        && value
+            .chars()
+            .all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+    {
+        return WireValue::String {
+            value: value.to_string(),
+        };
+    }
+    if depth < MAX_DEPTH
+        && !data.is_empty()
+        && let Ok(fields) = parse_message(data, depth + 1)
+    {
+        return WireValue::Message { fields };
+    }
+    let mut rest = data;
+    let mut values = Vec::new();
+    while let Some(value) = read_varint(&mut rest) {
+        values.push(value);
+    }
+    if rest.is_empty() && !values.is_empty() {
+        return WireValue::PackedVarints { values };
+    }
+    WireValue::Bytes {
+        hex: hex::encode(data),
+    }
+}
+
+fn read_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (i, &byte) in buf.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            *buf = &buf[i + 1..];
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if buf.len() < len {
+        return None;
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Some(head)
+}
+
+type Reencoder = fn(&[u8]) -> Result<Vec<u8>, String>;
+
+fn reencode<T: Message + Default>(payload: &[u8]) -> Result<Vec<u8>, String> {
+    T::decode(payload)
+        .map(|message| message.encode_to_vec())
+        .map_err(|e| e.to_string())
+}
+
+/// Returns the decode/encode round trip through the prost type for `pkt`,
+/// for the opcodes that have one.
+fn schema_for(pkt: Pkt) -> Option<Reencoder> {
+    let reencoder: Reencoder = match pkt {
+        Pkt::EnterScene => reencode::<blueprotobuf::EnterScene>,
+        Pkt::SyncNearEntities => reencode::<blueprotobuf::SyncNearEntities>,
+        Pkt::SyncContainerData => reencode::<blueprotobuf::SyncContainerData>,
+        Pkt::SyncContainerDirtyData => reencode::<blueprotobuf::SyncContainerDirtyData>,
+        Pkt::SyncServerTime => reencode::<blueprotobuf::SyncServerTime>,
+        Pkt::SyncDungeonData => reencode::<blueprotobuf::SyncDungeonData>,
+        Pkt::SyncDungeonDirtyData => reencode::<blueprotobuf::SyncDungeonDirtyData>,
+        Pkt::SyncToMeDeltaInfo => reencode::<blueprotobuf::SyncToMeDeltaInfo>,
+        Pkt::SyncNearDeltaInfo => reencode::<blueprotobuf::SyncNearDeltaInfo>,
+        Pkt::NotifyReviveUser => reencode::<blueprotobuf::NotifyReviveUser>,
+        Pkt::BuffInfoSync => reencode::<blueprotobuf::BuffInfoSync>,
+        Pkt::SyncClientUseSkill => reencode::<blueprotobuf::SyncClientUseSkill>,
+        Pkt::GetSocialDataReply => reencode::<blueprotobuf::GetSocialDataReply>,
+        _ => return None,
+    };
+    Some(reencoder)
+}
+
+/// Looks up an opcode by its `Pkt` variant name, e.g. `SyncNearDeltaInfo`.
+pub fn pkt_from_name(name: &str) -> Option<Pkt> {
+    (0..=0xff)
+        .filter_map(|id| Pkt::try_from(id).ok())
+        .chain(Pkt::RPC_REPLIES)
+        .find(|pkt| format!("{pkt:?}") == name)
+}
+
+/// A field that is on the wire but not in the prost schema.
+#[derive(specta::Type, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingField {
+    /// Opcode name followed by the field numbers leading to the field, e.g.
+    /// `SyncNearDeltaInfo.1.7.2.18`.
+    pub path: String,
+    pub wire_type: String,
+    /// Times the field was seen.
+    pub count: u64,
+    /// The first value seen.
+    pub example: String,
+}
+
+/// Collects fields missing from the schema across many messages.
+#[derive(Debug, Default)]
+pub struct SchemaDiff {
+    missing: BTreeMap<(String, &'static str), MissingField>,
+}
+
+impl SchemaDiff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compares one message against the schema of `pkt`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, String>` - Whether `pkt` has a schema to compare against,
+    ///   or an error if the payload fails to decode.
+    pub fn add(&mut self, pkt: Pkt, payload: &[u8]) -> Result<bool, String> {
+        let Some(reencode) = schema_for(pkt) else {
+            return Ok(false);
+        };
+        let wire = inspect(payload)?;
+        let known = inspect(&reencode(payload)?)?;
+        self.compare(&format!("{pkt:?}"), &wire, &known);
+        Ok(true)
+    }
+
+    fn compare(&mut self, path: &str, wire: &[WireField], known: &[WireField]) {
+        let mut numbers: Vec<u32> = wire.iter().map(|field| field.number).collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+        for number in numbers {
+            let path = format!("{path}.{number}");
+            let known_values: Vec<&WireValue> = known
+                .iter()
+                .filter(|field| field.number == number)
+                .map(|field| &field.value)
+                .collect();
+            let wire_values = wire
+                .iter()
+                .filter(|field| field.number == number)
+                .map(|field| &field.value);
+            if known_values.is_empty() {
+                for value in wire_values {
+                    self.record(&path, value);
+                }
+                continue;
+            }
+            for (value, known_value) in wire_values.zip(known_values) {
+                if let (
+                    WireValue::Message { fields },
+                    WireValue::Message {
+                        fields: known_fields,
+                    },
+                ) = (value, known_value)
+                {
+                    self.compare(&path, fields, known_fields);
+                }
+            }
+        }
+    }
+
+    fn record(&mut self, path: &str, value: &WireValue) {
+        self.missing
+            .entry((path.to_string(), value.wire_type()))
+            .or_insert_with(|| MissingField {
+                path: path.to_string(),
+                wire_type: value.wire_type().to_string(),
+                count: 0,
+                example: value.summary(),
+            })
+            .count += 1;
+    }
+
+    /// Returns the missing fields ordered by path.
+    pub fn missing_fields(&self) -> Vec<MissingField> {
+        self.missing.values().cloned().collect()
+    }
+}
+
+/// The wire tree of a payload and, when its opcode is given, the fields the
+/// prost schema does not know.
+#[derive(specta::Type, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtobufInspection {
+    pub fields: Vec<WireField>,
+    pub missing_fields: Vec<MissingField>,
+}
+
+/// Decodes a raw protobuf payload without a schema.
+///
+/// # Arguments
+///
+/// * `payload_hex` - The payload, hex encoded (e.g. a dumped unknown method sample).
+/// * `pkt` - Optional opcode name; when set the payload is also diffed against its prost type.
+///
+/// # Returns
+///
+/// * `Result<ProtobufInspection, String>` - The decoded wire tree.
+#[tauri::command]
+#[specta::specta]
+pub fn inspect_protobuf(
+    payload_hex: String,
+    pkt: Option<String>,
+) -> Result<ProtobufInspection, String> {
+    let payload = hex::decode(payload_hex.trim()).map_err(|e| format!("Invalid hex: {}", e))?;
+    inspect_payload(&payload, pkt.as_deref())
+}
+
+/// Decodes `payload` and, when `pkt` names an opcode, diffs it against that opcode's prost type.
+pub fn inspect_payload(payload: &[u8], pkt: Option<&str>) -> Result<ProtobufInspection, String> {
+    let fields = inspect(payload)?;
+    let mut diff = SchemaDiff::new();
+    if let Some(name) = pkt {
+        let pkt = pkt_from_name(name).ok_or_else(|| format!("Unknown opcode {}", name))?;
+        if !diff.add(pkt, payload)? {
+            return Err(format!("No prost schema registered for {}", name));
+        }
+    }
+    Ok(ProtobufInspection {
+        fields,
+        missing_fields: diff.missing_fields(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SchemaDiff, WireValue, inspect, pkt_from_name};
+    use crate::packets::opcodes::Pkt;
+    use blueprotobuf_lib::blueprotobuf;
+    use prost::Message;
+
+    #[test]
+    fn inspects_nested_messages_and_scalars() {
+        let message = blueprotobuf::SyncClientUseSkill {
+            skill_target_uuid: Some(-2),
+            skill_level_id: Some(1401),
+        };
+        let fields = inspect(&message.encode_to_vec()).unwrap();
+        assert_eq!(fields.len(), 2);
+        assert!(matches!(
+            fields[1].value,
+            WireValue::Varint { value: 1401, .. }
+        ));
+
+        // field 1 = { field 2 = "hi" }
+        let nested = [0x0a, 0x04, 0x12, 0x02, b'h', b'i'];
+        let fields = inspect(&nested).unwrap();
+        let WireValue::Message { fields: inner } = &fields[0].value else {
+            panic!("expected a nested message, got {:?}", fields[0].value);
+        };
+        assert_eq!(
+            inner[0].value,
+            WireValue::String {
+                value: "hi".to_string()
+            }
+        );
+        assert!(inspect(&[0x0b]).is_err());
+    }
+
+    #[test]
+    fn reports_fields_missing_from_schema() {
+        let mut payload = blueprotobuf::SyncClientUseSkill {
+            skill_target_uuid: Some(7),
+            skill_level_id: Some(1),
+        }
+        .encode_to_vec();
+        // Unknown field 9 (varint 300), twice.
+        payload.extend_from_slice(&[0x48, 0xac, 0x02, 0x48, 0xac, 0x02]);
+
+        let mut diff = SchemaDiff::new();
+        assert!(diff.add(Pkt::SyncClientUseSkill, &payload).unwrap());
+        let missing = diff.missing_fields();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].path, "SyncClientUseSkill.9");
+        assert_eq!((missing[0].count, missing[0].example.as_str()), (2, "300"));
+
+        assert!(!diff.add(Pkt::NotifyTimerList, &payload).unwrap());
+        assert_eq!(
+            pkt_from_name("SyncNearDeltaInfo"),
+            Some(Pkt::SyncNearDeltaInfo)
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! End-to-end regression tests for the live pipeline.
+//!
+//! Each scenario is written as a pcap of synthetic server traffic around the
+//! recorded `fixtures/test_add_packet.json` frame, then replayed through
+//! capture, TCP and frame reassembly, decoding and the live state. The
+//! finished encounters are compared against `golden/<scenario>.json`.
+//!
+//! Only fields that do not depend on the wall clock are compared. After an
+//! intended behaviour change, rerun with `UPDATE_GOLDEN=1` to rewrite the
+//! golden files and review their diff; `tests/README.md` has the full workflow.
+
+use blueprotobuf_lib::blueprotobuf::{
+    AoiSyncDelta, Attr, AttrCollection, EDamageType, SyncDamageInfo, SyncNearDeltaInfo,
+    SyncServerTime,
+};
+use resonance_logs_lib::encoder::{self, Pkt, TcpFlow};
+use resonance_logs_lib::headless::{self, EncounterReport};
+use serde_json::{Map, Value};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::Path;
+
+const MSS: usize = 1400;
+
+/// The local player in the fixture's `SyncContainerData`.
+const LOCAL_PLAYER_UID: i64 = 1231757;
+const ALLY_UID: i64 = 2_000_001;
+const TRASH_UID: i64 = 3_000_001;
+const BOSS_UID: i64 = 3_000_002;
+const UNNAMED_MONSTER_UID: i64 = 3_000_003;
+
+/// A regular monster and a listed boss from `meter-data/MonsterName*.json`.
+const TRASH_MONSTER_ID: u64 = 101;
+const BOSS_MONSTER_ID: u64 = 203;
+
+const ATTR_NAME: i32 = 0x01;
+const ATTR_ID: i32 = 0x0a;
+const CRIT_FLAG: i32 = 0b1;
+
+fn char_uuid(uid: i64) -> i64 {
+    (uid << 16) | 640
+}
+
+fn monster_uuid(uid: i64) -> i64 {
+    (uid << 16) | 64
+}
+
+/// Server -> client traffic of a capture, in the order it was sent.
+#[derive(Default)]
+struct Capture {
+    packets: Vec<Vec<u8>>,
+}
+
+impl Capture {
+    /// Opens a game connection from `server`; its first frame only identifies
+    /// the server and is not decoded.
+    fn connect(&mut self, server: Ipv4Addr) -> TcpFlow {
+        let mut flow = TcpFlow::new(
+            SocketAddrV4::new(server, 5003),
+            SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 2), 50000),
+            0x1000,
+        );
+        let hello = encoder::notify(Pkt::SyncServerTime, &SyncServerTime::default(), false);
+        self.send(&mut flow, &encoder::frame_down(&hello, false));
+        flow
+    }
+
+    fn send(&mut self, flow: &mut TcpFlow, stream: &[u8]) {
+        self.packets.extend(flow.send(stream, MSS));
+    }
+
+    /// Sends the recorded login frame: local player, scene and dungeon state.
+    fn send_fixture(&mut self, flow: &mut TcpFlow) {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/test_add_packet.json");
+        let text = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("failed to read {}: {e}", path.display()));
+        let frame: Vec<u8> = serde_json::from_str(&text).expect("fixture is a JSON byte array");
+        self.send(flow, &frame);
+    }
+
+    /// Sends each message as a `SyncNearDeltaInfo` notify, bundled in one compressed frame.
+    fn send_deltas(&mut self, flow: &mut TcpFlow, messages: &[SyncNearDeltaInfo]) {
+        let notifies: Vec<u8> = messages
+            .iter()
+            .flat_map(|message| encoder::notify(Pkt::SyncNearDeltaInfo, message, false))
+            .collect();
+        self.send(flow, &encoder::frame_down(&notifies, true));
+    }
+
+    /// Replays the capture through the headless analyzer.
+    fn replay(&self, name: &str) -> Vec<EncounterReport> {
+        let path = std::env::temp_dir().join(format!(
+            "resonance-golden-{}-{name}.pcap",
+            std::process::id()
+        ));
+        std::fs::write(&path, encoder::pcap(&self.packets)).expect("write capture");
+        let reports = headless::analyze_file(&path);
+        let _ = std::fs::remove_file(&path);
+        reports.expect("replay capture")
+    }
+}
+
+fn varint(value: u64) -> Vec<u8> {
+    let mut buf = Vec::new();
+    prost::encoding::encode_varint(value, &mut buf);
+    buf
+}
+
+fn attrs(uuid: i64, attrs: Vec<Attr>) -> AoiSyncDelta {
+    AoiSyncDelta {
+        uuid: Some(uuid),
+        attrs: Some(AttrCollection {
+            uuid: Some(uuid),
+            attrs,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn player_name(name: &str) -> Attr {
+    let mut raw_data = varint(name.len() as u64);
+    raw_data.extend_from_slice(name.as_bytes());
+    Attr {
+        id: Some(ATTR_NAME),
+        raw_data: Some(raw_data),
+    }
+}
+
+fn monster_id(id: u64) -> Attr {
+    Attr {
+        id: Some(ATTR_ID),
+        raw_data: Some(varint(id)),
+    }
+}
+
+fn hit(attacker_uuid: i64, value: i64) -> SyncDamageInfo {
+    SyncDamageInfo {
+        attacker_uuid: Some(attacker_uuid),
+        value: Some(value),
+        owner_id: Some(1),
+        ..Default::default()
+    }
+}
+
+fn crit(attacker_uuid: i64, value: i64) -> SyncDamageInfo {
+    SyncDamageInfo {
+        type_flag: Some(CRIT_FLAG),
+        ..hit(attacker_uuid, value)
+    }
+}
+
+fn lucky(attacker_uuid: i64, value: i64) -> SyncDamageInfo {
+    SyncDamageInfo {
+        value: None,
+        lucky_value: Some(value),
+        ..hit(attacker_uuid, value)
+    }
+}
+
+fn heal(attacker_uuid: i64, value: i64) -> SyncDamageInfo {
+    SyncDamageInfo {
+        r#type: Some(EDamageType::Heal as i32),
+        ..hit(attacker_uuid, value)
+    }
+}
+
+/// A hit from a monster, which reports the health it took.
+fn monster_hit(attacker_uuid: i64, value: i64) -> SyncDamageInfo {
+    SyncDamageInfo {
+        hp_lessen_value: Some(value),
+        ..hit(attacker_uuid, value)
+    }
+}
+
+/// Copies `keys` of the object `value`.
+fn pick(value: &Value, keys: &[&str]) -> Value {
+    let fields: Map<String, Value> = keys
+        .iter()
+        .map(|key| (key.to_string(), value[*key].clone()))
+        .collect();
+    Value::Object(fields)
+}
+
+fn pick_each(values: &Value, keys: &[&str]) -> Value {
+    values
+        .as_array()
+        .map(|values| values.iter().map(|value| pick(value, keys)).collect())
+        .unwrap_or_default()
+}
+
+/// The parts of a report that do not depend on when the capture was replayed.
+fn project(report: &EncounterReport) -> Value {
+    let report = serde_json::to_value(report).expect("serialize report");
+    let entities: Vec<Value> = report["entities"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|entity| {
+            let mut projected = pick(entity, &["uid", "name", "damage", "healing", "taken"]);
+            // Per-target stats only know monster names, so players show up as `#<uid>`.
+            let target_keys = ["targetUid", "targetName", "totalValue"];
+            projected["dmgPerTarget"] = pick_each(&entity["dmgPerTarget"], &target_keys);
+            projected["healPerTarget"] = pick_each(&entity["healPerTarget"], &target_keys);
+            projected
+        })
+        .collect();
+
+    let mut projected = pick(&report, &["isManuallyReset", "isIncomplete", "playerNames"]);
+    projected["totalDmg"] = report["header"]["totalDmg"].clone();
+    projected["entities"] = Value::Array(entities);
+    projected["segments"] = pick_each(
+        &report["segments"],
+        &[
+            "id",
+            "segmentType",
+            "bossName",
+            "bossMonsterTypeId",
+            "totalDamage",
+            "hitCount",
+        ],
+    );
+    projected
+}
+
+fn assert_golden(name: &str, reports: &[EncounterReport]) {
+    let actual = Value::Array(reports.iter().map(project).collect());
+    let pretty = serde_json::to_string_pretty(&actual).expect("serialize projection") + "\n";
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.json"));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, pretty).expect("write golden file");
+        return;
+    }
+    let expected: Value = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| panic!("failed to load {}: {e}", path.display()));
+    assert!(
+        actual == expected,
+        "{} does not match the pipeline output (rerun with UPDATE_GOLDEN=1 if the change is intended):\n{pretty}",
+        path.display()
+    );
+}
+
+#[test]
+fn trash_then_boss_fight() {
+    let local = char_uuid(LOCAL_PLAYER_UID);
+    let ally = char_uuid(ALLY_UID);
+    let trash = monster_uuid(TRASH_UID);
+    let boss = monster_uuid(BOSS_UID);
+
+    let mut capture = Capture::default();
+    let mut flow = capture.connect(Ipv4Addr::new(10, 0, 0, 1));
+    capture.send_fixture(&mut flow);
+    capture.send_deltas(
+        &mut flow,
+        &[
+            SyncNearDeltaInfo {
+                delta_infos: vec![
+                    attrs(ally, vec![player_name("Ally")]),
+                    attrs(trash, vec![monster_id(TRASH_MONSTER_ID)]),
+                    attrs(boss, vec![monster_id(BOSS_MONSTER_ID)]),
+                ],
+            },
+            encoder::near_delta_damage(
+                trash,
+                vec![hit(local, 1_000), hit(local, 1_500), hit(ally, 700)],
+            ),
+        ],
+    );
+    capture.send_deltas(
+        &mut flow,
+        &[
+            encoder::near_delta_damage(
+                boss,
+                vec![crit(local, 5_000), lucky(local, 2_000), hit(ally, 3_000)],
+            ),
+            encoder::near_delta_damage(local, vec![heal(ally, 800), monster_hit(boss, 1_200)]),
+        ],
+    );
+
+    assert_golden(
+        "trash_then_boss_fight",
+        &capture.replay("trash_then_boss_fight"),
+    );
+}
+
+#[test]
+fn server_change_splits_encounters() {
+    let local = char_uuid(LOCAL_PLAYER_UID);
+    let unnamed = monster_uuid(UNNAMED_MONSTER_UID);
+    let trash = monster_uuid(TRASH_UID);
+
+    let mut capture = Capture::default();
+    let mut first = capture.connect(Ipv4Addr::new(10, 0, 0, 1));
+    capture.send_fixture(&mut first);
+    capture.send_deltas(
+        &mut first,
+        &[encoder::near_delta_damage(unnamed, vec![hit(local, 4_000)])],
+    );
+
+    let mut second = capture.connect(Ipv4Addr::new(10, 0, 0, 2));
+    capture.send_deltas(
+        &mut second,
+        &[
+            SyncNearDeltaInfo {
+                delta_infos: vec![attrs(trash, vec![monster_id(TRASH_MONSTER_ID)])],
+            },
+            encoder::near_delta_damage(trash, vec![hit(local, 2_500)]),
+            encoder::near_delta_damage(local, vec![monster_hit(trash, 600)]),
+        ],
+    );
+
+    assert_golden(
+        "server_change_splits_encounters",
+        &capture.replay("server_change_splits_encounters"),
+    );
+}
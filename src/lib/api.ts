@@ -19,6 +19,9 @@ export type BossHealth = {
   name: string;
   currentHp: number | null;
   maxHp: number | null;
+  timeToKillMs: number | null;
+  enrageMs: number | null;
+  onTrack: boolean | null;
 };
 
 export type HeaderInfo = {
@@ -133,6 +136,7 @@ export type LiveDataPayload = {
   entities: RawEntityData[];
   currentSegmentType: "boss" | "trash" | null;
   currentSegmentName: string | null;
+  isIncomplete: boolean;
 };
 
 export type BossDeathPayload = {
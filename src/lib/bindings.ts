@@ -196,6 +196,48 @@ async setBuffPriority(priorityBuffIds: number[]) : Promise<Result<null, string>>
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Starts recording every received message into a new session file.
+ * 
+ * Sessions are written to `<app data>/sessions` and can be replayed with the
+ * `SessionFile` capture method.
+ * 
+ * # Arguments
+ * 
+ * * `app_handle` - A handle to the Tauri application instance.
+ * * `state_manager` - The state manager.
+ * 
+ * # Returns
+ * 
+ * * `Result<String, String>` - The path of the session file being written.
+ */
+async startSessionRecording() : Promise<Result<string, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("start_session_recording") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Stops the active session recording, if any, and finalizes its file.
+ * 
+ * # Arguments
+ * 
+ * * `state_manager` - The state manager.
+ * 
+ * # Returns
+ * 
+ * * `Result<(), String>` - An empty result.
+ */
+async stopSessionRecording() : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("stop_session_recording") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 /**
  * Gets a list of recent encounters.
  * 
@@ -318,6 +360,83 @@ async getEncounterEntitiesRaw(encounterId: number) : Promise<Result<HistoryEntit
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Gets the per-second damage, healing and damage taken of each entity in an encounter.
+ * 
+ * # Arguments
+ * 
+ * * `encounter_id` - The ID of the encounter.
+ * 
+ * # Returns
+ * 
+ * * `Result<Vec<lc::EntityTimeline>, String>` - One timeline per entity that dealt, healed or took damage.
+ */
+async getEncounterTimeline(encounterId: number) : Promise<Result<EntityTimeline[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_encounter_timeline", { encounterId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Gets the death recaps of an encounter: who died, to what, and the hits and heals before it.
+ * 
+ * # Arguments
+ * 
+ * * `encounter_id` - The ID of the encounter.
+ * 
+ * # Returns
+ * 
+ * * `Result<Vec<lc::DeathRecap>, String>` - The player deaths, in the order they happened.
+ */
+async getEncounterDeaths(encounterId: number) : Promise<Result<DeathRecap[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_encounter_deaths", { encounterId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Gets the buff uptime of each entity over the fight of an encounter.
+ * 
+ * # Arguments
+ * 
+ * * `encounter_id` - The ID of the encounter.
+ * 
+ * # Returns
+ * 
+ * * `Result<Vec<lc::EntityBuffUptime>, String>` - Uptime percentage and average stacks per buff.
+ */
+async getEncounterBuffUptime(encounterId: number) : Promise<Result<EntityBuffUptime[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_encounter_buff_uptime", { encounterId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Gets the HP timeline of each boss in an encounter, split into phases at HP thresholds.
+ * 
+ * # Arguments
+ * 
+ * * `encounter_id` - The ID of the encounter.
+ * * `phase_thresholds` - HP thresholds per monster type id; bosses without any form a single phase.
+ * 
+ * # Returns
+ * 
+ * * `Result<Vec<lc::BossHpTimeline>, String>` - HP samples and per-player damage per phase for each boss.
+ */
+async getEncounterPhases(encounterId: number, phaseThresholds: PhaseThresholds[]) : Promise<Result<BossHpTimeline[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_encounter_phases", { encounterId, phaseThresholds }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 /**
  * Deletes an encounter by its ID.
  * 
@@ -414,9 +533,15 @@ async getPlayerNameCommand(uid: number) : Promise<Result<string | null, string>>
     else return { status: "error", error: e  as any };
 }
 },
-async savePacketCaptureSettings(method: string, npcapDevice: string) : Promise<Result<null, string>> {
+/**
+ * Persists the packet capture method and its options to `stores/packetCapture.json`.
+ * 
+ * Keys not covered by the arguments (e.g. replay file paths) are preserved.
+ * The `af_packet_*` options are only used by the Linux `AfPacket` method.
+ */
+async savePacketCaptureSettings(method: string, npcapDevice: string, afPacketDevice: string | null, afPacketFilter: boolean | null) : Promise<Result<null, string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("save_packet_capture_settings", { method, npcapDevice }) };
+    return { status: "ok", data: await TAURI_INVOKE("save_packet_capture_settings", { method, npcapDevice, afPacketDevice, afPacketFilter }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -433,6 +558,125 @@ async getNetworkDevices() : Promise<Result<Device[], string>> {
 async checkNpcapStatus() : Promise<boolean> {
     return await TAURI_INVOKE("check_npcap_status");
 },
+/**
+ * Lists the names of the network interfaces available for AF_PACKET capture.
+ */
+async getAfPacketInterfaces() : Promise<string[]> {
+    return await TAURI_INVOKE("get_af_packet_interfaces");
+},
+/**
+ * Returns the game connections the capture thread is currently decoding.
+ */
+async getActiveFlows() : Promise<ActiveFlow[]> {
+    return await TAURI_INVOKE("get_active_flows");
+},
+/**
+ * Returns the capture pipeline counters.
+ */
+async getCaptureStats() : Promise<CaptureStats> {
+    return await TAURI_INVOKE("get_capture_stats");
+},
+/**
+ * Enables or disables discovery of unknown method ids.
+ * 
+ * Samples are written to `<app log dir>/unknown-methods`, keeping a few
+ * rotating files per method id.
+ * 
+ * # Arguments
+ * 
+ * * `enabled` - Whether unknown method ids should be recorded.
+ * * `dump_payloads` - Whether sample payloads should be written to disk.
+ * * `app_handle` - A handle to the Tauri application instance.
+ * 
+ * # Returns
+ * 
+ * * `Result<Option<String>, String>` - The dump directory, when payloads are dumped.
+ */
+async setMethodDiscovery(enabled: boolean, dumpPayloads: boolean) : Promise<Result<string | null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_method_discovery", { enabled, dumpPayloads }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Returns the unknown method ids seen since discovery was enabled, most frequent first.
+ */
+async getUnknownMethods() : Promise<UnknownMethod[]> {
+    return await TAURI_INVOKE("get_unknown_methods");
+},
+/**
+ * Clears the unknown method histogram.
+ */
+async clearUnknownMethods() : Promise<void> {
+    await TAURI_INVOKE("clear_unknown_methods");
+},
+/**
+ * Returns the detection rules in use and which rule matched last.
+ */
+async getServerDetectionStatus() : Promise<ServerDetectionStatus> {
+    return await TAURI_INVOKE("get_server_detection_status");
+},
+/**
+ * Reloads the detection rules, preferring `ServerDetection.json` in the app
+ * data dir over the bundled rules.
+ * 
+ * # Arguments
+ * 
+ * * `app_handle` - A handle to the Tauri application instance.
+ * 
+ * # Returns
+ * 
+ * * `Result<ServerDetectionStatus, String>` - The rules now in use.
+ */
+async reloadServerDetectionRules() : Promise<Result<ServerDetectionStatus, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("reload_server_detection_rules") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Pins a game server so its connections are decoded without matching a rule.
+ * 
+ * # Arguments
+ * 
+ * * `endpoint` - The server `ip:port` to pin, or `None` to remove the pin.
+ * 
+ * # Returns
+ * 
+ * * `Result<ServerDetectionStatus, String>` - The detection status after the change.
+ */
+async pinGameServer(endpoint: string | null) : Promise<Result<ServerDetectionStatus, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("pin_game_server", { endpoint }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Decodes a raw protobuf payload without a schema.
+ * 
+ * # Arguments
+ * 
+ * * `payload_hex` - The payload, hex encoded (e.g. a dumped unknown method sample).
+ * * `pkt` - Optional opcode name; when set the payload is also diffed against its prost type.
+ * 
+ * # Returns
+ * 
+ * * `Result<ProtobufInspection, String>` - The decoded wire tree.
+ */
+async inspectProtobuf(payloadHex: string, pkt: string | null) : Promise<Result<ProtobufInspection, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("inspect_protobuf", { payloadHex, pkt }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async openLogDir() : Promise<Result<null, string>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("open_log_dir") };
@@ -494,6 +738,38 @@ async greedyOptimizeModules(modules: ModuleInfo[], targetAttributes: number[], e
 
 /** user-defined types **/
 
+/**
+ * A game connection currently being decoded.
+ */
+export type ActiveFlow = { 
+/**
+ * `server_ip:port -> client_ip:port`.
+ */
+flow: string; 
+/**
+ * When the flow was identified as a game connection, in ms since the Unix epoch.
+ */
+identifiedAtMs: number; 
+/**
+ * When the flow last carried payload, in ms since the Unix epoch.
+ */
+lastSeenMs: number; 
+/**
+ * TCP payload bytes received since the flow was identified.
+ */
+payloadBytes: number; 
+/**
+ * Name of the detection rule that identified the flow, or `pinned`.
+ */
+detectedBy: string }
+/**
+ * HP over time and phases of one boss in an encounter.
+ */
+export type BossHpTimeline = { uid: number; name: string; monsterTypeId: number | null; samples: HpSampleRecord[]; 
+/**
+ * Empty when the boss never lost HP.
+ */
+phases: BossPhase[] }
 /**
  * The result of a query for boss names.
  */
@@ -502,6 +778,22 @@ export type BossNamesResult = {
  * A list of boss names.
  */
 names: string[] }
+/**
+ * A stretch of a boss fight between two HP thresholds.
+ */
+export type BossPhase = { 
+/**
+ * 1-based phase number.
+ */
+index: number; startedAtMs: number; endedAtMs: number; 
+/**
+ * The threshold that started the phase; 100 for the first phase.
+ */
+startHpPct: number; 
+/**
+ * Damage each player dealt during the phase, highest first.
+ */
+players: PhasePlayerDamage[] }
 /**
  * A summary of a boss.
  */
@@ -520,11 +812,103 @@ maxHp: number | null;
 isDefeated: boolean }
 export type BuffDefinition = { baseId: number; name: string; spriteFile: string; searchKeywords: string[] }
 export type BuffNameInfo = { baseId: number; name: string; hasSpriteFile: boolean }
+/**
+ * Uptime of one buff base id on an entity.
+ */
+export type BuffUptimeStats = { baseId: number; name: string | null; uptimeMs: number; 
+/**
+ * Share of the encounter the buff was up, 0-100.
+ */
+uptimePct: number; 
+/**
+ * Average stacks while the buff was up.
+ */
+avgStacks: number }
+/**
+ * Snapshot of the capture pipeline counters since the app started.
+ */
+export type CaptureStats = { 
+/**
+ * Packets read from the capture source.
+ */
+packetsSeen: number; 
+/**
+ * Bytes read from the capture source.
+ */
+bytesSeen: number; 
+/**
+ * Packets that were not TCP or did not belong to an identified game flow.
+ */
+nonGamePackets: number; 
+/**
+ * Times a flow jumped backwards far enough to reset its reassembly state.
+ */
+sequenceRegressions: number; 
+/**
+ * Times the TCP reassembler gave up waiting for missing data and skipped ahead.
+ */
+reassemblyGaps: number; 
+/**
+ * Stream bytes skipped over by gap skips and frame resynchronisation.
+ */
+bytesLost: number; 
+/**
+ * Frames or notify payloads that failed zstd decompression.
+ */
+zstdFailures: number; 
+/**
+ * Frames dropped because of an invalid length header.
+ */
+malformedFrames: number; 
+/**
+ * Notify messages whose method id is not a known [`Pkt`].
+ */
+unknownMethods: number; 
+/**
+ * Messages dropped by the capture queue's overflow policy.
+ */
+channelDropped: number; 
+/**
+ * Messages replaced by a newer snapshot while the capture queue was full.
+ */
+channelCoalesced: number; 
+/**
+ * Per-opcode message counts, ordered by opcode name.
+ */
+messages: MessageStats[] }
 export type CombatState = "idle" | "inCombat"
 /**
  * Discrete damage occurrence stored on a segment.
  */
 export type DamageEvent = { timestampMs: number; attackerId: number; targetId: number; targetName: string | null; targetMonsterTypeId: number | null; amount: number; isBossTarget: boolean; isKillingBlow: boolean }
+/**
+ * A player death with the hits and heals received before it.
+ */
+export type DeathRecap = { uid: number; name: string; classId: number; timestampMs: number; killerUid: number | null; killerName: string | null; 
+/**
+ * The skill that dealt the killing blow.
+ */
+skillId: number | null; 
+/**
+ * Hits and heals received before the death, oldest first, including the killing blow.
+ */
+events: RecapEventRecord[] }
+/**
+ * The most recent connection identified as a game server.
+ */
+export type DetectionMatch = { 
+/**
+ * `server_ip:port -> client_ip:port`.
+ */
+flow: string; 
+/**
+ * Name of the rule that matched, or `pinned`.
+ */
+rule: string; 
+/**
+ * When the match happened, in ms since the Unix epoch.
+ */
+matchedAtMs: number }
 export type Device = { name: string; description: string | null }
 /**
  * Master container for dungeon segments within a scene.
@@ -613,13 +997,92 @@ remoteEncounterId: number | null;
 /**
  * Whether the encounter is favorited.
  */
-isFavorite: boolean }
+isFavorite: boolean; 
+/**
+ * Whether capture data was lost during the encounter, so its numbers are not accurate.
+ */
+isIncomplete: boolean }
+/**
+ * Buff uptime of one entity over an encounter.
+ */
+export type EntityBuffUptime = { uid: number; name: string; classId: number; isPlayer: boolean; 
+/**
+ * Buffs sorted by uptime, highest first.
+ */
+buffs: BuffUptimeStats[] }
+/**
+ * Damage, healing and damage taken of one entity over an encounter, in fixed-width buckets.
+ */
+export type EntityTimeline = { uid: number; name: string; classId: number; isPlayer: boolean; 
+/**
+ * Bucket width in ms; bucket `i` starts `i * bucket_ms` after the fight started.
+ */
+bucketMs: number; damage: number[]; healing: number[]; taken: number[] }
 export type GpuSupport = { cuda_available: boolean; opencl_available: boolean }
-export type HistoryEntityData = { uid: number; name: string; classId: number; classSpec: number; className: string; classSpecName: string; abilityScore: number; damage: RawCombatStats; damageBossOnly: RawCombatStats; healing: RawCombatStats; taken: RawCombatStats; activeDmgTimeMs: number; dmgSkills: Partial<{ [key in number]: RawSkillStats }>; healSkills: Partial<{ [key in number]: RawSkillStats }>; takenSkills: Partial<{ [key in number]: RawSkillStats }>; dmgPerTarget: PerTargetStats[]; healPerTarget: PerTargetStats[] }
+export type HistoryEntityData = { uid: number; name: string; classId: number; classSpec: number; className: string; classSpecName: string; abilityScore: number; damage: RawCombatStats; damageBossOnly: RawCombatStats; healing: RawCombatStats; taken: RawCombatStats; activeDmgTimeMs: number; dmgSkills: Partial<{ [key in number]: RawSkillStats }>; healSkills: Partial<{ [key in number]: RawSkillStats }>; takenSkills: Partial<{ [key in number]: RawSkillStats }>; dmgPerTarget: PerTargetStats[]; healPerTarget: PerTargetStats[]; 
+/**
+ * Skill casts in the order they were seen; only recorded for the local player.
+ */
+casts: SkillCastRecord[] }
+export type HpSampleRecord = { timestampMs: number; hp: number; maxHp: number }
+/**
+ * Message counts for a single opcode.
+ */
+export type MessageStats = { pkt: string; 
+/**
+ * Messages parsed from the capture stream.
+ */
+received: number; 
+/**
+ * Messages whose protobuf payload failed to decode.
+ */
+decodeFailures: number; 
+/**
+ * Messages dropped or coalesced away by the capture queue.
+ */
+shed: number; 
+/**
+ * Messages run through their live state handler.
+ */
+handled: number; 
+/**
+ * Time spent decoding and applying the handled messages, in microseconds.
+ */
+handlerTimeUs: number; 
+/**
+ * Slowest single handler run, in microseconds.
+ */
+handlerMaxTimeUs: number }
+/**
+ * A field that is on the wire but not in the prost schema.
+ */
+export type MissingField = { 
+/**
+ * Opcode name followed by the field numbers leading to the field, e.g.
+ * `SyncNearDeltaInfo.1.7.2.18`.
+ */
+path: string; wireType: string; 
+/**
+ * Times the field was seen.
+ */
+count: number; 
+/**
+ * The first value seen.
+ */
+example: string }
 export type ModuleInfo = { name: string; config_id: number; uuid: number; quality: number; parts: ModulePart[] }
 export type ModulePart = { id: number; name: string; value: number }
 export type ModuleSolution = { modules: ModuleInfo[]; score: number; attr_breakdown: Partial<{ [key in string]: number }> }
 export type PerTargetStats = { targetUid: number; targetName: string; totalValue: number; damage: RawCombatStats; skills: Partial<{ [key in number]: RawSkillStats }> }
+export type PhasePlayerDamage = { uid: number; name: string; classId: number; damage: number }
+/**
+ * HP percentages at which fights against one boss type move to the next phase.
+ */
+export type PhaseThresholds = { monsterTypeId: number; 
+/**
+ * Boss HP in percent (0-100); a new phase starts when HP drops to each of them.
+ */
+hpPct: number[] }
 /**
  * The result of a query for player names.
  */
@@ -628,8 +1091,25 @@ export type PlayerNamesResult = {
  * A list of player names.
  */
 names: string[] }
+/**
+ * The wire tree of a payload and, when its opcode is given, the fields the
+ * prost schema does not know.
+ */
+export type ProtobufInspection = { fields: WireField[]; missingFields: MissingField[] }
 export type RawCombatStats = { total: number; hits: number; critHits: number; critTotal: number; luckyHits: number; luckyTotal: number }
 export type RawSkillStats = { totalValue: number; hits: number; critHits: number; critTotalValue: number; luckyHits: number; luckyTotalValue: number }
+/**
+ * A hit or heal in a death recap.
+ */
+export type RecapEventRecord = { timestampMs: number; sourceUid: number; sourceName: string | null; skillId: number; 
+/**
+ * Damage (HP and shield lost) or healing received.
+ */
+value: number; isHeal: boolean; 
+/**
+ * Current HP before the event, when known.
+ */
+hpBefore: number | null }
 /**
  * The result of a query for recent encounters.
  */
@@ -659,6 +1139,58 @@ export type Segment = { id: number; segmentType: SegmentType;
  */
 bossEntityId: number | null; bossMonsterTypeId: number | null; bossName: string | null; startedAtMs: number; endedAtMs: number | null; totalDamage: number; hitCount: number; events: DamageEvent[] }
 export type SegmentType = "boss" | "trash"
+/**
+ * A single skill cast in an entity's cast log.
+ */
+/**
+ * Current detection rules, pin and last match.
+ */
+export type ServerDetectionStatus = { 
+/**
+ * `bundled`, or the path of the override file the rules were loaded from.
+ */
+rulesSource: string; 
+/**
+ * Rule names in the order they are tried.
+ */
+rules: string[]; allowPorts: number[]; allowIps: string[]; 
+/**
+ * Server endpoint (`ip:port`) accepted without matching any rule.
+ */
+pinnedServer: string | null; lastMatch: DetectionMatch | null }
+export type SkillCastRecord = { timestampMs: number; skillId: number; skillLevelId: number; targetUid: number | null }
+/**
+ * Histogram entry for one unknown method id.
+ */
+export type UnknownMethod = { methodId: number; 
+/**
+ * Messages seen with this method id.
+ */
+count: number; 
+/**
+ * Sum of the payload sizes, after zstd decompression.
+ */
+totalBytes: number; minBytes: number; maxBytes: number; 
+/**
+ * Messages that arrived zstd-compressed.
+ */
+compressed: number; 
+/**
+ * When the method id was first seen, in ms since the Unix epoch.
+ */
+firstSeenMs: number; 
+/**
+ * When the method id was last seen, in ms since the Unix epoch.
+ */
+lastSeenMs: number }
+/**
+ * One field as it appears on the wire.
+ */
+export type WireField = { number: number; value: WireValue }
+/**
+ * A field value, typed only as far as the wire format allows.
+ */
+export type WireValue = { kind: "varint"; value: number; zigzag: number } | { kind: "fixed64"; value: number; double: number } | { kind: "fixed32"; value: number; float: number } | { kind: "message"; fields: WireField[] } | { kind: "string"; value: string } | { kind: "packedVarints"; values: number[] } | { kind: "bytes"; hex: string }
 
 /** tauri-specta globals **/
 